@@ -0,0 +1,285 @@
+//! Composable analyzer pipeline
+//!
+//! An `Analyzer` splits text on Unicode word boundaries and passes the
+//! resulting tokens through an ordered list of `TokenFilter`s. Built-in
+//! filters cover lowercasing, ASCII folding, stopword removal, stemming and
+//! minimum length; custom filters implement the trait.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+use rust_stemmers::{Algorithm, Stemmer};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::language::Language;
+
+/// A token and the byte range of the source text it came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub text: String,
+    pub span: Range<usize>,
+}
+
+/// One step of an analyzer pipeline
+pub trait TokenFilter: Send + Sync {
+    /// Short name shown in debug output
+    fn name(&self) -> &str;
+
+    /// Transform the token stream; filters may rewrite, drop or add tokens
+    fn apply(&self, tokens: Vec<Token>) -> Vec<Token>;
+
+    /// Whether this filter removes stopwords, so it can be skipped on fallback
+    fn is_stopword_filter(&self) -> bool {
+        false
+    }
+}
+
+/// Ordered token filters applied after word splitting
+#[derive(Clone, Default)]
+pub struct Analyzer {
+    filters: Vec<Arc<dyn TokenFilter>>,
+}
+
+impl Analyzer {
+    /// Create an analyzer with no filters (word splitting only)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Analyzer matching the default `Tokenizer`: lowercase, then min length 2
+    pub fn standard() -> Self {
+        Self::new().with_filter(LowercaseFilter).with_filter(MinLengthFilter(2))
+    }
+
+    /// Append a filter to the end of the pipeline
+    pub fn with_filter(mut self, filter: impl TokenFilter + 'static) -> Self {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+
+    /// Split text into words and run them through every filter in order
+    pub fn analyze(&self, text: &str) -> Vec<Token> {
+        self.analyze_filtered(text, true)
+    }
+
+    /// Like `analyze`, but skipping every stopword filter
+    pub fn analyze_keeping_stopwords(&self, text: &str) -> Vec<Token> {
+        self.analyze_filtered(text, false)
+    }
+
+    fn analyze_filtered(&self, text: &str, stopwords: bool) -> Vec<Token> {
+        let tokens = text
+            .unicode_word_indices()
+            .map(|(start, word)| Token {
+                text: word.to_string(),
+                span: start..start + word.len(),
+            })
+            .collect();
+        self.filters
+            .iter()
+            .filter(|filter| stopwords || !filter.is_stopword_filter())
+            .fold(tokens, |tokens, filter| filter.apply(tokens))
+    }
+}
+
+impl fmt::Debug for Analyzer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.filters.iter().map(|filter| filter.name())).finish()
+    }
+}
+
+/// Lowercases tokens using Unicode default casing
+pub struct LowercaseFilter;
+
+impl TokenFilter for LowercaseFilter {
+    fn name(&self) -> &str {
+        "lowercase"
+    }
+
+    fn apply(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        for token in &mut tokens {
+            token.text = token.text.to_lowercase();
+        }
+        tokens
+    }
+}
+
+/// Folds accented and ligature characters to ASCII where possible ("café" -> "cafe")
+pub struct AsciiFoldingFilter;
+
+impl TokenFilter for AsciiFoldingFilter {
+    fn name(&self) -> &str {
+        "asciifold"
+    }
+
+    fn apply(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        for token in &mut tokens {
+            if token.text.is_ascii() {
+                continue;
+            }
+            let mut folded = String::with_capacity(token.text.len());
+            for c in token.text.nfd().filter(|&c| !is_combining_mark(c)) {
+                match c {
+                    'ß' => folded.push_str("ss"),
+                    'æ' => folded.push_str("ae"),
+                    'Æ' => folded.push_str("AE"),
+                    'œ' => folded.push_str("oe"),
+                    'Œ' => folded.push_str("OE"),
+                    'ø' => folded.push('o'),
+                    'Ø' => folded.push('O'),
+                    'ł' => folded.push('l'),
+                    'Ł' => folded.push('L'),
+                    'đ' => folded.push('d'),
+                    'Đ' => folded.push('D'),
+                    _ => folded.push(c),
+                }
+            }
+            token.text = folded;
+        }
+        tokens
+    }
+}
+
+/// Drops tokens found in a stopword set (compared as-is, so lowercase first)
+pub struct StopwordFilter {
+    words: HashSet<String>,
+}
+
+impl StopwordFilter {
+    /// Create a filter from a custom stopword list
+    pub fn new<S: Into<String>>(words: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            words: words.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Common English stopwords
+    pub fn english() -> Self {
+        Self::for_language(Language::English)
+    }
+
+    /// Common stopwords of a supported language
+    pub fn for_language(language: Language) -> Self {
+        Self::new(language.stopwords().iter().copied())
+    }
+}
+
+impl TokenFilter for StopwordFilter {
+    fn name(&self) -> &str {
+        "stopword"
+    }
+
+    fn apply(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        tokens.retain(|token| !self.words.contains(&token.text));
+        tokens
+    }
+
+    fn is_stopword_filter(&self) -> bool {
+        true
+    }
+}
+
+/// Reduces tokens to their stem with a Snowball stemmer
+pub struct StemFilter {
+    stemmer: Stemmer,
+}
+
+impl StemFilter {
+    /// Create a stemmer for the given Snowball algorithm
+    pub fn new(algorithm: Algorithm) -> Self {
+        Self {
+            stemmer: Stemmer::create(algorithm),
+        }
+    }
+
+    /// English (Porter2) stemmer
+    pub fn english() -> Self {
+        Self::new(Algorithm::English)
+    }
+}
+
+impl TokenFilter for StemFilter {
+    fn name(&self) -> &str {
+        "stem"
+    }
+
+    fn apply(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        for token in &mut tokens {
+            token.text = self.stemmer.stem(&token.text).into_owned();
+        }
+        tokens
+    }
+}
+
+/// Drops tokens shorter than the given length in bytes
+pub struct MinLengthFilter(pub usize);
+
+impl TokenFilter for MinLengthFilter {
+    fn name(&self) -> &str {
+        "min_length"
+    }
+
+    fn apply(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+        tokens.retain(|token| token.text.len() >= self.0);
+        tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(tokens: Vec<Token>) -> Vec<String> {
+        tokens.into_iter().map(|token| token.text).collect()
+    }
+
+    #[test]
+    fn test_lowercase_stopword_stem() {
+        let analyzer = Analyzer::new()
+            .with_filter(LowercaseFilter)
+            .with_filter(StopwordFilter::english())
+            .with_filter(StemFilter::english());
+        let tokens = analyzer.analyze("The Running Dogs are Jumping over fences");
+        assert_eq!(texts(tokens), vec!["run", "dog", "jump", "over", "fenc"]);
+    }
+
+    #[test]
+    fn test_order_matters() {
+        // Stopwords are matched as-is, so "The" survives without lowercasing first
+        let analyzer = Analyzer::new()
+            .with_filter(StopwordFilter::english())
+            .with_filter(LowercaseFilter);
+        assert_eq!(texts(analyzer.analyze("The cat")), vec!["the", "cat"]);
+    }
+
+    #[test]
+    fn test_asciifold_and_spans() {
+        let text = "Crème brûlée, Straße";
+        let analyzer = Analyzer::standard().with_filter(AsciiFoldingFilter);
+        let tokens = analyzer.analyze(text);
+        assert_eq!(texts(tokens.clone()), vec!["creme", "brulee", "strasse"]);
+        assert_eq!(&text[tokens[1].span.clone()], "brûlée");
+        assert_eq!(format!("{:?}", analyzer), r#"["lowercase", "min_length", "asciifold"]"#);
+    }
+
+    #[test]
+    fn test_custom_filter() {
+        struct Reverse;
+        impl TokenFilter for Reverse {
+            fn name(&self) -> &str {
+                "reverse"
+            }
+            fn apply(&self, mut tokens: Vec<Token>) -> Vec<Token> {
+                tokens.reverse();
+                tokens
+            }
+        }
+
+        let analyzer = Analyzer::standard().with_filter(Reverse);
+        assert_eq!(texts(analyzer.analyze("one two three")), vec!["three", "two", "one"]);
+    }
+}
@@ -0,0 +1,327 @@
+//! Block-based postings with skip information
+//!
+//! A postings list is split into fixed-size blocks. Each block stores its
+//! last (maximum) doc_id and its byte length ahead of the VByte deltas, so a
+//! reader can skip whole blocks without decoding them.
+//!
+//! Layout (all integers VByte-encoded):
+//!
+//! ```text
+//! [doc_count] [block_size] { [last_doc_id] [payload_len] [deltas...] }*
+//! ```
+//!
+//! The first delta of a block is relative to the previous block's last doc_id.
+
+use std::io::Cursor;
+
+use crate::varint::{decode_varint, encode_varint};
+
+/// Default number of doc_ids per block
+pub const DEFAULT_BLOCK_SIZE: usize = 128;
+
+/// Encode sorted doc_ids into block postings
+pub fn encode_block_postings(doc_ids: &[u32], block_size: usize) -> Vec<u8> {
+    let block_size = block_size.max(1);
+    let mut buf = Vec::new();
+    encode_varint(doc_ids.len() as u32, &mut buf).unwrap();
+    encode_varint(block_size as u32, &mut buf).unwrap();
+
+    let mut prev = 0u32;
+    let mut payload = Vec::new();
+    for block in doc_ids.chunks(block_size) {
+        payload.clear();
+        for &doc_id in block {
+            encode_varint(doc_id - prev, &mut payload).unwrap();
+            prev = doc_id;
+        }
+        encode_varint(prev, &mut buf).unwrap();
+        encode_varint(payload.len() as u32, &mut buf).unwrap();
+        buf.extend_from_slice(&payload);
+    }
+    buf
+}
+
+/// Decode every doc_id from block postings
+pub fn decode_block_postings(data: &[u8]) -> Vec<u32> {
+    BlockPostingsReader::new(data).collect()
+}
+
+/// Number of doc_ids in block postings, read from the header
+pub fn block_postings_len(data: &[u8]) -> usize {
+    decode_varint(&mut Cursor::new(data)).unwrap_or(0) as usize
+}
+
+/// Forward-only reader over block postings supporting skips
+pub struct BlockPostingsReader<'a> {
+    data: &'a [u8],
+    /// Offset of the next undecoded block header
+    pos: usize,
+    /// Last doc_id of the most recently consumed block
+    base: u32,
+    /// Decoded doc_ids of the current block
+    block: Vec<u32>,
+    /// Index of the next doc_id to return from `block`
+    idx: usize,
+    len: usize,
+}
+
+impl<'a> BlockPostingsReader<'a> {
+    /// Create a reader over encoded block postings
+    pub fn new(data: &'a [u8]) -> Self {
+        let mut cursor = Cursor::new(data);
+        let len = decode_varint(&mut cursor).unwrap_or(0) as usize;
+        let _block_size = decode_varint(&mut cursor).unwrap_or(0);
+        Self {
+            data,
+            pos: cursor.position() as usize,
+            base: 0,
+            block: Vec::new(),
+            idx: 0,
+            len,
+        }
+    }
+
+    /// Total number of doc_ids in the list
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the list is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Move to the first remaining doc_id that is `>= target` and return it
+    ///
+    /// Blocks whose last doc_id is below `target` are skipped without being
+    /// decoded. Doc_ids before the returned one are consumed; the returned
+    /// doc_id itself is not, so the next `next()` yields it again.
+    pub fn advance(&mut self, target: u32) -> Option<u32> {
+        // Try the current block first
+        if self.idx < self.block.len() && self.block.last().is_some_and(|&last| last >= target) {
+            return self.take_from_block(target);
+        }
+
+        // Skip blocks that end before the target
+        loop {
+            let (last, payload_start, payload_len) = self.read_header()?;
+            if last >= target {
+                self.decode_block(payload_start, payload_len);
+                self.pos = payload_start + payload_len;
+                self.base = last;
+                return self.take_from_block(target);
+            }
+            self.pos = payload_start + payload_len;
+            self.base = last;
+            self.block.clear();
+            self.idx = 0;
+        }
+    }
+
+    /// Consume doc_ids in the current block before the first `>= target`
+    fn take_from_block(&mut self, target: u32) -> Option<u32> {
+        let offset = self.block[self.idx..].partition_point(|&doc_id| doc_id < target);
+        self.idx += offset;
+        self.block.get(self.idx).copied()
+    }
+
+    /// Read the next block header: (last doc_id, payload offset, payload length)
+    fn read_header(&self) -> Option<(u32, usize, usize)> {
+        if self.pos >= self.data.len() {
+            return None;
+        }
+        let mut cursor = Cursor::new(&self.data[self.pos..]);
+        let last = decode_varint(&mut cursor).ok()?;
+        let payload_len = decode_varint(&mut cursor).ok()? as usize;
+        Some((last, self.pos + cursor.position() as usize, payload_len))
+    }
+
+    /// Decode a block's deltas into the block buffer
+    fn decode_block(&mut self, payload_start: usize, payload_len: usize) {
+        let payload = &self.data[payload_start..payload_start + payload_len];
+        let mut cursor = Cursor::new(payload);
+        let mut prev = self.base;
+        self.block.clear();
+        self.idx = 0;
+        while (cursor.position() as usize) < payload.len() {
+            match decode_varint(&mut cursor) {
+                Ok(delta) => {
+                    prev += delta;
+                    self.block.push(prev);
+                }
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl Iterator for BlockPostingsReader<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        if self.idx < self.block.len() {
+            self.idx += 1;
+            return Some(self.block[self.idx - 1]);
+        }
+        let (last, payload_start, payload_len) = self.read_header()?;
+        self.decode_block(payload_start, payload_len);
+        self.pos = payload_start + payload_len;
+        self.base = last;
+        self.next()
+    }
+}
+
+/// Intersect several block postings lists using skips
+pub fn intersect_block_postings(lists: &[&[u8]]) -> Vec<u32> {
+    let mut readers: Vec<BlockPostingsReader> = lists.iter().map(|data| BlockPostingsReader::new(data)).collect();
+    let mut result = Vec::new();
+    if readers.is_empty() {
+        return result;
+    }
+    // Drive from the shortest list
+    readers.sort_by_key(|reader| reader.len());
+    let (lead, rest) = readers.split_first_mut().unwrap();
+
+    let mut target = 0;
+    while let Some(candidate) = lead.advance(target) {
+        let mut next_target = None;
+        for reader in rest.iter_mut() {
+            match reader.advance(candidate) {
+                None => return result,
+                Some(doc_id) if doc_id != candidate => {
+                    next_target = Some(doc_id);
+                    break;
+                }
+                Some(_) => {}
+            }
+        }
+        target = match next_target {
+            Some(doc_id) => doc_id,
+            None => {
+                result.push(candidate);
+                match candidate.checked_add(1) {
+                    Some(doc_id) => doc_id,
+                    None => break,
+                }
+            }
+        };
+    }
+    result
+}
+
+/// Number of distinct doc_ids across several block postings lists
+///
+/// Merges the lists in doc_id order, so every list is decoded but no union
+/// set is allocated.
+pub fn union_block_postings_len(lists: &[&[u8]]) -> usize {
+    union_len(lists.iter().map(|data| BlockPostingsReader::new(data)).collect())
+}
+
+/// Number of distinct doc_ids across several sorted doc_id iterators
+pub fn union_len<I: Iterator<Item = u32>>(mut readers: Vec<I>) -> usize {
+    let mut heads: Vec<Option<u32>> = readers.iter_mut().map(|reader| reader.next()).collect();
+    let mut count = 0;
+    while let Some(min) = heads.iter().flatten().copied().min() {
+        count += 1;
+        for (reader, head) in readers.iter_mut().zip(heads.iter_mut()) {
+            if *head == Some(min) {
+                *head = reader.next();
+            }
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::varint::{decode_postings, encode_postings};
+
+    #[test]
+    fn test_block_roundtrip() {
+        let doc_ids: Vec<u32> = (0..1000).map(|i| i * 3 + 1).collect();
+        for block_size in [1, 7, 128, 2000] {
+            let data = encode_block_postings(&doc_ids, block_size);
+            assert_eq!(decode_block_postings(&data), doc_ids);
+            assert_eq!(block_postings_len(&data), doc_ids.len());
+        }
+        assert!(decode_block_postings(&encode_block_postings(&[], 4)).is_empty());
+    }
+
+    #[test]
+    fn test_advance() {
+        let doc_ids: Vec<u32> = (0..100).map(|i| i * 10).collect(); // 0, 10, ..., 990
+        let data = encode_block_postings(&doc_ids, 8);
+        let mut reader = BlockPostingsReader::new(&data);
+
+        assert_eq!(reader.advance(0), Some(0));
+        assert_eq!(reader.advance(5), Some(10));
+        assert_eq!(reader.advance(10), Some(10)); // advance doesn't consume
+        assert_eq!(reader.next(), Some(10));
+        assert_eq!(reader.advance(10), Some(20));
+        assert_eq!(reader.advance(455), Some(460)); // skips whole blocks
+        assert_eq!(reader.next(), Some(460));
+        assert_eq!(reader.next(), Some(470));
+        assert_eq!(reader.advance(990), Some(990));
+        assert_eq!(reader.advance(991), None);
+    }
+
+    #[test]
+    fn test_intersection() {
+        let a: Vec<u32> = (0..1000).filter(|i| i % 2 == 0).collect();
+        let b: Vec<u32> = (0..1000).filter(|i| i % 3 == 0).collect();
+        let c: Vec<u32> = (0..1000).filter(|i| i % 5 == 0).collect();
+        let expected: Vec<u32> = (0..1000).filter(|i| i % 30 == 0).collect();
+
+        let (a, b, c) = (
+            encode_block_postings(&a, 16),
+            encode_block_postings(&b, 16),
+            encode_block_postings(&c, 16),
+        );
+        assert_eq!(intersect_block_postings(&[&a, &b, &c]), expected);
+        assert!(intersect_block_postings(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_union_len() {
+        let a: Vec<u32> = (0..1000).filter(|i| i % 2 == 0).collect();
+        let b: Vec<u32> = (0..1000).filter(|i| i % 3 == 0).collect();
+        let expected = (0..1000).filter(|i| i % 2 == 0 || i % 3 == 0).count();
+
+        let (a, b) = (encode_block_postings(&a, 16), encode_block_postings(&b, 16));
+        assert_eq!(union_block_postings_len(&[&a, &b]), expected);
+        assert_eq!(union_block_postings_len(&[&a]), 500);
+        assert_eq!(union_block_postings_len(&[]), 0);
+    }
+
+    /// Run with `cargo test --release -p pocketwiki-core -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_intersection() {
+        use std::time::Instant;
+
+        let long: Vec<u32> = (0..2_000_000).collect();
+        let short: Vec<u32> = (0..2_000_000).step_by(10_000).collect();
+
+        let (long_flat, short_flat) = (encode_postings(&long), encode_postings(&short));
+        let start = Instant::now();
+        let long_ids = decode_postings(&long_flat).unwrap();
+        let flat: Vec<u32> = decode_postings(&short_flat)
+            .unwrap()
+            .into_iter()
+            .filter(|doc_id| long_ids.binary_search(doc_id).is_ok())
+            .collect();
+        let flat_time = start.elapsed();
+
+        let (long_block, short_block) = (
+            encode_block_postings(&long, DEFAULT_BLOCK_SIZE),
+            encode_block_postings(&short, DEFAULT_BLOCK_SIZE),
+        );
+        let start = Instant::now();
+        let blocked = intersect_block_postings(&[&long_block, &short_block]);
+        let block_time = start.elapsed();
+
+        assert_eq!(flat, blocked);
+        println!("flat decode + search: {:?}, block skip: {:?}", flat_time, block_time);
+    }
+}
@@ -2,21 +2,124 @@
 
 use ahash::{AHashMap, AHashSet};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
+use crate::error::{DocLimitReached, DuplicateDocument, HashCollision, IndexError, IntegrityError, Result, TextNotStored};
+use crate::language::Language;
+use crate::rng::SplitMix64;
 use crate::tokenizer::Tokenizer;
-use crate::varint::encode_postings;
+use crate::frozen::FrozenBM25Index;
+use crate::wal::{Wal, WalEntry};
+use crate::block::{
+    block_postings_len, decode_block_postings, encode_block_postings, union_len, BlockPostingsReader,
+    DEFAULT_BLOCK_SIZE,
+};
+
+/// IDF formula used by the scorer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum IdfVariant {
+    /// Probabilistic IDF: `ln((N - df + 0.5) / (df + 0.5) + 1)`, the one
+    /// Lucene's BM25 similarity uses
+    #[default]
+    Robertson,
+    /// Lucene's pre-BM25 `ClassicSimilarity` IDF: `1 + ln((N + 1) / (df + 1))`
+    #[serde(alias = "Lucene")]
+    LuceneClassic,
+    /// Textbook IDF: `ln(N / df)`
+    Classic,
+}
+
+/// How repeated query terms contribute to the score
+///
+/// Before this option existed every occurrence was scored again (`Linear`);
+/// the default is now `Binary`, which counts each distinct term once.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum QueryTfMode {
+    /// Weight each term by its query frequency
+    Linear,
+    /// Count each distinct term once
+    #[default]
+    Binary,
+    /// Classic BM25 query-side saturation: `(k3 + 1) * qtf / (k3 + qtf)`
+    Saturated { k3: f32 },
+}
+
+/// How a document's static prior is combined with its query score
+///
+/// Documents added without a prior use the neutral value for the mode (1.0
+/// when multiplying, 0.0 when adding), so their scores are unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PriorMode {
+    /// `score * prior`
+    #[default]
+    Multiplicative,
+    /// `score + prior`
+    Additive,
+}
+
+/// What adding a document does once the index holds `max_docs` documents
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CapacityPolicy {
+    /// Refuse the new document
+    #[default]
+    Reject,
+    /// Remove the earliest-added document to make room, like a ring buffer
+    EvictOldest,
+}
+
+/// What `BM25Index::add_document_mode` does with a doc_id already indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddMode {
+    /// Remove the old document and everything stored for it, then add the new text
+    Replace,
+    /// Add the text to the document, summing term frequencies and lengths
+    Accumulate,
+    /// Fail with `DuplicateDocument`
+    Reject,
+}
+
+impl PriorMode {
+    /// Combine a query score with a document prior, if it has one
+    pub fn apply(&self, score: f32, prior: Option<f32>) -> f32 {
+        match (self, prior) {
+            (_, None) => score,
+            (PriorMode::Multiplicative, Some(prior)) => score * prior,
+            (PriorMode::Additive, Some(prior)) => score + prior,
+        }
+    }
+}
 
 /// BM25 parameters
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BM25Params {
     pub k1: f32,
     pub b: f32,
+    pub idf: IdfVariant,
+    pub query_tf: QueryTfMode,
+    pub prior_mode: PriorMode,
+    /// Score multiplier for short documents containing every query term
+    ///
+    /// A document qualifies when it has all distinct query terms and at most
+    /// twice as many unique terms as the query. 1.0 disables the boost.
+    pub exact_match_boost: f32,
 }
 
 impl Default for BM25Params {
     fn default() -> Self {
-        Self { k1: 1.5, b: 0.75 }
+        Self {
+            k1: 1.5,
+            b: 0.75,
+            idf: IdfVariant::default(),
+            query_tf: QueryTfMode::default(),
+            prior_mode: PriorMode::default(),
+            exact_match_boost: 1.0,
+        }
     }
 }
 
@@ -29,7 +132,15 @@ pub struct BM25Scorer {
 
 impl BM25Scorer {
     /// Create a new BM25 scorer
+    ///
+    /// A non-positive or non-finite `avg_doc_len` (e.g. from an empty corpus)
+    /// falls back to 1.0 so length normalization never divides by zero.
     pub fn new(params: BM25Params, avg_doc_len: f32, doc_count: usize) -> Self {
+        let avg_doc_len = if avg_doc_len.is_finite() && avg_doc_len > 0.0 {
+            avg_doc_len
+        } else {
+            1.0
+        };
         Self {
             params,
             avg_doc_len,
@@ -44,33 +155,479 @@ impl BM25Scorer {
         doc_len: f32,
         doc_freq: usize,
     ) -> f32 {
-        let idf = self.idf(doc_freq);
+        self.score_term_with_idf(self.idf(doc_freq), term_freq, doc_len)
+    }
+
+    /// Calculate BM25 score for a single term with a precomputed IDF
+    pub fn score_term_with_idf(&self, idf: f32, term_freq: f32, doc_len: f32) -> f32 {
         let tf_component = (term_freq * (self.params.k1 + 1.0))
             / (term_freq + self.params.k1 * (1.0 - self.params.b + self.params.b * (doc_len / self.avg_doc_len)));
         idf * tf_component
     }
 
+    /// Weight of a query term given how often it occurs in the query
+    pub fn query_term_weight(&self, query_tf: f32) -> f32 {
+        match self.params.query_tf {
+            QueryTfMode::Linear => query_tf,
+            QueryTfMode::Binary => 1.0,
+            QueryTfMode::Saturated { k3 } => ((k3 + 1.0) * query_tf) / (k3 + query_tf),
+        }
+    }
+
     /// Calculate IDF (inverse document frequency)
-    fn idf(&self, doc_freq: usize) -> f32 {
-        let n = self.doc_count as f32;
+    pub(crate) fn idf(&self, doc_freq: usize) -> f32 {
+        // A term can't occur in more documents than the corpus holds
+        let n = self.doc_count.max(doc_freq) as f32;
         let df = doc_freq as f32;
-        ((n - df + 0.5) / (df + 0.5) + 1.0).ln()
+        match self.params.idf {
+            IdfVariant::Robertson => ((n - df + 0.5) / (df + 0.5) + 1.0).ln(),
+            IdfVariant::LuceneClassic => 1.0 + ((n + 1.0) / (df + 1.0)).ln(),
+            IdfVariant::Classic => {
+                if doc_freq == 0 {
+                    0.0
+                } else {
+                    (n / df).ln()
+                }
+            }
+        }
+    }
+}
+
+/// Corpus statistics available to a `Scorer`
+#[derive(Debug, Clone, Copy)]
+pub struct CorpusStats {
+    pub doc_count: usize,
+    pub avg_doc_len: f32,
+}
+
+/// Per-term relevance function plugged into `BM25Index::with_scorer`
+///
+/// A document's score is the sum of `score` over the query terms it
+/// contains, weighted by the query tf mode.
+pub trait Scorer: Send + Sync {
+    /// Score one term occurring `tf` times in a document of `doc_len` tokens
+    fn score(&self, tf: f32, doc_len: f32, doc_freq: usize, ctx: &CorpusStats) -> f32;
+}
+
+impl Scorer for BM25Scorer {
+    /// BM25 with this scorer's parameters and the corpus statistics in `ctx`
+    fn score(&self, tf: f32, doc_len: f32, doc_freq: usize, ctx: &CorpusStats) -> f32 {
+        BM25Scorer::new(self.params, ctx.avg_doc_len, ctx.doc_count).score_term(tf, doc_len, doc_freq)
+    }
+}
+
+/// How a clause of a structured query constrains matching documents
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Occur {
+    /// Documents must contain the clause's terms; they add to the score
+    Must,
+    /// Documents may contain the clause's terms; they add to the score
+    Should,
+    /// Documents containing any of the clause's terms are excluded
+    MustNot,
+}
+
+/// One clause of a query for `BM25Index::search_structured`
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryTerm {
+    /// Text tokenized like a query; every resulting term gets the clause's `occur`
+    pub text: String,
+    pub occur: Occur,
+    /// Multiplier on the clause's score contribution (ignored for `MustNot`)
+    pub boost: f32,
+}
+
+impl QueryTerm {
+    /// Create a clause with a boost of 1.0
+    pub fn new(text: impl Into<String>, occur: Occur) -> Self {
+        Self {
+            text: text.into(),
+            occur,
+            boost: 1.0,
+        }
+    }
+
+    /// Set the clause's boost
+    pub fn with_boost(mut self, boost: f32) -> Self {
+        self.boost = boost;
+        self
+    }
+}
+
+/// Set-overlap similarity used by `BM25Index::search_set_similarity`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetSimilarity {
+    /// `|Q ∩ D| / |Q ∪ D|`
+    Jaccard,
+    /// `2 |Q ∩ D| / (|Q| + |D|)`
+    Dice,
+    /// `|Q ∩ D| / min(|Q|, |D|)`
+    Overlap,
+}
+
+impl SetSimilarity {
+    /// Similarity from the intersection size and both set sizes
+    pub fn score(&self, intersection: usize, query_size: usize, doc_size: usize) -> f32 {
+        let (i, q, d) = (intersection as f32, query_size as f32, doc_size as f32);
+        let denominator = match self {
+            SetSimilarity::Jaccard => q + d - i,
+            SetSimilarity::Dice => (q + d) / 2.0,
+            SetSimilarity::Overlap => q.min(d),
+        };
+        if denominator > 0.0 {
+            i / denominator
+        } else {
+            0.0
+        }
     }
 }
 
+/// Position of a term in the sorted term dictionary
+pub type TermId = u32;
+
+/// Width of the counts stored in `DocMeta`
+#[cfg(feature = "compact-doc-len")]
+type StoredLen = u16;
+#[cfg(not(feature = "compact-doc-len"))]
+type StoredLen = u32;
+
 /// Document metadata for BM25
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// With the `compact-doc-len` feature, counts are stored as `u16`. Documents
+/// of 65535 tokens or more store the cap and keep their real counts in the
+/// index's overflow map; read lengths with `BM25Index::doc_len`.
+#[derive(Debug, Clone)]
 pub struct DocMeta {
     pub doc_id: u32,
-    pub doc_len: u32,
+    doc_len: StoredLen,
+    unique_terms: StoredLen, // number of distinct terms in the document
+    /// Language detected when indexed with `auto_language`
+    pub language: Option<Language>,
+}
+
+// Casts between `StoredLen` and u32 are no-ops without `compact-doc-len`
+#[allow(clippy::unnecessary_cast)]
+impl DocMeta {
+    /// Pack a document's counts, recording them in `long_docs` if they reach the cap
+    pub(crate) fn new(doc_id: u32, doc_len: u32, unique_terms: u32, long_docs: &mut AHashMap<u32, (u32, u32)>) -> Self {
+        if doc_len >= StoredLen::MAX as u32 {
+            long_docs.insert(doc_id, (doc_len, unique_terms));
+            return Self {
+                doc_id,
+                doc_len: StoredLen::MAX,
+                unique_terms: StoredLen::MAX,
+                language: None,
+            };
+        }
+        Self {
+            doc_id,
+            doc_len: doc_len as StoredLen,
+            unique_terms: unique_terms as StoredLen,
+            language: None,
+        }
+    }
+
+    /// Token count, read from `long_docs` for documents past the cap
+    pub(crate) fn doc_len(&self, long_docs: &AHashMap<u32, (u32, u32)>) -> u32 {
+        self.counts(long_docs).0
+    }
+
+    /// Distinct term count, read from `long_docs` for documents past the cap
+    pub(crate) fn unique_terms(&self, long_docs: &AHashMap<u32, (u32, u32)>) -> u32 {
+        self.counts(long_docs).1
+    }
+
+    fn counts(&self, long_docs: &AHashMap<u32, (u32, u32)>) -> (u32, u32) {
+        if self.doc_len == StoredLen::MAX {
+            if let Some(&counts) = long_docs.get(&self.doc_id) {
+                return counts;
+            }
+        }
+        (self.doc_len as u32, self.unique_terms as u32)
+    }
+}
+
+/// `DocMeta` as saved, with full-width counts whatever the storage width
+#[derive(Serialize, Deserialize)]
+struct SavedDocMeta {
+    doc_id: u32,
+    doc_len: u32,
+    #[serde(default)]
+    unique_terms: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<Language>,
 }
 
 /// Search result
 #[derive(Debug, Clone, PartialEq)]
 pub struct SearchResult {
+    pub doc_id: u32,
+    pub chunk_id: String,
+    pub score: f32,
+    pub rank: usize,
+}
+
+/// Search result with the document's payload from `add_document_with_payload`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResultWithPayload {
+    pub doc_id: u32,
     pub chunk_id: String,
     pub score: f32,
     pub rank: usize,
+    pub payload: Option<String>,
+}
+
+/// Search results as parallel columns, best first
+///
+/// `doc_ids[i]` scored `scores[i]`; ranks are the positions. No chunk ids are
+/// formatted, so callers map doc_ids to their own keys.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchResultsSoA {
+    pub doc_ids: Vec<u32>,
+    pub scores: Vec<f32>,
+}
+
+/// Candidates `BM25Index::search_deadline` scores between clock checks
+const DEADLINE_CHECK_INTERVAL: usize = 256;
+
+/// Top BM25 results per requested result that `BM25Index::search_mmr` reranks
+const MMR_POOL_FACTOR: usize = 4;
+
+/// Snapshot format version written by `BM25Index::write_to`
+///
+/// Version history:
+/// - 1: JSON snapshot lines without a `format_version` field. Fields added
+///   since then are optional, so these load with their defaults.
+/// - 2: every snapshot line starts with `format_version`.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Version assumed for snapshots written before versioning
+fn legacy_format_version() -> u32 {
+    1
+}
+
+/// Leading fields of a snapshot, parsed without the rest of the index
+#[derive(Deserialize)]
+struct SnapshotHeader {
+    #[serde(default = "legacy_format_version")]
+    format_version: u32,
+}
+
+/// On-disk representation of an index
+///
+/// Compressed postings are not stored; they are rebuilt on load when the
+/// saved index had been built. Written through `SnapshotRef`.
+#[derive(Deserialize)]
+struct IndexSnapshot {
+    #[serde(default = "legacy_format_version")]
+    format_version: u32,
+    tokenizer: Tokenizer,
+    params: BM25Params,
+    doc_metas: Vec<SavedDocMeta>,
+    term_freqs: BTreeMap<String, BTreeMap<u32, u32>>,
+    built: bool,
+    #[serde(default)]
+    external_ids: BTreeMap<u32, String>,
+    #[serde(default)]
+    groups: BTreeMap<u32, String>,
+    #[serde(default)]
+    priors: BTreeMap<u32, f32>,
+    #[serde(default)]
+    payloads: BTreeMap<u32, String>,
+    #[serde(default)]
+    store_text: bool,
+    #[serde(default)]
+    texts: BTreeMap<u32, String>,
+    #[serde(default)]
+    rank_base: usize,
+    #[serde(default)]
+    score_epsilon: f32,
+    #[serde(default)]
+    raw_postings: bool,
+    #[serde(default)]
+    highlight_markers: Option<(String, String)>,
+    #[serde(default)]
+    hashed_keys: BTreeMap<u32, String>,
+    #[serde(default)]
+    sorted_term_dict: bool,
+    #[serde(default)]
+    vocabulary: Vec<String>,
+    #[serde(default)]
+    auto_language: bool,
+    #[serde(default)]
+    bigrams: bool,
+    #[serde(default)]
+    position_gap: u32,
+    #[serde(default)]
+    max_docs: Option<usize>,
+    #[serde(default)]
+    max_candidates: Option<usize>,
+    #[serde(default)]
+    capacity_policy: CapacityPolicy,
+    #[serde(default)]
+    field_starts: BTreeMap<u32, Vec<u32>>,
+    #[cfg(feature = "vectors")]
+    #[serde(default)]
+    vectors: BTreeMap<u32, Vec<f32>>,
+}
+
+/// An index serialized in the `IndexSnapshot` format, borrowing its data
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    format_version: u32,
+    tokenizer: &'a Tokenizer,
+    params: BM25Params,
+    doc_metas: SavedDocMetas<'a>,
+    term_freqs: TermFreqs<'a>,
+    built: bool,
+    external_ids: DocMap<'a, String>,
+    groups: DocMap<'a, String>,
+    priors: DocMap<'a, f32>,
+    payloads: DocMap<'a, String>,
+    store_text: bool,
+    texts: DocMap<'a, String>,
+    rank_base: usize,
+    score_epsilon: f32,
+    raw_postings: bool,
+    highlight_markers: Option<(&'a str, &'a str)>,
+    hashed_keys: DocMap<'a, String>,
+    sorted_term_dict: bool,
+    vocabulary: &'a [String],
+    auto_language: bool,
+    bigrams: bool,
+    position_gap: u32,
+    max_docs: Option<usize>,
+    max_candidates: Option<usize>,
+    capacity_policy: CapacityPolicy,
+    field_starts: DocMap<'a, Vec<u32>>,
+    #[cfg(feature = "vectors")]
+    vectors: DocMap<'a, Vec<f32>>,
+}
+
+/// Metadata of the documents from `from` on, oldest first
+///
+/// Saved in insertion order so `EvictOldest` keeps its order across a reload.
+struct SavedDocMetas<'a> {
+    index: &'a BM25Index,
+    from: usize,
+}
+
+impl Serialize for SavedDocMetas<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let index = self.index;
+        let metas: AHashMap<u32, &DocMeta> = index.doc_metas.iter().map(|m| (m.doc_id, m)).collect();
+        serializer.collect_seq(index.insertion_order.range(self.from..).filter_map(|doc_id| metas.get(doc_id)).map(|m| {
+            let (doc_len, unique_terms) = m.counts(&index.long_docs);
+            SavedDocMeta {
+                doc_id: m.doc_id,
+                doc_len,
+                unique_terms,
+                language: m.language,
+            }
+        }))
+    }
+}
+
+/// Term frequencies of the kept documents, sorted by term then doc_id
+struct TermFreqs<'a> {
+    term_freqs: &'a AHashMap<String, HashMap<u32, u32>>,
+    keep: &'a dyn Fn(&u32) -> bool,
+}
+
+impl Serialize for TermFreqs<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut terms: Vec<&String> = self.term_freqs.keys().collect();
+        terms.sort_unstable();
+        // Only one term's frequencies are copied (to sort them) at a time
+        serializer.collect_map(terms.into_iter().filter_map(|term| {
+            let mut docs: Vec<(u32, u32)> = self.term_freqs[term]
+                .iter()
+                .filter(|(doc_id, _)| (self.keep)(doc_id))
+                .map(|(&doc_id, &freq)| (doc_id, freq))
+                .collect();
+            docs.sort_unstable();
+            (!docs.is_empty()).then_some((term, SortedPairs(docs)))
+        }))
+    }
+}
+
+/// Already sorted `(doc_id, value)` pairs written as a map
+struct SortedPairs(Vec<(u32, u32)>);
+
+impl Serialize for SortedPairs {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(doc_id, value)| (doc_id, value)))
+    }
+}
+
+/// A per-document map written sorted by doc_id, skipping documents `keep` rejects
+struct DocMap<'a, V> {
+    docs: &'a AHashMap<u32, V>,
+    keep: &'a dyn Fn(&u32) -> bool,
+}
+
+impl<V: Serialize> Serialize for DocMap<'_, V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut doc_ids: Vec<u32> = self.docs.keys().copied().filter(|doc_id| (self.keep)(doc_id)).collect();
+        doc_ids.sort_unstable();
+        serializer.collect_map(doc_ids.iter().map(|doc_id| (doc_id, &self.docs[doc_id])))
+    }
+}
+
+/// A term's sorted doc_ids, block-compressed or stored as-is
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PostingsList {
+    Compressed(Vec<u8>),
+    Raw(Vec<u32>),
+}
+
+impl PostingsList {
+    /// Number of doc_ids in the list
+    fn len(&self) -> usize {
+        match self {
+            PostingsList::Compressed(data) => block_postings_len(data),
+            PostingsList::Raw(doc_ids) => doc_ids.len(),
+        }
+    }
+
+    /// Iterate doc_ids in ascending order
+    fn iter(&self) -> Box<dyn Iterator<Item = u32> + '_> {
+        match self {
+            PostingsList::Compressed(data) => Box::new(BlockPostingsReader::new(data)),
+            PostingsList::Raw(doc_ids) => Box::new(doc_ids.iter().copied()),
+        }
+    }
+
+    /// Heap bytes used by the list
+    fn heap_bytes(&self) -> usize {
+        match self {
+            PostingsList::Compressed(data) => data.len(),
+            PostingsList::Raw(doc_ids) => doc_ids.len() * std::mem::size_of::<u32>(),
+        }
+    }
+
+    /// Block-compressed form, encoding raw lists
+    fn into_block_bytes(self) -> Vec<u8> {
+        match self {
+            PostingsList::Compressed(data) => data,
+            PostingsList::Raw(doc_ids) => encode_block_postings(&doc_ids, DEFAULT_BLOCK_SIZE),
+        }
+    }
+}
+
+/// One occurrence of a term in a stored document
+struct TermPosition {
+    token: u32, // index of the token within the document
+    start: u32, // byte offset of the token in the stored text
+    end: u32,
+}
+
+/// Caches precomputed by `BM25Index::warm_up`
+struct WarmCaches {
+    idf: AHashMap<String, f32>,         // term -> idf
+    upper_bounds: AHashMap<String, f32>, // term -> max single-doc score
+    sorted_terms: Vec<String>,
+    doc_positions: AHashMap<u32, usize>, // doc_id -> index into doc_metas
 }
 
 /// BM25 inverted index
@@ -79,12 +636,74 @@ pub struct BM25Index {
     params: BM25Params,
 
     // Core index structures
-    postings: AHashMap<String, Vec<u8>>, // term -> compressed doc_ids
+    postings: AHashMap<String, PostingsList>, // term -> sorted dense ids
+    compress_postings: bool,                 // block-compress postings on build
+    dense_ids: AHashMap<u32, u32>,           // doc_id -> dense id (position in doc_metas)
+    dense_synced: usize,                     // doc_metas entries covered by dense_ids
+    dirty_terms: AHashSet<String>,       // terms changed since postings were built
+    sorted_term_dict: bool,              // keep a sorted term array for binary search
+    auto_language: bool,                 // analyze each document in its detected language
+    bigrams: bool,                       // also index adjacent token pairs as `first_second`
+    term_dict: Vec<String>,              // sorted terms as of the last build, if enabled
+    vocabulary: Vec<String>,             // imported shared vocabulary, in term id order
+    vocabulary_ids: AHashMap<String, TermId>, // term -> position in `vocabulary`
     term_freqs: AHashMap<String, HashMap<u32, u32>>, // term -> {doc_id: freq}
     doc_metas: Vec<DocMeta>,
+    long_docs: AHashMap<u32, (u32, u32)>, // doc_id -> (doc_len, unique_terms) past the DocMeta cap
+    insertion_order: VecDeque<u32>,       // doc_ids, oldest first
+    doc_terms: AHashMap<u32, Vec<String>>, // doc_id -> its terms, kept under EvictOldest
+
+    // Document cap enforced on add
+    max_docs: Option<usize>,
+
+    // Log of additions and removals since the last save, if opened with one
+    wal: Option<Wal>,
+
+    // What the last save or load covers, so segments hold only new documents
+    saved_docs: AtomicUsize, // leading entries of insertion_order already in the file
+    saved_stale: AtomicBool, // a saved document changed since, which segments can't express
+
+    // Bound on documents scored per query, filled from the rarest terms first
+    max_candidates: Option<usize>,
+    capacity_policy: CapacityPolicy,
+
+    // External identifiers
+    external_ids: AHashMap<u32, String>, // doc_id -> external id
+    doc_ids: AHashMap<String, u32>,      // external id -> doc_id
+    hashed_keys: AHashMap<u32, String>,  // doc_id -> key, for collision checks
+
+    // Group tags for result collapsing
+    groups: AHashMap<u32, String>, // doc_id -> group id
+
+    // Static per-document scores combined per `BM25Params::prior_mode`
+    priors: AHashMap<u32, f32>, // doc_id -> prior
+
+    // Opaque caller data returned with search results
+    payloads: AHashMap<u32, String>, // doc_id -> payload
+
+    // Stored document text and term spans within it, kept if `store_text`
+    store_text: bool,
+    texts: AHashMap<u32, String>, // doc_id -> original text
+    highlight_markers: (String, String), // (open, close) wrapped around matches
+    positions: AHashMap<String, HashMap<u32, Vec<TermPosition>>>, // term -> {doc_id: occurrences}
+    position_gap: u32,                    // token positions skipped between fields
+    field_starts: AHashMap<u32, Vec<u32>>, // doc_id -> byte offsets of the 2nd and later fields
+
+    // Dense vectors for hybrid reranking
+    #[cfg(feature = "vectors")]
+    vectors: AHashMap<u32, Vec<f32>>, // doc_id -> embedding
 
     // Statistics
     total_doc_len: u64,
+
+    // How scored documents are ordered and numbered
+    ranking: Ranking,
+
+    // Replaces BM25 term scoring when set
+    custom_scorer: Option<Box<dyn Scorer>>,
+
+    // Precomputed caches, cleared whenever the index changes
+    warm: Option<WarmCaches>,
 }
 
 impl BM25Index {
@@ -95,201 +714,5571 @@ impl BM25Index {
 
     /// Create index with custom parameters
     pub fn with_params(params: BM25Params) -> Self {
+        Self::with_tokenizer(params, Tokenizer::default())
+    }
+
+    /// Create index with custom parameters and tokenizer
+    ///
+    /// The tokenizer is used for both documents and queries.
+    pub fn with_tokenizer(params: BM25Params, tokenizer: Tokenizer) -> Self {
         Self {
-            tokenizer: Tokenizer::default(),
+            tokenizer,
             params,
             postings: AHashMap::new(),
+            compress_postings: true,
+            dense_ids: AHashMap::new(),
+            dense_synced: 0,
+            dirty_terms: AHashSet::new(),
+            sorted_term_dict: false,
+            term_dict: Vec::new(),
+            vocabulary: Vec::new(),
+            vocabulary_ids: AHashMap::new(),
+            auto_language: false,
+            bigrams: false,
             term_freqs: AHashMap::new(),
             doc_metas: Vec::new(),
+            long_docs: AHashMap::new(),
+            insertion_order: VecDeque::new(),
+            doc_terms: AHashMap::new(),
+            max_docs: None,
+            wal: None,
+            saved_docs: AtomicUsize::new(0),
+            saved_stale: AtomicBool::new(false),
+            max_candidates: None,
+            capacity_policy: CapacityPolicy::default(),
+            external_ids: AHashMap::new(),
+            doc_ids: AHashMap::new(),
+            hashed_keys: AHashMap::new(),
+            groups: AHashMap::new(),
+            priors: AHashMap::new(),
+            payloads: AHashMap::new(),
+            store_text: false,
+            texts: AHashMap::new(),
+            highlight_markers: ("<em>".to_string(), "</em>".to_string()),
+            positions: AHashMap::new(),
+            position_gap: 0,
+            field_starts: AHashMap::new(),
+            #[cfg(feature = "vectors")]
+            vectors: AHashMap::new(),
             total_doc_len: 0,
+            ranking: Ranking::default(),
+            custom_scorer: None,
+            warm: None,
         }
     }
 
-    /// Add a document to the index
-    pub fn add_document(&mut self, doc_id: u32, text: &str) {
-        let tokens = self.tokenizer.tokenize(text);
-        let doc_len = tokens.len() as u32;
+    /// Score terms with a custom function instead of BM25 (default: `BM25Scorer`)
+    ///
+    /// Used by `search` and the searches built on it. Proximity, set
+    /// similarity and global-stats searches keep BM25, and the scorer is
+    /// neither saved nor carried over by `freeze`.
+    pub fn with_scorer(mut self, scorer: impl Scorer + 'static) -> Self {
+        self.custom_scorer = Some(Box::new(scorer));
+        self
+    }
 
-        // Track document metadata
-        self.doc_metas.push(DocMeta { doc_id, doc_len });
-        self.total_doc_len += doc_len as u64;
+    /// Keep each document's text and term positions for highlighting
+    ///
+    /// Only documents added after this is enabled are stored.
+    pub fn with_store_text(mut self, store_text: bool) -> Self {
+        self.store_text = store_text;
+        self
+    }
 
-        // Count term frequencies
-        let mut term_counts: HashMap<String, u32> = HashMap::new();
-        for term in tokens {
-            *term_counts.entry(term).or_insert(0) += 1;
-        }
+    /// Store postings block-compressed (default) or as raw sorted doc_ids
+    ///
+    /// Raw postings skip delta/varint encoding, which saves CPU on tiny or
+    /// debug indexes at the cost of 4 bytes per posting. Takes effect on the
+    /// next build; search results are identical either way.
+    pub fn with_compress_postings(mut self, compress_postings: bool) -> Self {
+        self.compress_postings = compress_postings;
+        self
+    }
 
-        // Update inverted index
-        for (term, count) in term_counts {
-            self.term_freqs
-                .entry(term)
-                .or_insert_with(HashMap::new)
-                .insert(doc_id, count);
-        }
+    /// Set the rank of the top search result (default: 0)
+    ///
+    /// Use 1 for 1-based ranks; later results increment from the base.
+    pub fn with_rank_base(mut self, rank_base: usize) -> Self {
+        self.ranking.rank_base = rank_base;
+        self
     }
 
-    /// Build compressed postings lists (call after adding all documents)
-    pub fn build(&mut self) {
-        for (term, doc_freqs) in &self.term_freqs {
-            let mut doc_ids: Vec<u32> = doc_freqs.keys().copied().collect();
-            doc_ids.sort_unstable();
-            let compressed = encode_postings(&doc_ids);
-            self.postings.insert(term.clone(), compressed);
-        }
+    /// Treat scores closer than `epsilon` as ties, ordered by doc_id (default: 0)
+    ///
+    /// Keeps result order stable when minor index changes nudge scores. Ties
+    /// don't chain: scores are grouped with the highest score less than
+    /// `epsilon` above them, so scores `epsilon` or more apart keep their
+    /// order.
+    pub fn with_score_epsilon(mut self, epsilon: f32) -> Self {
+        self.ranking.score_epsilon = epsilon;
+        self
     }
 
-    /// Search the index
-    pub fn search(&self, query: &str, k: usize) -> Vec<SearchResult> {
-        let query_tokens = self.tokenizer.tokenize(query);
-        if query_tokens.is_empty() {
-            return Vec::new();
-        }
+    /// Also keep the vocabulary as a sorted array, rebuilt on every build
+    ///
+    /// Term lookups then binary-search a contiguous array instead of hashing
+    /// and `terms_with_prefix` becomes a range scan. Point lookups are usually
+    /// still faster through the hash map (see `bench_term_lookup`); the array
+    /// mainly pays off for prefix queries. Terms added since the last build
+    /// fall back to the hash map until the next build.
+    pub fn with_sorted_term_dict(mut self, enabled: bool) -> Self {
+        self.sorted_term_dict = enabled;
+        self
+    }
 
-        // Create scorer
-        let avg_doc_len = if self.doc_metas.is_empty() {
-            1.0
-        } else {
-            self.total_doc_len as f32 / self.doc_metas.len() as f32
-        };
-        let scorer = BM25Scorer::new(self.params, avg_doc_len, self.doc_metas.len());
+    /// Score at most `max_candidates` documents per query (default: unbounded)
+    ///
+    /// Bounds worst-case latency when a query contains a very common term.
+    /// Candidates are taken from the query's rarest terms first, so
+    /// documents matching selective terms are kept; which documents of the
+    /// term that reaches the cap get in is unspecified. Above the cap,
+    /// results are approximate: a document outside the candidate set can't
+    /// be returned even if it would have scored well.
+    pub fn with_max_candidates(mut self, max_candidates: usize) -> Self {
+        self.max_candidates = Some(max_candidates);
+        self
+    }
 
-        // Collect candidate documents
-        let mut candidates = AHashSet::new();
-        for token in &query_tokens {
-            if let Some(term_docs) = self.term_freqs.get(token) {
-                candidates.extend(term_docs.keys());
-            }
-        }
+    /// Also index each pair of adjacent tokens as one term, e.g. "new_york"
+    ///
+    /// Queries are expanded with their own bigrams, so documents containing
+    /// the words next to each other score above documents where they occur
+    /// apart. Bigrams don't count toward document length. Frozen indexes
+    /// don't expand queries, and `append_to_document` doesn't pair the
+    /// appended text with the document's last token.
+    pub fn with_bigrams(mut self, bigrams: bool) -> Self {
+        self.bigrams = bigrams;
+        self
+    }
 
-        // Score each candidate
-        let mut scores: Vec<(u32, f32)> = candidates
-            .iter()
-            .map(|&&doc_id| {
-                let score = self.score_document(doc_id, &query_tokens, &scorer);
-                (doc_id, score)
-            })
-            .collect();
+    /// Detect each document's language and apply its stopwords and stemmer
+    ///
+    /// Detection is a stopword-count heuristic over the supported
+    /// `Language`s; documents where no language clearly wins are indexed
+    /// with the plain tokenizer. BM25 searches, including frozen indexes,
+    /// analyze the query once per language and match each variant only
+    /// against documents of that language. `search_structured`,
+    /// `search_fast`, `search_set_similarity` and stored-text positions
+    /// (phrase matching, `best_span`, proximity bonuses) use the plain
+    /// tokenizer.
+    pub fn with_auto_language(mut self, auto_language: bool) -> Self {
+        self.auto_language = auto_language;
+        self
+    }
 
-        // Sort by score descending
-        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    /// Skip `gap` token positions between fields of `add_document_fields` (default: 0)
+    ///
+    /// Like Lucene's `positionIncrementGap`: a large gap stops phrase and
+    /// proximity matches from spanning the end of one field and the start of
+    /// the next. Only affects positions, which are kept when `store_text` is on.
+    pub fn with_position_gap(mut self, gap: u32) -> Self {
+        self.position_gap = gap;
+        self
+    }
 
-        // Return top-k results
-        scores
-            .into_iter()
-            .take(k)
-            .enumerate()
-            .map(|(rank, (doc_id, score))| SearchResult {
-                chunk_id: format!("chunk_{}", doc_id),
-                score,
-                rank,
-            })
-            .collect()
+    /// Cap the index at `max_docs` documents (default: unbounded)
+    ///
+    /// Once full, adding a document either fails (`CapacityPolicy::Reject`:
+    /// the add methods return `false`, `try_add_document` returns an error)
+    /// or first removes the earliest-added document
+    /// (`CapacityPolicy::EvictOldest`). Documents that tokenize to nothing
+    /// are rejected before the cap is checked, so they never evict.
+    pub fn with_max_docs(mut self, max_docs: usize, policy: CapacityPolicy) -> Self {
+        self.max_docs = Some(max_docs);
+        self.capacity_policy = policy;
+        self
     }
 
-    /// Score a single document for a query
-    fn score_document(&self, doc_id: u32, query_tokens: &[String], scorer: &BM25Scorer) -> f32 {
-        let doc_len = self.doc_metas
-            .iter()
-            .find(|m| m.doc_id == doc_id)
-            .map(|m| m.doc_len as f32)
-            .unwrap_or(1.0);
-
-        let mut score = 0.0;
-        for token in query_tokens {
-            if let Some(term_docs) = self.term_freqs.get(token) {
-                if let Some(&term_freq) = term_docs.get(&doc_id) {
-                    let doc_freq = term_docs.len();
-                    score += scorer.score_term(term_freq as f32, doc_len, doc_freq);
-                }
-            }
+    /// Create an empty index that logs changes to a write-ahead log at `path`
+    ///
+    /// Entries already in the log (from a run that crashed before saving)
+    /// are replayed first. See `attach_wal`.
+    pub fn open_with_wal(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new().attach_wal(path)
+    }
+
+    /// Replay the write-ahead log at `path`, then log every later change to it
+    ///
+    /// Every method that changes documents appends an entry before
+    /// returning: the `add_document*` family (including
+    /// `add_document_counts`, external ids, groups, priors, payloads and
+    /// hashed keys), `append_to_document`, `remove_document`, `decay` and
+    /// `reindex_with`. Documents therefore
+    /// survive a crash and are restored by reopening the log. Settings and
+    /// builds are not logged; open the index with the same settings before
+    /// attaching. A successful `save` truncates the log, so to recover load
+    /// the last saved index and attach its log. Write errors are kept for
+    /// `take_wal_error` rather than failing the mutation.
+    pub fn attach_wal(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let (wal, entries) = Wal::open(path)?;
+        for entry in entries {
+            self.replay(entry);
         }
-        score
+        self.wal = Some(wal);
+        Ok(self)
     }
 
-    /// Get index statistics
-    pub fn stats(&self) -> IndexStats {
-        IndexStats {
-            num_docs: self.doc_metas.len(),
-            num_terms: self.postings.len(),
-            avg_doc_len: if self.doc_metas.is_empty() {
-                0.0
-            } else {
-                self.total_doc_len as f32 / self.doc_metas.len() as f32
-            },
+    /// Apply one logged mutation; the log isn't attached yet, so it isn't re-logged
+    fn replay(&mut self, entry: WalEntry) {
+        match entry {
+            WalEntry::Add { doc_id, text } => {
+                self.add_document(doc_id, &text);
+            }
+            WalEntry::AddFields { doc_id, fields } => {
+                let fields: Vec<&str> = fields.iter().map(String::as_str).collect();
+                self.add_document_fields(doc_id, &fields);
+            }
+            WalEntry::AddCounts { doc_id, counts, doc_len } => {
+                let counts: Vec<(&str, u32)> = counts.iter().map(|(term, count)| (term.as_str(), *count)).collect();
+                self.add_document_counts(doc_id, &counts, doc_len);
+            }
+            WalEntry::Append { doc_id, text } => {
+                self.append_to_document(doc_id, &text);
+            }
+            WalEntry::Remove { doc_id } => {
+                self.remove_document(doc_id);
+            }
+            WalEntry::ExternalId { doc_id, external_id } => {
+                self.doc_ids.insert(external_id.clone(), doc_id);
+                self.external_ids.insert(doc_id, external_id);
+            }
+            WalEntry::Group { doc_id, group_id } => {
+                self.groups.insert(doc_id, group_id);
+            }
+            WalEntry::Prior { doc_id, prior } => {
+                self.priors.insert(doc_id, prior);
+            }
+            WalEntry::Payload { doc_id, payload } => {
+                self.payloads.insert(doc_id, payload);
+            }
+            WalEntry::HashedKey { doc_id, key } => {
+                self.hashed_keys.insert(doc_id, key);
+            }
+            #[cfg(feature = "vectors")]
+            WalEntry::Vector { doc_id, vector } => {
+                self.vectors.insert(doc_id, vector);
+            }
+            WalEntry::Decay { factor } => self.decay(factor),
+            WalEntry::Reindex { tokenizer } => {
+                // Logged only after succeeding, so the stored texts are there
+                let _ = self.reindex_with(tokenizer);
+            }
         }
     }
-}
 
-impl Default for BM25Index {
-    fn default() -> Self {
-        Self::new()
+    /// The first write-ahead log write error since the last call, if any
+    pub fn take_wal_error(&mut self) -> Option<io::Error> {
+        self.wal.as_mut()?.take_error()
     }
-}
 
-/// Index statistics
-#[derive(Debug, Clone)]
-pub struct IndexStats {
-    pub num_docs: usize,
-    pub num_terms: usize,
-    pub avg_doc_len: f32,
-}
+    /// Add a document to the index, failing if it is full under `CapacityPolicy::Reject`
+    ///
+    /// Otherwise behaves like `add_document`.
+    pub fn try_add_document(&mut self, doc_id: u32, text: &str) -> std::result::Result<bool, DocLimitReached> {
+        match self.max_docs {
+            Some(max_docs) if self.capacity_policy == CapacityPolicy::Reject && self.doc_metas.len() >= max_docs => {
+                Err(DocLimitReached { max_docs })
+            }
+            _ => Ok(self.add_document(doc_id, text)),
+        }
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Add a document with an external identifier (e.g. a chunk id)
+    ///
+    /// The external id is reported as `chunk_id` in search results.
+    pub fn add_document_with_external_id(&mut self, doc_id: u32, external_id: &str, text: &str) -> bool {
+        if !self.add_document(doc_id, text) {
+            return false;
+        }
+        self.external_ids.insert(doc_id, external_id.to_string());
+        self.doc_ids.insert(external_id.to_string(), doc_id);
+        if let Some(wal) = &mut self.wal {
+            wal.append(&WalEntry::ExternalId {
+                doc_id,
+                external_id: external_id.to_string(),
+            });
+        }
+        true
+    }
 
-    #[test]
-    fn test_bm25_scorer() {
-        let scorer = BM25Scorer::new(BM25Params::default(), 10.0, 100);
-        let score = scorer.score_term(2.0, 10.0, 50);
-        assert!(score > 0.0);
+    /// Add a document tagged with a group (e.g. its source article)
+    ///
+    /// Groups are used by `search_grouped` to cap results per source.
+    pub fn add_document_grouped(&mut self, doc_id: u32, group_id: &str, text: &str) -> bool {
+        if !self.add_document(doc_id, text) {
+            return false;
+        }
+        self.groups.insert(doc_id, group_id.to_string());
+        if let Some(wal) = &mut self.wal {
+            wal.append(&WalEntry::Group {
+                doc_id,
+                group_id: group_id.to_string(),
+            });
+        }
+        true
     }
 
-    #[test]
-    fn test_index_build() {
+    /// Add a document with a static, query-independent prior
+    ///
+    /// The prior boosts (or demotes) the document in every BM25 search,
+    /// e.g. for chunks from featured articles. It is multiplied with or
+    /// added to the score depending on `BM25Params::prior_mode`.
+    pub fn add_document_with_prior(&mut self, doc_id: u32, text: &str, prior: f32) -> bool {
+        if !self.add_document(doc_id, text) {
+            return false;
+        }
+        self.priors.insert(doc_id, prior);
+        if let Some(wal) = &mut self.wal {
+            wal.append(&WalEntry::Prior { doc_id, prior });
+        }
+        true
+    }
+
+    /// Add a document with an opaque payload, e.g. a JSON blob of metadata
+    ///
+    /// The payload is saved with the index and returned by
+    /// `search_with_payload`, so callers need no separate lookup.
+    pub fn add_document_with_payload(&mut self, doc_id: u32, text: &str, payload: String) -> bool {
+        if !self.add_document(doc_id, text) {
+            return false;
+        }
+        if let Some(wal) = &mut self.wal {
+            wal.append(&WalEntry::Payload {
+                doc_id,
+                payload: payload.clone(),
+            });
+        }
+        self.payloads.insert(doc_id, payload);
+        true
+    }
+
+    /// Payload stored for a document, if any
+    pub fn payload(&self, doc_id: u32) -> Option<&str> {
+        self.payloads.get(&doc_id).map(String::as_str)
+    }
+
+    /// Add a document under a doc_id derived from a string key
+    ///
+    /// The id is `hash_doc_key(key)`, so the same key always maps to the same
+    /// id without keeping an external-id map. If another hashed key already
+    /// produced that id (or the key was already added) the document is not
+    /// indexed and the collision is returned. Collisions are only detected
+    /// among hashed documents, so don't mix them with explicit doc_ids.
+    ///
+    /// As with `add_document`, a text with no tokens is not indexed; its id
+    /// is still returned.
+    pub fn add_document_hashed(&mut self, key: &str, text: &str) -> std::result::Result<u32, HashCollision> {
+        let doc_id = hash_doc_key(key);
+        if let Some(existing) = self.hashed_keys.get(&doc_id) {
+            return Err(HashCollision {
+                key: key.to_string(),
+                doc_id,
+                existing: existing.clone(),
+            });
+        }
+        if self.add_document(doc_id, text) {
+            self.hashed_keys.insert(doc_id, key.to_string());
+            if let Some(wal) = &mut self.wal {
+                wal.append(&WalEntry::HashedKey {
+                    doc_id,
+                    key: key.to_string(),
+                });
+            }
+        }
+        Ok(doc_id)
+    }
+
+    /// Resolve an external identifier to its internal doc_id
+    pub fn resolve_id(&self, external: &str) -> Option<u32> {
+        self.doc_ids.get(external).copied()
+    }
+
+    /// Get the external identifier registered for a doc_id
+    pub fn external_id(&self, doc_id: u32) -> Option<&str> {
+        self.external_ids.get(&doc_id).map(String::as_str)
+    }
+
+    /// Add a document to the index
+    ///
+    /// Documents that tokenize to nothing (e.g. every word is shorter than
+    /// `min_length`) are rejected and `false` is returned: they could never
+    /// match a query and a zero length would skew the average document length.
+    pub fn add_document(&mut self, doc_id: u32, text: &str) -> bool {
+        let language = self.detect_language(text);
+        // Without a language the tokens are counted as they stream, never collected
+        let counts = match language {
+            Some(_) => count_terms(self.analyze(text, language), self.bigrams),
+            None => count_terms(self.tokenizer.tokenize_stream(text), self.bigrams),
+        };
+        if !self.index_terms(doc_id, counts, language) {
+            return false;
+        }
+        if self.store_text {
+            self.store_document_text(doc_id, text.to_string());
+        }
+        if let Some(wal) = &mut self.wal {
+            wal.append(&WalEntry::Add {
+                doc_id,
+                text: text.to_string(),
+            });
+        }
+        true
+    }
+
+    /// Add a document, choosing what happens if its doc_id is already indexed
+    ///
+    /// `add_document` itself assumes doc_ids are new. A new doc_id behaves
+    /// like `add_document` in every mode; `Accumulate` on an indexed one
+    /// behaves like `append_to_document`. Returns whether text was indexed.
+    pub fn add_document_mode(
+        &mut self,
+        doc_id: u32,
+        text: &str,
+        mode: AddMode,
+    ) -> std::result::Result<bool, DuplicateDocument> {
+        if self.doc_meta(doc_id).is_none() {
+            return Ok(self.add_document(doc_id, text));
+        }
+        match mode {
+            AddMode::Replace => {
+                self.remove_document(doc_id);
+                Ok(self.add_document(doc_id, text))
+            }
+            AddMode::Accumulate => Ok(self.append_to_document(doc_id, text)),
+            AddMode::Reject => Err(DuplicateDocument { doc_id }),
+        }
+    }
+
+    /// Add a document from precomputed term counts, skipping tokenization
+    ///
+    /// Terms are used as given, so they must match what the tokenizer
+    /// produces for queries. Repeated terms are summed and zero counts
+    /// ignored. `doc_len` is taken as given rather than summed from the
+    /// counts. Returns `false` for a zero `doc_len` or no counted terms.
+    pub fn add_document_counts(&mut self, doc_id: u32, term_counts: &[(&str, u32)], doc_len: u32) -> bool {
+        let mut counts: HashMap<String, u32> = HashMap::with_capacity(term_counts.len());
+        for &(term, count) in term_counts.iter().filter(|(_, count)| *count > 0) {
+            *counts.entry(term.to_string()).or_insert(0) += count;
+        }
+        if counts.is_empty() {
+            return false;
+        }
+        let logged = self.wal.is_some().then(|| {
+            let mut counts: Vec<(String, u32)> = counts.iter().map(|(term, &count)| (term.clone(), count)).collect();
+            counts.sort_unstable();
+            WalEntry::AddCounts { doc_id, counts, doc_len }
+        });
+        if !self.index_terms(doc_id, (doc_len, counts), None) {
+            return false;
+        }
+        if let (Some(wal), Some(entry)) = (&mut self.wal, logged) {
+            wal.append(&entry);
+        }
+        true
+    }
+
+    /// Add a document made of several fields, e.g. title and body
+    ///
+    /// Fields are scored as one text, but their token positions are separated
+    /// by the configured `position_gap`. Stored text joins fields with a space.
+    pub fn add_document_fields(&mut self, doc_id: u32, fields: &[&str]) -> bool {
+        let language = self.detect_language(&fields.join(" "));
+        let counts = count_terms(fields.iter().flat_map(|field| self.analyze(field, language)), self.bigrams);
+        if !self.index_terms(doc_id, counts, language) {
+            return false;
+        }
+        if self.store_text {
+            let mut starts = Vec::with_capacity(fields.len().saturating_sub(1));
+            let mut start = 0;
+            for field in &fields[..fields.len() - 1] {
+                start += field.len() + 1;
+                starts.push(start as u32);
+            }
+            self.field_starts.insert(doc_id, starts);
+            self.store_document_text(doc_id, fields.join(" "));
+        }
+        if let Some(wal) = &mut self.wal {
+            wal.append(&WalEntry::AddFields {
+                doc_id,
+                fields: fields.iter().map(|field| field.to_string()).collect(),
+            });
+        }
+        true
+    }
+
+    /// Language to analyze a new document in, if `auto_language` is on
+    fn detect_language(&self, text: &str) -> Option<Language> {
+        if self.auto_language {
+            Language::detect(text)
+        } else {
+            None
+        }
+    }
+
+    /// Tokenize text, then apply the language's stopwords and stemmer if any
+    fn analyze(&self, text: &str, language: Option<Language>) -> Vec<String> {
+        let tokens = self.tokenizer.tokenize(text);
+        match language {
+            Some(language) => language.analyze_terms(tokens),
+            None => tokens,
+        }
+    }
+
+    /// Add a document's `count_terms` statistics, rejecting empty documents
+    ///
+    /// Applies the `max_docs` cap, evicting the oldest document if needed.
+    fn index_terms(&mut self, doc_id: u32, counts: TermCounts, language: Option<Language>) -> bool {
+        let (doc_len, term_counts) = counts;
+        if doc_len == 0 {
+            return false;
+        }
+        if let Some(max_docs) = self.max_docs {
+            if self.doc_metas.len() >= max_docs {
+                if self.capacity_policy == CapacityPolicy::Reject || max_docs == 0 {
+                    return false;
+                }
+                while self.doc_metas.len() >= max_docs {
+                    let Some(&oldest) = self.insertion_order.front() else { break };
+                    self.remove_document(oldest);
+                }
+            }
+        }
+        self.warm = None;
+        if self.tracks_doc_terms() {
+            self.doc_terms.insert(doc_id, term_counts.keys().cloned().collect());
+        }
+
+        // Track document metadata
+        let mut meta = DocMeta::new(doc_id, doc_len, term_counts.len() as u32, &mut self.long_docs);
+        meta.language = language;
+        self.doc_metas.push(meta);
+        self.insertion_order.push_back(doc_id);
+        self.total_doc_len += doc_len as u64;
+
+        // Update inverted index
+        let built = !self.postings.is_empty();
+        for (term, count) in term_counts {
+            if built {
+                self.dirty_terms.insert(term.clone());
+            }
+            self.term_freqs
+                .entry(term)
+                .or_default()
+                .insert(doc_id, count);
+        }
+        true
+    }
+
+    /// Whether each document's term list is kept, so evictions are cheap
+    fn tracks_doc_terms(&self) -> bool {
+        self.max_docs.is_some() && self.capacity_policy == CapacityPolicy::EvictOldest
+    }
+
+    /// Remove a document and everything stored for it
+    ///
+    /// Term frequencies and document statistics are updated immediately.
+    /// If the index was built, the postings of the document's terms are
+    /// marked dirty and `build_incremental` brings them up to date; terms
+    /// left without documents are dropped right away. Under
+    /// `CapacityPolicy::EvictOldest` each document's term list is kept and
+    /// only those terms are visited; otherwise this scans the whole
+    /// vocabulary.
+    ///
+    /// Returns `false` if the document isn't indexed.
+    pub fn remove_document(&mut self, doc_id: u32) -> bool {
+        let Some(index) = self.doc_metas.iter().position(|m| m.doc_id == doc_id) else {
+            return false;
+        };
+        self.warm = None;
+
+        // Fill the hole with the last document so only its dense id changes
+        let meta = self.doc_metas.swap_remove(index);
+        self.total_doc_len -= meta.doc_len(&self.long_docs) as u64;
+        self.long_docs.remove(&doc_id);
+        self.dense_ids.remove(&doc_id);
+        let moved = self.doc_metas.get(index).map(|m| m.doc_id);
+        if let Some(moved) = moved {
+            if index < self.dense_synced {
+                self.dense_ids.insert(moved, index as u32);
+            }
+        }
+        self.dense_synced = self.dense_synced.min(self.doc_metas.len());
+        if let Some(position) = self.insertion_order.iter().position(|&d| d == doc_id) {
+            self.insertion_order.remove(position);
+            let saved_docs = self.saved_docs.get_mut();
+            if position < *saved_docs {
+                *saved_docs -= 1;
+                *self.saved_stale.get_mut() = true;
+            }
+        }
+
+        let built = !self.postings.is_empty();
+        let mut emptied = Vec::new();
+        let doc_terms = self.doc_terms.remove(&doc_id);
+        let listed = doc_terms.is_some() && moved.is_none_or(|moved| self.doc_terms.contains_key(&moved));
+        match doc_terms {
+            // Only the removed document's terms and the moved one's dense id change
+            Some(terms) if listed => {
+                for term in terms {
+                    let Some(docs) = self.term_freqs.get_mut(&term) else { continue };
+                    if docs.remove(&doc_id).is_none() {
+                        continue;
+                    }
+                    if docs.is_empty() {
+                        emptied.push(term);
+                    } else if built {
+                        self.dirty_terms.insert(term);
+                    }
+                }
+                if let Some(moved) = moved.filter(|_| built) {
+                    for term in &self.doc_terms[&moved] {
+                        if self.term_freqs.get(term).is_some_and(|docs| docs.contains_key(&moved)) {
+                            self.dirty_terms.insert(term.clone());
+                        }
+                    }
+                }
+            }
+            _ => {
+                for (term, docs) in self.term_freqs.iter_mut() {
+                    let removed = docs.remove(&doc_id).is_some();
+                    if docs.is_empty() {
+                        emptied.push(term.clone());
+                    } else if built && (removed || moved.is_some_and(|moved| docs.contains_key(&moved))) {
+                        self.dirty_terms.insert(term.clone());
+                    }
+                }
+            }
+        }
+        for term in &emptied {
+            self.term_freqs.remove(term);
+            self.postings.remove(term);
+            self.dirty_terms.remove(term);
+        }
+        if !emptied.is_empty() {
+            // The dictionary's term count no longer identifies it as current
+            self.term_dict.clear();
+        }
+
+        if let Some(external_id) = self.external_ids.remove(&doc_id) {
+            self.doc_ids.remove(&external_id);
+        }
+        self.hashed_keys.remove(&doc_id);
+        self.groups.remove(&doc_id);
+        self.priors.remove(&doc_id);
+        self.payloads.remove(&doc_id);
+        self.field_starts.remove(&doc_id);
+        match self.texts.remove(&doc_id) {
+            // Positions come from the stored text, so its tokens are their terms
+            Some(text) if listed => {
+                for (term, _) in self.tokenizer.tokenize_spans(&text) {
+                    let Some(docs) = self.positions.get_mut(&term) else { continue };
+                    docs.remove(&doc_id);
+                    if docs.is_empty() {
+                        self.positions.remove(&term);
+                    }
+                }
+            }
+            None if listed => {}
+            _ => self.positions.retain(|_, docs| {
+                docs.remove(&doc_id);
+                !docs.is_empty()
+            }),
+        }
+        #[cfg(feature = "vectors")]
+        self.vectors.remove(&doc_id);
+        if let Some(wal) = &mut self.wal {
+            wal.append(&WalEntry::Remove { doc_id });
+        }
+        true
+    }
+
+    /// Age term statistics by scaling every term frequency by `factor`
+    ///
+    /// Frequencies are multiplied by `factor` (clamped to 0..=1) and rounded
+    /// down, like halving counters in a streaming sketch: a term occurring
+    /// once in a document is dropped from it by the first decay below 1.
+    /// Calling this periodically gives old content exponentially less
+    /// weight, so the same query scores differently over time. Entries that
+    /// reach zero are removed along with the document's positions and
+    /// unique-term count for them, and terms left without documents
+    /// disappear. Document lengths are unchanged. Changed postings are marked dirty for
+    /// `build_incremental`.
+    pub fn decay(&mut self, factor: f32) {
+        let factor = factor.clamp(0.0, 1.0);
+        if factor == 1.0 {
+            return;
+        }
+        self.warm = None;
+        *self.saved_stale.get_mut() |= *self.saved_docs.get_mut() > 0;
+        let built = !self.postings.is_empty();
+        let mut emptied = Vec::new();
+        let mut dropped: AHashMap<u32, AHashSet<String>> = AHashMap::new(); // doc_id -> terms it lost
+        for (term, docs) in self.term_freqs.iter_mut() {
+            let before = docs.len();
+            docs.retain(|&doc_id, freq| {
+                *freq = (*freq as f32 * factor) as u32;
+                if *freq == 0 {
+                    dropped.entry(doc_id).or_default().insert(term.clone());
+                }
+                *freq > 0
+            });
+            if docs.is_empty() {
+                emptied.push(term.clone());
+            } else if built && docs.len() != before {
+                self.dirty_terms.insert(term.clone());
+            }
+        }
+        for term in &emptied {
+            self.term_freqs.remove(term);
+            self.postings.remove(term);
+            self.dirty_terms.remove(term);
+            self.positions.remove(term);
+        }
+        if !emptied.is_empty() {
+            self.term_dict.clear();
+        }
+
+        // Documents that lost terms no longer count, list or locate them
+        let mut languages = Vec::with_capacity(dropped.len());
+        for meta in &mut self.doc_metas {
+            let Some(terms) = dropped.get(&meta.doc_id) else { continue };
+            let (doc_len, unique_terms) = meta.counts(&self.long_docs);
+            let language = meta.language;
+            *meta = DocMeta::new(meta.doc_id, doc_len, unique_terms - terms.len() as u32, &mut self.long_docs);
+            meta.language = language;
+            languages.push((meta.doc_id, language));
+        }
+        for (doc_id, language) in languages {
+            let terms = &dropped[&doc_id];
+            if let Some(doc_terms) = self.doc_terms.get_mut(&doc_id) {
+                doc_terms.retain(|term| !terms.contains(term));
+            }
+            self.drop_positions(doc_id, language, terms);
+        }
+        if let Some(wal) = &mut self.wal {
+            wal.append(&WalEntry::Decay { factor });
+        }
+    }
+
+    /// Forget a document's positions of the stored tokens that analyze to `terms`
+    fn drop_positions(&mut self, doc_id: u32, language: Option<Language>, terms: &AHashSet<String>) {
+        let Some(text) = self.texts.get(&doc_id) else { return };
+        for (token, _) in self.tokenizer.tokenize_spans(text) {
+            let term = match language {
+                Some(language) => language.analyze_terms(vec![token.clone()]).pop(),
+                None => Some(token.clone()),
+            };
+            if !term.is_some_and(|term| terms.contains(&term)) {
+                continue;
+            }
+            if let Some(docs) = self.positions.get_mut(&token) {
+                docs.remove(&doc_id);
+                if docs.is_empty() {
+                    self.positions.remove(&token);
+                }
+            }
+        }
+    }
+
+    /// Recalculate corpus statistics from the documents and drop derived caches
+    ///
+    /// `total_doc_len` is summed afresh from the document lengths, terms
+    /// left without documents are removed, and the warm caches (IDF, score
+    /// upper bounds) and sorted term dictionary are rebuilt from scratch on
+    /// their next use or build. Scores afterwards match an index built from
+    /// the same documents. Call after bulk adds and removals.
+    pub fn recompute_stats(&mut self) {
+        self.total_doc_len = self.doc_metas.iter().map(|m| m.doc_len(&self.long_docs) as u64).sum();
+        self.term_freqs.retain(|_, docs| !docs.is_empty());
+        let term_freqs = &self.term_freqs;
+        self.postings.retain(|term, _| term_freqs.contains_key(term));
+        self.dirty_terms.retain(|term| term_freqs.contains_key(term));
+        self.term_dict.clear();
+        self.warm = None;
+    }
+
+    /// Re-tokenize every document's stored text with a new tokenizer and rebuild
+    ///
+    /// Needs `store_text` to have been on for every document; otherwise
+    /// nothing changes and the first document without text is reported.
+    /// Document lengths, term frequencies and positions are recomputed, while
+    /// external ids, payloads, priors and other per-document data are kept.
+    /// Documents the new tokenizer reduces to nothing are removed, as
+    /// `add_document` would have rejected them.
+    pub fn reindex_with(&mut self, tokenizer: Tokenizer) -> std::result::Result<(), TextNotStored> {
+        if let Some(meta) = self.doc_metas.iter().find(|m| !self.texts.contains_key(&m.doc_id)) {
+            return Err(TextNotStored { doc_id: meta.doc_id });
+        }
+        self.tokenizer = tokenizer;
+        *self.saved_stale.get_mut() |= *self.saved_docs.get_mut() > 0;
+        self.term_freqs.clear();
+        self.doc_terms.clear();
+        self.positions.clear();
+        self.postings.clear();
+        self.dirty_terms.clear();
+        self.total_doc_len = 0;
+
+        let mut empty = Vec::new();
+        for i in 0..self.doc_metas.len() {
+            let (doc_id, language) = (self.doc_metas[i].doc_id, self.doc_metas[i].language);
+            let text = self.texts.remove(&doc_id).unwrap_or_default();
+            let (doc_len, term_counts) = count_terms(self.analyze(&text, language), self.bigrams);
+            self.long_docs.remove(&doc_id);
+            let mut meta = DocMeta::new(doc_id, doc_len, term_counts.len() as u32, &mut self.long_docs);
+            meta.language = language;
+            self.doc_metas[i] = meta;
+            self.total_doc_len += doc_len as u64;
+            if self.tracks_doc_terms() {
+                self.doc_terms.insert(doc_id, term_counts.keys().cloned().collect());
+            }
+            for (term, count) in term_counts {
+                self.term_freqs.entry(term).or_default().insert(doc_id, count);
+            }
+            self.store_document_text(doc_id, text);
+            if doc_len == 0 {
+                empty.push(doc_id);
+            }
+        }
+        for doc_id in empty {
+            self.remove_document(doc_id);
+        }
+        self.build();
+        if let Some(wal) = &mut self.wal {
+            wal.append(&WalEntry::Reindex {
+                tokenizer: self.tokenizer.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Append text to an already indexed document
+    ///
+    /// Only `extra_text` is tokenized: its terms increment the document's
+    /// existing frequencies or are added as new terms, and the document
+    /// length grows by its token count. Changed terms are marked dirty so
+    /// `build_incremental` recompresses just their postings. Stored text, if
+    /// kept, is extended with a space and the new text.
+    ///
+    /// Returns `false` if the document isn't indexed or `extra_text` has no
+    /// tokens.
+    pub fn append_to_document(&mut self, doc_id: u32, extra_text: &str) -> bool {
+        let Some(language) = self.doc_metas.iter().find(|m| m.doc_id == doc_id).map(|m| m.language) else {
+            return false;
+        };
+        let mut tokens = self.analyze(extra_text, language);
+        let added_len = tokens.len() as u32;
+        if self.bigrams {
+            append_bigrams(&mut tokens);
+        }
+        let Some(meta) = self.doc_metas.iter_mut().find(|m| m.doc_id == doc_id) else {
+            return false;
+        };
+        if tokens.is_empty() {
+            return false;
+        }
+        self.warm = None;
+        let mut doc_terms = self.doc_terms.get_mut(&doc_id);
+
+        let (old_len, mut unique_terms) = meta.counts(&self.long_docs);
+        self.total_doc_len += added_len as u64;
+        let doc_len = old_len + added_len;
+
+        for term in tokens {
+            let freq = self.term_freqs.entry(term.clone()).or_default().entry(doc_id).or_insert(0);
+            if *freq == 0 {
+                unique_terms += 1;
+                if let Some(doc_terms) = &mut doc_terms {
+                    doc_terms.push(term.clone());
+                }
+            }
+            *freq += 1;
+            self.dirty_terms.insert(term);
+        }
+        *meta = DocMeta::new(doc_id, doc_len, unique_terms, &mut self.long_docs);
+        meta.language = language;
+
+        if let Some(text) = self.texts.get_mut(&doc_id) {
+            text.push(' ');
+            let byte_offset = text.len();
+            text.push_str(extra_text);
+            let gaps = self.field_starts.get(&doc_id).map_or(0, Vec::len) as u32;
+            self.index_positions(doc_id, extra_text, old_len + gaps * self.position_gap, byte_offset);
+        }
+        self.mark_saved_changed(doc_id);
+        if let Some(wal) = &mut self.wal {
+            wal.append(&WalEntry::Append {
+                doc_id,
+                text: extra_text.to_string(),
+            });
+        }
+        true
+    }
+
+    /// Note a change to `doc_id` that `save_segment` can't append if it was saved
+    fn mark_saved_changed(&mut self, doc_id: u32) {
+        let saved_docs = *self.saved_docs.get_mut();
+        if self.insertion_order.iter().take(saved_docs).any(|&d| d == doc_id) {
+            *self.saved_stale.get_mut() = true;
+        }
+    }
+
+    /// Store a document's text and index the position of every term
+    ///
+    /// Fields recorded in `field_starts` are indexed `position_gap` apart.
+    fn store_document_text(&mut self, doc_id: u32, text: String) {
+        let starts = self.field_starts.get(&doc_id).cloned().unwrap_or_default();
+        let bounds: Vec<usize> = std::iter::once(0)
+            .chain(starts.iter().map(|&start| start as usize))
+            .chain(std::iter::once(text.len() + 1))
+            .collect();
+        let mut token_offset = 0;
+        for field in bounds.windows(2) {
+            let field_text = &text[field[0]..field[1] - 1];
+            token_offset += self.index_positions(doc_id, field_text, token_offset, field[0]);
+            token_offset += self.position_gap;
+        }
+        self.texts.insert(doc_id, text);
+    }
+
+    /// Record term positions of `text`, offset to where it sits in the document
+    ///
+    /// Returns the number of tokens recorded.
+    fn index_positions(&mut self, doc_id: u32, text: &str, token_offset: u32, byte_offset: usize) -> u32 {
+        let spans = self.tokenizer.tokenize_spans(text);
+        let count = spans.len() as u32;
+        for (token, (term, span)) in spans.into_iter().enumerate() {
+            self.positions
+                .entry(term)
+                .or_default()
+                .entry(doc_id)
+                .or_default()
+                .push(TermPosition {
+                    token: token_offset + token as u32,
+                    start: (byte_offset + span.start) as u32,
+                    end: (byte_offset + span.end) as u32,
+                });
+        }
+        count
+    }
+
+    /// Stored text of a document, if `store_text` was enabled when it was added
+    pub fn stored_text(&self, doc_id: u32) -> Option<&str> {
+        self.texts.get(&doc_id).map(String::as_str)
+    }
+
+    /// Set the markers `highlight` wraps around matches (default: `<em>`, `</em>`)
+    pub fn set_highlight_markers(&mut self, open: &str, close: &str) {
+        self.highlight_markers = (open.to_string(), close.to_string());
+    }
+
+    /// Stored text of a document with every query term occurrence marked
+    ///
+    /// Markers are placed at token boundaries from `match_positions`;
+    /// overlapping matches (e.g. a hyphenated compound and its parts) are
+    /// merged into one marked span. Returns `None` if the text isn't stored.
+    pub fn highlight(&self, doc_id: u32, query: &str) -> Option<String> {
+        let text = self.texts.get(&doc_id)?;
+        let (open, close) = &self.highlight_markers;
+
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in self.match_positions(doc_id, query) {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        let mut out = String::with_capacity(text.len() + merged.len() * (open.len() + close.len()));
+        let mut cursor = 0;
+        for (start, end) in merged {
+            out.push_str(&text[cursor..start]);
+            out.push_str(open);
+            out.push_str(&text[start..end]);
+            out.push_str(close);
+            cursor = end;
+        }
+        out.push_str(&text[cursor..]);
+        Some(out)
+    }
+
+    /// Byte spans in a document's stored text where query terms occur
+    ///
+    /// Returns `(byte_start, byte_end)` pairs sorted by position, for
+    /// highlighting every occurrence. Empty unless the document was added
+    /// with `store_text` enabled.
+    pub fn match_positions(&self, doc_id: u32, query: &str) -> Vec<(usize, usize)> {
+        let mut spans: Vec<(usize, usize)> = self
+            .tokenizer
+            .tokenize_unique(query)
+            .iter()
+            .filter_map(|term| self.positions.get(term)?.get(&doc_id))
+            .flatten()
+            .map(|position| (position.start as usize, position.end as usize))
+            .collect();
+        spans.sort_unstable();
+        spans
+    }
+
+    /// Highest-scoring window of `window_tokens` tokens within a document
+    ///
+    /// Every window starting at a query term occurrence is scored with BM25,
+    /// using the term counts inside the window and the window size as the
+    /// document length. Returns `(byte_start, byte_end, score)`, spanning
+    /// the first to the last matched token of the best window; the earliest
+    /// window wins ties. `None` unless the document was added with
+    /// `store_text` and contains a query term.
+    pub fn best_span(&self, doc_id: u32, query: &str, window_tokens: usize) -> Option<(usize, usize, f32)> {
+        let scorer = self.scorer();
+        let query_terms = self.query_terms(query, &scorer);
+        let idfs: Vec<f32> = query_terms.iter().map(|(term, _)| scorer.idf(self.posting_len(term))).collect();
+
+        // (occurrence, index into query_terms), in document order
+        let mut occurrences: Vec<(&TermPosition, usize)> = query_terms
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (term, _))| Some((self.positions.get(term)?.get(&doc_id)?, i)))
+            .flat_map(|(positions, i)| positions.iter().map(move |position| (position, i)))
+            .collect();
+        occurrences.sort_unstable_by_key(|(position, _)| position.token);
+
+        let window = window_tokens.max(1) as u32;
+        let mut counts = vec![0u32; query_terms.len()];
+        let mut best: Option<(usize, usize, f32)> = None;
+        let mut end = 0;
+        for (start, (first, _)) in occurrences.iter().enumerate() {
+            while end < occurrences.len() && occurrences[end].0.token < first.token + window {
+                counts[occurrences[end].1] += 1;
+                end += 1;
+            }
+            let score: f32 = counts
+                .iter()
+                .zip(&query_terms)
+                .zip(&idfs)
+                .filter(|((&tf, _), _)| tf > 0)
+                .map(|((&tf, (_, weight)), &idf)| weight * scorer.score_term_with_idf(idf, tf as f32, window as f32))
+                .sum();
+            if best.is_none_or(|(_, _, best_score)| score > best_score) {
+                best = Some((first.start as usize, occurrences[end - 1].0.end as usize, score));
+            }
+            counts[occurrences[start].1] -= 1;
+        }
+        best
+    }
+
+    /// Add documents from JSON lines such as `{"id": 1, "text": "..."}`
+    ///
+    /// `id_field` must hold a u32 and `text_field` a string. A line that
+    /// isn't valid JSON or lacks either field is malformed: with
+    /// `skip_malformed` it is skipped, otherwise indexing stops with an
+    /// `InvalidData` error naming the line. Blank lines are ignored.
+    ///
+    /// Returns the number of documents indexed; documents rejected by
+    /// `add_document` are not counted.
+    pub fn index_jsonl(
+        &mut self,
+        reader: impl BufRead,
+        id_field: &str,
+        text_field: &str,
+        skip_malformed: bool,
+    ) -> io::Result<usize> {
+        let mut indexed = 0;
+        for (line_no, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record = serde_json::from_str::<serde_json::Value>(&line).ok().and_then(|value| {
+                let doc_id = u32::try_from(value.get(id_field)?.as_u64()?).ok()?;
+                let text = value.get(text_field)?.as_str()?.to_string();
+                Some((doc_id, text))
+            });
+            match record {
+                Some((doc_id, text)) => {
+                    if self.add_document(doc_id, &text) {
+                        indexed += 1;
+                    }
+                }
+                None if skip_malformed => {}
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("malformed JSONL record on line {}", line_no + 1),
+                    ))
+                }
+            }
+        }
+        Ok(indexed)
+    }
+
+    /// Build postings lists (call after adding all documents)
+    ///
+    /// Postings hold dense internal ids (each document's position in insertion
+    /// order) rather than doc_ids, so sparse or unordered doc_ids such as
+    /// database keys still delta-encode compactly. Search results always
+    /// report the original doc_ids.
+    ///
+    /// With the `parallel` feature, postings are compressed on rayon's global
+    /// thread pool.
+    pub fn build(&mut self) {
+        self.warm = None;
+        self.dirty_terms.clear();
+        self.sync_dense_ids();
+        #[cfg(feature = "parallel")]
+        {
+            self.build_parallel();
+        }
+        #[cfg(not(feature = "parallel"))]
+        for (term, doc_freqs) in &self.term_freqs {
+            self.postings.insert(term.clone(), build_postings(doc_freqs, &self.dense_ids, self.compress_postings));
+        }
+        self.rebuild_term_dict();
+    }
+
+    /// Whether postings are built and cover every document and term
+    ///
+    /// `false` before the first build and after documents are added,
+    /// appended to or removed until the next `build` or `build_incremental`.
+    /// An empty index is never built.
+    pub fn is_built(&self) -> bool {
+        !self.postings.is_empty() && self.dirty_terms.is_empty() && self.dense_synced == self.doc_metas.len()
+    }
+
+    /// Refresh the sorted term dictionary, if enabled
+    fn rebuild_term_dict(&mut self) {
+        self.term_dict.clear();
+        if self.sorted_term_dict {
+            self.term_dict.extend(self.term_freqs.keys().cloned());
+            self.term_dict.sort_unstable();
+        }
+    }
+
+    /// The sorted term dictionary, if enabled and covering every term
+    fn term_dict(&self) -> Option<&[String]> {
+        // Terms are only ever added between builds, so a matching count means none are new
+        (self.sorted_term_dict && self.term_dict.len() == self.term_freqs.len()).then_some(self.term_dict.as_slice())
+    }
+
+    /// Assign dense ids to documents added since the last sync
+    fn sync_dense_ids(&mut self) {
+        for (dense, meta) in self.doc_metas.iter().enumerate().skip(self.dense_synced) {
+            self.dense_ids.insert(meta.doc_id, dense as u32);
+        }
+        self.dense_synced = self.doc_metas.len();
+    }
+
+    /// Build compressed postings inside a specific rayon thread pool
+    #[cfg(feature = "parallel")]
+    pub fn build_in_pool(&mut self, pool: &rayon::ThreadPool) {
+        self.warm = None;
+        self.dirty_terms.clear();
+        self.sync_dense_ids();
+        pool.install(|| self.build_parallel());
+        self.rebuild_term_dict();
+    }
+
+    /// Compress postings in parallel on the current rayon pool
+    #[cfg(feature = "parallel")]
+    fn build_parallel(&mut self) {
+        use rayon::prelude::*;
+
+        let (compress, dense_ids) = (self.compress_postings, &self.dense_ids);
+        let terms: Vec<(&String, &HashMap<u32, u32>)> = self.term_freqs.iter().collect();
+        let compressed: Vec<(String, PostingsList)> = terms
+            .into_par_iter()
+            .map(|(term, doc_freqs)| (term.clone(), build_postings(doc_freqs, dense_ids, compress)))
+            .collect();
+        self.postings.extend(compressed);
+    }
+
+    /// Free the compressed postings, keeping the term frequencies
+    ///
+    /// Search reads the term frequencies and keeps working, so this saves
+    /// memory during mutation-heavy phases (e.g. after `save`). The index
+    /// reports unbuilt until the next `build` regenerates the postings.
+    pub fn drop_postings(&mut self) {
+        self.postings = AHashMap::new();
+        self.dirty_terms.clear();
+    }
+
+    /// Recompress only the postings of terms changed since the last build
+    ///
+    /// Documents added or appended to after a build mark their terms dirty.
+    /// Falls back to a full `build` if the index was never built.
+    pub fn build_incremental(&mut self) {
+        if self.postings.is_empty() {
+            self.build();
+            return;
+        }
+        self.warm = None;
+        self.sync_dense_ids();
+        for term in std::mem::take(&mut self.dirty_terms) {
+            if let Some(doc_freqs) = self.term_freqs.get(&term) {
+                self.postings.insert(term, build_postings(doc_freqs, &self.dense_ids, self.compress_postings));
+            }
+        }
+        self.rebuild_term_dict();
+    }
+
+    /// Build after dropping terms that occur in fewer than `min_df` documents
+    ///
+    /// Pruned terms are removed from both the term frequencies and the
+    /// postings and become unsearchable. Document lengths still count them.
+    pub fn build_pruned(&mut self, min_df: usize) {
+        let terms = self.term_freqs.len();
+        self.term_freqs.retain(|_, docs| docs.len() >= min_df);
+        if self.term_freqs.len() < terms {
+            *self.saved_stale.get_mut() |= *self.saved_docs.get_mut() > 0;
+        }
+        let term_freqs = &self.term_freqs;
+        self.postings.retain(|term, _| term_freqs.contains_key(term));
+        self.build();
+    }
+
+    /// Deterministically sample up to `n` doc_ids using a seeded RNG
+    ///
+    /// The same seed over the same index yields the same sample on every run
+    /// and platform.
+    pub fn sample_docs(&self, n: usize, seed: u64) -> Vec<u32> {
+        let mut doc_ids: Vec<u32> = self.doc_metas.iter().map(|m| m.doc_id).collect();
+        let n = n.min(doc_ids.len());
+        let mut rng = SplitMix64::new(seed);
+
+        // Partial Fisher-Yates shuffle
+        for i in 0..n {
+            let j = i + rng.next_below((doc_ids.len() - i) as u64) as usize;
+            doc_ids.swap(i, j);
+        }
+        doc_ids.truncate(n);
+        doc_ids
+    }
+
+    /// Whether a term exists in the index
+    ///
+    /// `term` is matched as-is, so pass an already-tokenized (lowercased) term.
+    pub fn contains_term(&self, term: &str) -> bool {
+        match self.term_dict() {
+            Some(dict) => dict.binary_search_by(|t| t.as_str().cmp(term)).is_ok(),
+            None => self.term_freqs.contains_key(term),
+        }
+    }
+
+    /// Id of an already-tokenized term
+    ///
+    /// With an imported vocabulary the id is the term's position in it,
+    /// whether or not this index contains the term, and terms outside it
+    /// have none. Otherwise it is the position in the sorted term
+    /// dictionary, and `None` if the term is unknown, the dictionary is
+    /// disabled, or terms were added since the last build.
+    pub fn term_id(&self, term: &str) -> Option<TermId> {
+        if !self.vocabulary.is_empty() {
+            return self.vocabulary_ids.get(term).copied();
+        }
+        let dict = self.term_dict()?;
+        dict.binary_search_by(|t| t.as_str().cmp(term)).ok().map(|i| i as TermId)
+    }
+
+    /// Terms in term id order, for sharing with other indexes
+    ///
+    /// The imported vocabulary if there is one, otherwise every term sorted.
+    /// A coordinator can merge the exports of several shards and pass the
+    /// result to `import_vocabulary` on each, so they all agree on ids.
+    pub fn export_vocabulary(&self) -> Vec<String> {
+        if !self.vocabulary.is_empty() {
+            return self.vocabulary.clone();
+        }
+        self.sorted_terms().into_iter().map(str::to_string).collect()
+    }
+
+    /// Fix term ids to positions in a shared vocabulary
+    ///
+    /// Replaces any earlier import; repeated terms keep their first
+    /// position. Indexing and scoring are unaffected. An empty list returns
+    /// to ids from the sorted term dictionary.
+    pub fn import_vocabulary(&mut self, terms: &[String]) {
+        self.vocabulary.clear();
+        self.vocabulary_ids.clear();
+        for term in terms {
+            if !self.vocabulary_ids.contains_key(term) {
+                self.vocabulary_ids.insert(term.clone(), self.vocabulary.len() as TermId);
+                self.vocabulary.push(term.clone());
+            }
+        }
+    }
+
+    /// All terms starting with `prefix`, in sorted order
+    ///
+    /// A range scan of the sorted term dictionary when available; otherwise
+    /// every term is checked.
+    pub fn terms_with_prefix(&self, prefix: &str) -> Vec<&str> {
+        match self.term_dict() {
+            Some(dict) => {
+                let start = dict.partition_point(|t| t.as_str() < prefix);
+                dict[start..]
+                    .iter()
+                    .take_while(|t| t.starts_with(prefix))
+                    .map(String::as_str)
+                    .collect()
+            }
+            None => {
+                let mut terms: Vec<&str> =
+                    self.term_freqs.keys().map(String::as_str).filter(|t| t.starts_with(prefix)).collect();
+                terms.sort_unstable();
+                terms
+            }
+        }
+    }
+
+    /// Whether any token of `text` exists in the index
+    pub fn contains_tokenized_query(&self, text: &str) -> bool {
+        self.tokenizer
+            .tokenize(text)
+            .iter()
+            .any(|token| self.contains_term(token))
+    }
+
+    /// Convert into a read-only index backed by the compressed postings
+    ///
+    /// Builds the postings first, then replaces the per-term hash maps with a
+    /// term frequency array aligned to each postings list.
+    pub fn freeze(mut self) -> FrozenBM25Index {
+        self.build();
+        let (term_freqs, doc_metas) = (self.term_freqs, &self.doc_metas);
+        let term_postings: Vec<(String, Vec<u8>, Vec<u32>)> = self
+            .postings
+            .into_iter()
+            .map(|(term, list)| {
+                let compressed = list.into_block_bytes();
+                let docs = &term_freqs[&term];
+                let freqs = decode_block_postings(&compressed)
+                    .iter()
+                    .map(|&dense| docs[&doc_metas[dense as usize].doc_id])
+                    .collect();
+                (term, compressed, freqs)
+            })
+            .collect();
+        FrozenBM25Index::from_parts(
+            self.tokenizer,
+            self.auto_language,
+            self.bigrams,
+            self.params,
+            term_postings.into_iter(),
+            self.doc_metas,
+            self.long_docs,
+            self.external_ids,
+            self.priors,
+            self.ranking,
+        )
+    }
+
+    /// Iterate over every term and its document frequency
+    ///
+    /// Order is unspecified but stable for a given index instance.
+    pub fn vocabulary(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.term_freqs
+            .iter()
+            .map(|(term, docs)| (term.as_str(), docs.len()))
+    }
+
+    /// Precompute IDF values, per-term score upper bounds, a sorted term
+    /// dictionary and a doc length lookup
+    ///
+    /// Call after `build` or `load` so the first queries run at steady-state
+    /// latency. Any later modification of the index drops the caches.
+    pub fn warm_up(&mut self) {
+        self.warm = None;
+        let scorer = self.scorer();
+        let mut idf = AHashMap::with_capacity(self.term_freqs.len());
+        let mut upper_bounds = AHashMap::with_capacity(self.term_freqs.len());
+        let doc_positions: AHashMap<u32, usize> =
+            self.doc_metas.iter().enumerate().map(|(i, m)| (m.doc_id, i)).collect();
+
+        for (term, docs) in &self.term_freqs {
+            let term_idf = scorer.idf(docs.len());
+            let upper_bound = docs
+                .iter()
+                .map(|(doc_id, &tf)| {
+                    let doc_len = doc_positions
+                        .get(doc_id)
+                        .map_or(1.0, |&i| self.doc_metas[i].doc_len(&self.long_docs) as f32);
+                    scorer.score_term_with_idf(term_idf, tf as f32, doc_len)
+                })
+                .fold(0.0f32, f32::max);
+            idf.insert(term.clone(), term_idf);
+            upper_bounds.insert(term.clone(), upper_bound);
+        }
+
+        let mut sorted_terms: Vec<String> = self.term_freqs.keys().cloned().collect();
+        sorted_terms.sort_unstable();
+
+        self.warm = Some(WarmCaches {
+            idf,
+            upper_bounds,
+            sorted_terms,
+            doc_positions,
+        });
+    }
+
+    /// Highest score a single document can get from `term` (0 if absent)
+    ///
+    /// Served from the `warm_up` cache when available.
+    pub fn term_upper_bound(&self, term: &str) -> f32 {
+        if let Some(&bound) = self.warm.as_ref().and_then(|warm| warm.upper_bounds.get(term)) {
+            return bound;
+        }
+        let Some(docs) = self.term_freqs.get(term) else {
+            return 0.0;
+        };
+        let scorer = self.scorer();
+        let idf = scorer.idf(docs.len());
+        docs.iter()
+            .map(|(&doc_id, &tf)| scorer.score_term_with_idf(idf, tf as f32, self.doc_len_or_default(doc_id)))
+            .fold(0.0f32, f32::max)
+    }
+
+    /// All terms in sorted order
+    ///
+    /// Served from the sorted term dictionary or the `warm_up` cache when
+    /// available.
+    pub fn sorted_terms(&self) -> Vec<&str> {
+        if let Some(dict) = self.term_dict() {
+            return dict.iter().map(String::as_str).collect();
+        }
+        match &self.warm {
+            Some(warm) => warm.sorted_terms.iter().map(String::as_str).collect(),
+            None => {
+                let mut terms: Vec<&str> = self.term_freqs.keys().map(String::as_str).collect();
+                terms.sort_unstable();
+                terms
+            }
+        }
+    }
+
+    /// Metadata for a document, if indexed
+    fn doc_meta(&self, doc_id: u32) -> Option<&DocMeta> {
+        match &self.warm {
+            Some(warm) => warm.doc_positions.get(&doc_id).map(|&i| &self.doc_metas[i]),
+            None => self.doc_metas.iter().find(|m| m.doc_id == doc_id),
+        }
+    }
+
+    /// Number of tokens in a document, if indexed
+    pub fn doc_len(&self, doc_id: u32) -> Option<u32> {
+        self.doc_meta(doc_id).map(|m| m.doc_len(&self.long_docs))
+    }
+
+    /// Length of a document for scoring, or 1.0 if it isn't indexed
+    fn doc_len_or_default(&self, doc_id: u32) -> f32 {
+        self.doc_len(doc_id).map_or(1.0, |len| len as f32)
+    }
+
+    /// Number of documents in a term's postings list (its document frequency)
+    ///
+    /// `term` is matched as-is, so pass an already-tokenized term.
+    pub fn posting_len(&self, term: &str) -> usize {
+        self.term_freqs.get(term).map_or(0, |docs| docs.len())
+    }
+
+    /// Live {doc_id: freq} map of an already-tokenized term, if indexed
+    pub(crate) fn term_doc_freqs(&self, term: &str) -> Option<&HashMap<u32, u32>> {
+        self.term_freqs.get(term)
+    }
+
+    /// Doc_ids of every document containing an already-tokenized term, ascending
+    ///
+    /// Decodes the compressed postings once built; terms not yet built (or
+    /// changed since the last build) are read from the term frequencies.
+    pub fn docs_for_term(&self, term: &str) -> Vec<u32> {
+        let mut doc_ids: Vec<u32> = match self.postings.get(term) {
+            Some(postings) if !self.dirty_terms.contains(term) => postings
+                .iter()
+                .map(|dense| self.doc_metas[dense as usize].doc_id)
+                .collect(),
+            _ => self.term_freqs.get(term).map(|docs| docs.keys().copied().collect()).unwrap_or_default(),
+        };
+        doc_ids.sort_unstable();
+        doc_ids
+    }
+
+    /// Doc_ids containing any of the already-tokenized terms, ascending and deduplicated
+    ///
+    /// Merges the terms' lists lazily as in `union_block_postings_len`, so the
+    /// union is never collected. Postings are ordered by insertion rather
+    /// than doc_id, so each term's own list is still decoded and sorted.
+    pub fn union_postings(&self, terms: &[&str]) -> impl Iterator<Item = u32> {
+        let mut lists: Vec<std::vec::IntoIter<u32>> =
+            terms.iter().map(|term| self.docs_for_term(term).into_iter()).collect();
+        let mut heads: Vec<Option<u32>> = lists.iter_mut().map(Iterator::next).collect();
+        std::iter::from_fn(move || {
+            let min = heads.iter().flatten().copied().min()?;
+            for (list, head) in lists.iter_mut().zip(heads.iter_mut()) {
+                if *head == Some(min) {
+                    *head = list.next();
+                }
+            }
+            Some(min)
+        })
+    }
+
+    /// Postings list lengths for several terms
+    pub fn posting_lens(&self, terms: &[&str]) -> HashMap<String, usize> {
+        terms
+            .iter()
+            .map(|&term| (term.to_string(), self.posting_len(term)))
+            .collect()
+    }
+
+    /// Doc_ids `search` would score for a query, ascending, without scoring them
+    ///
+    /// The union of the query terms' documents, capped like `search` when
+    /// `with_max_candidates` is set. Lets callers rank the candidates with
+    /// their own model.
+    pub fn candidates(&self, query: &str) -> Vec<u32> {
+        let query = self.analyze_query(query, &self.scorer());
+        let mut doc_ids: Vec<u32> = self.analyzed_candidates(&query).into_iter().map(|(doc_id, _)| doc_id).collect();
+        doc_ids.sort_unstable();
+        doc_ids
+    }
+
+    /// Number of documents matching at least one query term, without scoring
+    ///
+    /// Useful for query planning: a single-term query is answered from its
+    /// postings length alone. For several terms the union is counted exactly
+    /// by merging the built block postings, which avoids allocating a
+    /// candidate set but still decodes every list, so its cost grows with the
+    /// total postings length. If a rough estimate is enough, the sum of
+    /// `posting_lens` is an O(1) upper bound. Terms whose postings are missing
+    /// or stale (documents added since `build`) fall back to a hash set union.
+    pub fn candidate_count(&self, query: &str) -> usize {
+        let query_terms = self.tokenizer.tokenize_unique(query);
+        let matched: Vec<(&str, &HashMap<u32, u32>)> = query_terms
+            .iter()
+            .filter_map(|term| Some((term.as_str(), self.term_freqs.get(term)?)))
+            .collect();
+        if matched.len() <= 1 {
+            return matched.first().map_or(0, |(_, docs)| docs.len());
+        }
+
+        let lists: Option<Vec<&PostingsList>> = matched
+            .iter()
+            .map(|&(term, docs)| {
+                let list = self.postings.get(term)?;
+                (list.len() == docs.len()).then_some(list)
+            })
+            .collect();
+        match lists {
+            Some(lists) => union_len(lists.into_iter().map(PostingsList::iter).collect()),
+            None => matched
+                .iter()
+                .flat_map(|(_, docs)| docs.keys())
+                .collect::<AHashSet<_>>()
+                .len(),
+        }
+    }
+
+    /// Estimated fraction of the corpus that `search` would score for a query
+    ///
+    /// Computed from postings lengths alone as `1 - Π(1 - df / N)` over the
+    /// distinct query terms, i.e. assuming terms occur independently. Far
+    /// cheaper than `candidate_count`, but overestimates when query terms
+    /// tend to co-occur. Returns 0.0 for an empty index.
+    pub fn estimate_selectivity(&self, query: &str) -> f32 {
+        let num_docs = self.doc_metas.len();
+        if num_docs == 0 {
+            return 0.0;
+        }
+        let miss: f64 = self
+            .tokenizer
+            .tokenize_unique(query)
+            .iter()
+            .map(|term| 1.0 - self.posting_len(term) as f64 / num_docs as f64)
+            .product();
+        (1.0 - miss) as f32
+    }
+
+    /// IDF of every term in the vocabulary, sorted by term
+    ///
+    /// The batch form of `query_idfs`, e.g. for exporting features to
+    /// another model. Read from the `warm_up` cache when it is current.
+    pub fn all_idfs(&self) -> Vec<(String, f32)> {
+        let scorer = self.scorer();
+        let mut idfs: Vec<(String, f32)> = self
+            .term_freqs
+            .iter()
+            .map(|(term, docs)| {
+                let idf = match self.warm.as_ref().and_then(|warm| warm.idf.get(term)) {
+                    Some(&idf) => idf,
+                    None => scorer.idf(docs.len()),
+                };
+                (term.clone(), idf)
+            })
+            .collect();
+        idfs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        idfs
+    }
+
+    /// IDF of each distinct query term, in query order
+    ///
+    /// Out-of-vocabulary terms get 0.0. Uses the same IDF variant and
+    /// document count as scoring.
+    pub fn query_idfs(&self, query: &str) -> Vec<(String, f32)> {
+        let scorer = self.scorer();
+        let mut seen = AHashSet::new();
+        self.tokenizer
+            .tokenize(query)
+            .into_iter()
+            .filter(|term| seen.insert(term.clone()))
+            .map(|term| {
+                let idf = match self.posting_len(&term) {
+                    0 => 0.0,
+                    doc_freq => scorer.idf(doc_freq),
+                };
+                (term, idf)
+            })
+            .collect()
+    }
+
+    /// Search the index
+    pub fn search(&self, query: &str, k: usize) -> Vec<SearchResult> {
+        self.search_multi(&[(query, 1.0)], k)
+    }
+
+    /// Search with scores mapped to probabilities by a logistic function
+    ///
+    /// Each BM25 score becomes `1 / (1 + exp(-(a * score + b)))` for
+    /// `sigmoid_params = (a, b)`, e.g. fitted by Platt scaling on labelled
+    /// queries. With `a > 0` the mapping is monotonic, so order and ranks are
+    /// those of `search`.
+    pub fn search_calibrated(&self, query: &str, k: usize, sigmoid_params: (f32, f32)) -> Vec<SearchResult> {
+        let (a, b) = sigmoid_params;
+        let mut results = self.search(query, k);
+        for result in &mut results {
+            result.score = 1.0 / (1.0 + (-(a * result.score + b)).exp());
+        }
+        results
+    }
+
+    /// Search returning columnar results without per-result allocations
+    ///
+    /// Same order and scores as `search`, for FFI consumers that only need
+    /// doc_ids and scores.
+    pub fn search_soa(&self, query: &str, k: usize) -> SearchResultsSoA {
+        let mut scores: Vec<(u32, f32)> = self.score_candidates(&[(query, 1.0)]).into_iter().collect();
+        sort_scores(&mut scores, self.ranking.score_epsilon);
+        scores.truncate(k);
+        let (doc_ids, scores) = scores.into_iter().unzip();
+        SearchResultsSoA { doc_ids, scores }
+    }
+
+    /// Search building each result with `f(doc_id, score, rank)`
+    ///
+    /// Same order, scores and ranks as `search`, but results go straight
+    /// into the caller's type (e.g. a protobuf message) and no `chunk_id`
+    /// string is allocated.
+    pub fn search_into<T>(&self, query: &str, k: usize, f: impl Fn(u32, f32, usize) -> T) -> Vec<T> {
+        let mut scores: Vec<(u32, f32)> = self.score_candidates(&[(query, 1.0)]).into_iter().collect();
+        sort_scores(&mut scores, self.ranking.score_epsilon);
+        scores
+            .into_iter()
+            .take(k)
+            .enumerate()
+            .map(|(rank, (doc_id, score))| f(doc_id, score, self.ranking.rank_base + rank))
+            .collect()
+    }
+
+    /// Search returning each result's payload alongside it
+    ///
+    /// Same order and scores as `search`; documents added without a
+    /// payload get `None`.
+    pub fn search_with_payload(&self, query: &str, k: usize) -> Vec<SearchResultWithPayload> {
+        self.search(query, k)
+            .into_iter()
+            .map(|result| SearchResultWithPayload {
+                payload: self.payloads.get(&result.doc_id).cloned(),
+                doc_id: result.doc_id,
+                chunk_id: result.chunk_id,
+                score: result.score,
+                rank: result.rank,
+            })
+            .collect()
+    }
+
+    /// Search returning at most `per_group_max` results from any one group
+    ///
+    /// Similar to field collapsing: lower-scoring documents from a group that
+    /// is already full are skipped. Ungrouped documents are never capped.
+    pub fn search_grouped(&self, query: &str, k: usize, per_group_max: usize) -> Vec<SearchResult> {
+        let mut scores: Vec<(u32, f32)> = self.score_candidates(&[(query, 1.0)]).into_iter().collect();
+        sort_scores(&mut scores, self.ranking.score_epsilon);
+
+        let mut group_counts: AHashMap<&str, usize> = AHashMap::new();
+        let kept = scores
+            .into_iter()
+            .filter(|(doc_id, _)| match self.groups.get(doc_id) {
+                Some(group) => {
+                    let count = group_counts.entry(group.as_str()).or_insert(0);
+                    *count += 1;
+                    *count <= per_group_max
+                }
+                None => true,
+            })
+            .take(k)
+            .collect();
+
+        self.rank(kept, k)
+    }
+
+    /// Return every candidate scoring at or above the given score percentile
+    ///
+    /// `percentile` is in `[0, 1]`: `0.9` keeps the top-decile-scoring
+    /// candidates and `0.0` keeps all of them. The cutoff is the score at
+    /// position `floor(percentile * n)` of the ascending candidate scores, and
+    /// documents tied with it are included.
+    pub fn search_top_percentile(&self, query: &str, percentile: f32) -> Vec<SearchResult> {
+        let scores: Vec<(u32, f32)> = self.score_candidates(&[(query, 1.0)]).into_iter().collect();
+        if scores.is_empty() {
+            return Vec::new();
+        }
+
+        let mut ascending: Vec<f32> = scores.iter().map(|&(_, score)| score).collect();
+        ascending.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let position = (percentile.clamp(0.0, 1.0) * ascending.len() as f32) as usize;
+        let cutoff = ascending[position.min(ascending.len() - 1)];
+
+        let kept: Vec<(u32, f32)> = scores.into_iter().filter(|&(_, score)| score >= cutoff).collect();
+        let k = kept.len();
+        self.rank(kept, k)
+    }
+
+    /// Search keeping only documents that match at least `min_should_match`
+    /// distinct query terms
+    ///
+    /// `1` behaves like the default OR search; the number of distinct query
+    /// terms behaves like AND.
+    pub fn search_msm(&self, query: &str, k: usize, min_should_match: usize) -> Vec<SearchResult> {
+        let scorer = self.scorer();
+        let query = self.analyze_query(query, &scorer);
+
+        let mut scores = Vec::new();
+        for (language, query_terms) in &query.analyses {
+            // Count matched distinct terms per candidate
+            let mut matched: AHashMap<u32, usize> = AHashMap::new();
+            for (term, _) in query_terms {
+                if let Some(term_docs) = self.term_freqs.get(term) {
+                    for &doc_id in term_docs.keys() {
+                        *matched.entry(doc_id).or_insert(0) += 1;
+                    }
+                }
+            }
+            scores.extend(
+                matched
+                    .into_iter()
+                    .filter(|&(doc_id, count)| {
+                        count >= min_should_match && query.applies(*language, || self.doc_language(doc_id))
+                    })
+                    .map(|(doc_id, _)| (doc_id, self.score_document(doc_id, query_terms, &scorer))),
+            );
+        }
+        self.rank(scores, k)
+    }
+
+    /// Search with `-word` excluding every document that contains the word
+    ///
+    /// Whitespace-separated words starting with `-` are tokenized and any
+    /// document containing one of their terms is dropped; the other words
+    /// form the query and are scored as in `search`. A lone `-` is ignored.
+    /// `search` itself treats `-` like any other character.
+    pub fn search_boolean(&self, query: &str, k: usize) -> Vec<SearchResult> {
+        let (excluded, included): (Vec<&str>, Vec<&str>) =
+            query.split_whitespace().partition(|word| word.starts_with('-'));
+        let excluded: Vec<&str> = excluded.iter().map(|word| &word[1..]).collect();
+
+        let scorer = self.scorer();
+        let excluded = self.analyze_query(&excluded.join(" "), &scorer);
+        let query = self.analyze_query(&included.join(" "), &scorer);
+        let scores = self
+            .analyzed_candidates(&query)
+            .into_iter()
+            .filter(|&(doc_id, _)| {
+                !excluded
+                    .terms_for(|| self.doc_language(doc_id))
+                    .iter()
+                    .any(|(term, _)| self.term_freqs.get(term).is_some_and(|docs| docs.contains_key(&doc_id)))
+            })
+            .map(|(doc_id, terms)| (doc_id, self.score_document(doc_id, terms, &scorer)))
+            .collect();
+        self.rank(scores, k)
+    }
+
+    /// Search with a Lucene-style boolean query of `Must`/`Should`/`MustNot` clauses
+    ///
+    /// Documents must contain every `Must` term and no `MustNot` term. With
+    /// no `Must` clause, they must contain at least one `Should` term, so a
+    /// query of only `MustNot` clauses matches nothing. Scores sum the BM25
+    /// scores of the distinct `Must` and `Should` terms, with priors
+    /// applied as in `search`. A term repeated across or within clauses is
+    /// weighted by the query tf mode like a repeated `search` term, times
+    /// the largest boost among its clauses.
+    pub fn search_structured(&self, terms: &[QueryTerm], k: usize) -> Vec<SearchResult> {
+        let (mut must, mut must_not, mut tokens) = (Vec::new(), Vec::new(), Vec::new());
+        let mut boosts: AHashMap<String, f32> = AHashMap::new();
+        for clause in terms {
+            for token in self.tokenizer.tokenize(&clause.text) {
+                match clause.occur {
+                    Occur::MustNot => {
+                        must_not.push(token);
+                        continue;
+                    }
+                    Occur::Must => must.push(token.clone()),
+                    Occur::Should => {}
+                }
+                let boost = boosts.entry(token.clone()).or_insert(clause.boost);
+                *boost = boost.max(clause.boost);
+                tokens.push(token);
+            }
+        }
+        let scorer = self.scorer();
+        let mut query_terms = weight_query_tokens(tokens, &scorer);
+        for (term, weight) in &mut query_terms {
+            *weight *= boosts[term.as_str()];
+        }
+
+        let contains = |term: &str, doc_id: u32| self.term_freqs.get(term).is_some_and(|docs| docs.contains_key(&doc_id));
+        let candidates: Vec<u32> = match must.iter().min_by_key(|term| self.posting_len(term)) {
+            // Check the other required terms against the rarest one's documents
+            Some(rarest) => self.term_freqs.get(rarest).map_or_else(Vec::new, |docs| {
+                docs.keys()
+                    .copied()
+                    .filter(|&doc_id| must.iter().all(|term| contains(term, doc_id)))
+                    .collect()
+            }),
+            None => self.collect_candidates(&query_terms).into_iter().collect(),
+        };
+
+        let scores = candidates
+            .into_iter()
+            .filter(|&doc_id| !must_not.iter().any(|term| contains(term, doc_id)))
+            .map(|doc_id| (doc_id, self.score_document(doc_id, &query_terms, &scorer)))
+            .collect();
+        self.rank(scores, k)
+    }
+
+    /// Run many searches at once, returning results in query order
+    ///
+    /// With the `parallel` feature, queries are searched on rayon's global
+    /// thread pool.
+    pub fn search_batch(&self, queries: &[&str], k: usize) -> Vec<Vec<SearchResult>> {
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            queries.par_iter().map(|query| self.search(query, k)).collect()
+        }
+        #[cfg(not(feature = "parallel"))]
+        queries.iter().map(|query| self.search(query, k)).collect()
+    }
+
+    /// Search ranking only documents in the `allowed` set
+    ///
+    /// Disallowed candidates are dropped before top-k selection, so up to `k`
+    /// permitted results are returned.
+    pub fn search_within(&self, query: &str, k: usize, allowed: &AHashSet<u32>) -> Vec<SearchResult> {
+        let scorer = self.scorer();
+        let query = self.analyze_query(query, &scorer);
+        let scores = self
+            .analyzed_candidates(&query)
+            .into_iter()
+            .filter(|(doc_id, _)| allowed.contains(doc_id))
+            .map(|(doc_id, terms)| (doc_id, self.score_document(doc_id, terms, &scorer)))
+            .collect();
+        self.rank(scores, k)
+    }
+
+    /// Search this shard using corpus-wide document frequencies
+    ///
+    /// IDF comes from `global` so scores are comparable across shards; term
+    /// frequencies, document lengths and the average length stay local. Terms
+    /// missing from `global` fall back to this shard's document frequency.
+    pub fn search_with_global_stats(&self, query: &str, k: usize, global: &GlobalStats) -> Vec<SearchResult> {
+        let local = self.scorer();
+        let scorer = BM25Scorer::new(self.params, local.avg_doc_len, global.doc_count.max(self.doc_metas.len()));
+        let query = self.analyze_query(query, &scorer);
+        self.search_with_scorer(&query, k, &scorer, |term| {
+            let local_df = self.term_freqs.get(term).map_or(0, |docs| docs.len());
+            scorer.idf(global.df.get(term).copied().unwrap_or(local_df))
+        })
+    }
+
+    /// Search with BM25 parameters that override the index's for this query only
+    ///
+    /// IDF is recomputed with `params.idf` rather than read from the warm-up
+    /// cache, and a custom scorer set with `with_scorer` is bypassed.
+    pub fn search_with_params(&self, query: &str, k: usize, params: BM25Params) -> Vec<SearchResult> {
+        let scorer = BM25Scorer::new(params, self.scorer().avg_doc_len, self.doc_metas.len());
+        let query = self.analyze_query(query, &scorer);
+        self.search_with_scorer(&query, k, &scorer, |term| scorer.idf(self.posting_len(term)))
+    }
+
+    /// Rank candidates with a given BM25 scorer and query-term IDFs
+    fn search_with_scorer(
+        &self,
+        query: &AnalyzedQuery,
+        k: usize,
+        scorer: &BM25Scorer,
+        idf: impl Fn(&str) -> f32,
+    ) -> Vec<SearchResult> {
+        let idfs: AHashMap<&str, f32> = query
+            .analyses
+            .iter()
+            .flat_map(|(_, terms)| terms)
+            .map(|(term, _)| (term.as_str(), idf(term)))
+            .collect();
+        let scores = self
+            .analyzed_candidates(query)
+            .into_iter()
+            .map(|(doc_id, query_terms)| {
+                let doc_len = self.doc_len_or_default(doc_id);
+                let score = query_terms
+                    .iter()
+                    .filter_map(|(term, weight)| {
+                        let term_freq = *self.term_freqs.get(term)?.get(&doc_id)?;
+                        Some(weight * scorer.score_term_with_idf(idfs[term.as_str()], term_freq as f32, doc_len))
+                    })
+                    .sum::<f32>()
+                    * self.exact_match_factor(doc_id, query_terms, scorer.params.exact_match_boost);
+                (doc_id, scorer.params.prior_mode.apply(score, self.priors.get(&doc_id).copied()))
+            })
+            .collect();
+        self.rank(scores, k)
+    }
+
+    /// This shard's contribution to corpus-wide statistics
+    ///
+    /// Merge the stats of every shard with `GlobalStats::merge` and pass the
+    /// result to `search_with_global_stats`.
+    pub fn global_stats(&self) -> GlobalStats {
+        GlobalStats {
+            doc_count: self.doc_metas.len(),
+            df: self.term_freqs.iter().map(|(term, docs)| (term.clone(), docs.len())).collect(),
+        }
+    }
+
+    /// Rank documents by set overlap with the query instead of BM25
+    ///
+    /// Query and documents are treated as sets of unique tokens, which suits
+    /// very short texts such as titles or tags. Candidates still come from
+    /// the inverted index, so only documents sharing a term are returned.
+    pub fn search_set_similarity(&self, query: &str, k: usize, metric: SetSimilarity) -> Vec<SearchResult> {
+        let query_terms = self.tokenizer.tokenize_unique(query);
+
+        let mut intersections: AHashMap<u32, usize> = AHashMap::new();
+        for term in &query_terms {
+            if let Some(term_docs) = self.term_freqs.get(term) {
+                for &doc_id in term_docs.keys() {
+                    *intersections.entry(doc_id).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let scores = intersections
+            .into_iter()
+            .map(|(doc_id, intersection)| {
+                let doc_size = self.doc_meta(doc_id).map_or(0, |m| m.unique_terms(&self.long_docs) as usize);
+                (doc_id, metric.score(intersection, query_terms.len(), doc_size))
+            })
+            .collect();
+        self.rank(scores, k)
+    }
+
+    /// Search only documents containing the query tokens in order
+    ///
+    /// Up to `slop` other tokens may sit between the phrase's tokens in
+    /// total; a slop of 0 requires them to be consecutive. Matches are ranked
+    /// by BM25. Uses the positional index, so only documents added with
+    /// `store_text` can match.
+    pub fn search_phrase(&self, query: &str, k: usize, slop: u32) -> Vec<SearchResult> {
+        let phrase = self.tokenizer.tokenize(query);
+        let scorer = self.scorer();
+        let query = self.analyze_query(query, &scorer);
+        let scores = self
+            .analyzed_candidates(&query)
+            .into_iter()
+            .filter(|&(doc_id, _)| self.contains_phrase(doc_id, &phrase, slop))
+            .map(|(doc_id, terms)| (doc_id, self.score_document(doc_id, terms, &scorer)))
+            .collect();
+        self.rank(scores, k)
+    }
+
+    /// Whether the terms occur in order with at most `slop` tokens between them
+    fn contains_phrase(&self, doc_id: u32, phrase: &[String], slop: u32) -> bool {
+        let lists: Option<Vec<&Vec<TermPosition>>> = phrase
+            .iter()
+            .map(|term| self.positions.get(term)?.get(&doc_id))
+            .collect();
+        let Some((first, rest)) = lists.as_deref().and_then(<[_]>::split_first) else {
+            return false;
+        };
+        // Taking each term's earliest following occurrence leaves the least slop used
+        first.iter().any(|start| {
+            let mut pos = start.token;
+            rest.iter().zip(1..).all(|(positions, offset)| {
+                let next = positions.partition_point(|p| p.token <= pos);
+                match positions.get(next) {
+                    Some(p) if p.token - start.token - offset <= slop => {
+                        pos = p.token;
+                        true
+                    }
+                    _ => false,
+                }
+            })
+        })
+    }
+
+    /// Search with a bonus for query terms occurring near each other
+    ///
+    /// Each pair of distinct query terms found within `window` tokens of
+    /// each other adds `min(idf_a, idf_b) * (window + 1 - distance) / (window + 1)`
+    /// on top of the BM25 score, so closer pairs earn more. Adjacency is not
+    /// required. Uses the positional index, so only documents added with
+    /// `store_text` can earn the bonus; others get plain BM25 scores.
+    pub fn search_proximity(&self, query: &str, k: usize, window: usize) -> Vec<SearchResult> {
+        let scorer = self.scorer();
+        // Positions hold plain tokens, so the bonus looks up the plain query terms
+        let plain_terms = self.query_terms(query, &scorer);
+        let query = self.analyze_query(query, &scorer);
+        let scores = self
+            .analyzed_candidates(&query)
+            .into_iter()
+            .map(|(doc_id, terms)| {
+                let score = self.score_document(doc_id, terms, &scorer)
+                    + self.proximity_bonus(doc_id, &plain_terms, window, &scorer);
+                (doc_id, score)
+            })
+            .collect();
+        self.rank(scores, k)
+    }
+
+    /// Proximity bonus for a document, summed over pairs of query terms
+    fn proximity_bonus(&self, doc_id: u32, query_terms: &[(String, f32)], window: usize, scorer: &BM25Scorer) -> f32 {
+        let present: Vec<(&[TermPosition], f32)> = query_terms
+            .iter()
+            .filter_map(|(term, _)| {
+                let positions = self.positions.get(term)?.get(&doc_id)?;
+                Some((positions.as_slice(), scorer.idf(self.posting_len(term))))
+            })
+            .collect();
+
+        let mut bonus = 0.0;
+        for (i, &(a, idf_a)) in present.iter().enumerate() {
+            for &(b, idf_b) in &present[i + 1..] {
+                let distance = min_token_distance(a, b);
+                if distance <= window {
+                    bonus += idf_a.min(idf_b) * (window + 1 - distance) as f32 / (window + 1) as f32;
+                }
+            }
+        }
+        bonus
+    }
+
+    /// Per-term BM25 contributions for the top-k results, for rerankers
+    ///
+    /// Each vector has one entry per distinct query term, in query order,
+    /// holding that term's weighted contribution (0.0 if the document lacks
+    /// it). Results are in `search` order; without document priors each
+    /// vector sums to the result's score. With `auto_language` the terms are
+    /// the query as analyzed in each document's language.
+    pub fn candidate_features(&self, query: &str, k: usize) -> Vec<(u32, Vec<f32>)> {
+        let scorer = self.scorer();
+        let analyzed = self.analyze_query(query, &scorer);
+        self.search(query, k)
+            .into_iter()
+            .map(|result| {
+                let doc_len = self.doc_len_or_default(result.doc_id);
+                let features = analyzed
+                    .terms_for(|| self.doc_language(result.doc_id))
+                    .iter()
+                    .map(|(term, weight)| weight * self.term_score(result.doc_id, term, doc_len, &scorer))
+                    .collect();
+                (result.doc_id, features)
+            })
+            .collect()
+    }
+
+    /// Score of the k-th best result, or `None` if fewer than `k` documents match
+    ///
+    /// Equals `search(query, k).last().score` but only partially orders the
+    /// candidate scores, for setting a pruning threshold on a follow-up query.
+    pub fn kth_score(&self, query: &str, k: usize) -> Option<f32> {
+        let mut scores: Vec<f32> = self.score_candidates(&[(query, 1.0)]).into_values().collect();
+        if k == 0 || scores.len() < k {
+            return None;
+        }
+        let (_, kth, _) = scores.select_nth_unstable_by(k - 1, |a, b| b.total_cmp(a));
+        Some(*kth)
+    }
+
+    /// Rank candidates by how many distinct query terms they contain
+    ///
+    /// Skips BM25 entirely: no tf, idf or length normalization, just one pass
+    /// over each query term's documents. Much cheaper than `search` but a
+    /// coarse ordering, for "probably relevant" results under heavy load.
+    /// The reported score is the matched term count; ties go to the lower
+    /// doc_id.
+    pub fn search_fast(&self, query: &str, k: usize) -> Vec<SearchResult> {
+        let mut matched: AHashMap<u32, f32> = AHashMap::new();
+        for term in self.tokenizer.tokenize_unique(query) {
+            if let Some(term_docs) = self.term_freqs.get(&term) {
+                for &doc_id in term_docs.keys() {
+                    *matched.entry(doc_id).or_insert(0.0) += 1.0;
+                }
+            }
+        }
+        self.rank(matched.into_iter().collect(), k)
+    }
+
+    /// Score of one document for a query, as `search` would report it
+    ///
+    /// Returns `None` if the document isn't indexed; a document sharing no
+    /// terms with the query scores 0.0.
+    pub fn score_pair(&self, query: &str, doc_id: u32) -> Option<f32> {
+        let language = self.doc_meta(doc_id)?.language;
+        let scorer = self.scorer();
+        let query = self.analyze_query(query, &scorer);
+        Some(self.score_document(doc_id, query.terms_for(|| language), &scorer))
+    }
+
+    /// BM25 similarity of document `b` to document `a` used as a query
+    ///
+    /// Each of `a`'s terms is weighted by its frequency in `a` and scored
+    /// against `b` as in `search`, without priors or the exact-match boost.
+    /// The measure is asymmetric. Returns 0.0 if either document isn't
+    /// indexed. Finding `a`'s terms scans the vocabulary (see `document_terms`).
+    pub fn doc_similarity(&self, a: u32, b: u32) -> f32 {
+        if self.doc_meta(a).is_none() || self.doc_meta(b).is_none() {
+            return 0.0;
+        }
+        self.terms_similarity(&self.document_terms(a), b, &self.scorer())
+    }
+
+    /// `doc_similarity` of `b` to a document given by its `document_terms`
+    fn terms_similarity(&self, terms: &[(String, u32)], b: u32, scorer: &BM25Scorer) -> f32 {
+        let doc_len = self.doc_len_or_default(b);
+        terms
+            .iter()
+            .map(|(term, tf)| *tf as f32 * self.term_score(b, term, doc_len, scorer))
+            .sum()
+    }
+
+    /// Search reranked for diversity with Maximal Marginal Relevance
+    ///
+    /// Starting from the top BM25 result, repeatedly picks the candidate
+    /// maximizing `lambda * relevance - (1 - lambda) * redundancy`, where
+    /// redundancy is the candidate's highest `doc_similarity` to an already
+    /// picked result, relative to its similarity to itself and scaled to the
+    /// top score. Only the best `4 * k` BM25 results are reranked. Results
+    /// keep their BM25 scores, ranked in pick order; `lambda = 1` matches
+    /// `search`.
+    pub fn search_mmr(&self, query: &str, k: usize, lambda: f32) -> Vec<SearchResult> {
+        let mut pool: Vec<(u32, f32)> = self.score_candidates(&[(query, 1.0)]).into_iter().collect();
+        sort_scores(&mut pool, self.ranking.score_epsilon);
+        pool.truncate(k.saturating_mul(MMR_POOL_FACTOR));
+        let max_score = pool.first().map_or(0.0, |&(_, score)| score);
+
+        let scorer = self.scorer();
+        let profiles: Vec<(Vec<(String, u32)>, f32)> = pool
+            .iter()
+            .map(|&(doc_id, _)| {
+                let terms = self.document_terms(doc_id);
+                let self_similarity = self.terms_similarity(&terms, doc_id, &scorer);
+                (terms, self_similarity)
+            })
+            .collect();
+
+        let mut redundancy = vec![0.0f32; pool.len()];
+        let mut remaining: Vec<usize> = (0..pool.len()).collect();
+        let mut picked = Vec::with_capacity(k.min(pool.len()));
+        while picked.len() < k && !remaining.is_empty() {
+            // Pool order breaks near-ties, as in `search`
+            let mut best = (0, f32::NEG_INFINITY);
+            for (pos, &i) in remaining.iter().enumerate() {
+                let mmr = lambda * pool[i].1 - (1.0 - lambda) * max_score * redundancy[i];
+                if mmr > best.1 + self.ranking.score_epsilon {
+                    best = (pos, mmr);
+                }
+            }
+            let chosen = remaining.remove(best.0);
+            picked.push(chosen);
+            for &i in &remaining {
+                let (terms, self_similarity) = &profiles[i];
+                if *self_similarity > 0.0 {
+                    let similarity = self.terms_similarity(terms, pool[chosen].0, &scorer) / self_similarity;
+                    redundancy[i] = redundancy[i].max(similarity);
+                }
+            }
+        }
+
+        picked
+            .into_iter()
+            .enumerate()
+            .map(|(rank, i)| SearchResult {
+                doc_id: pool[i].0,
+                chunk_id: self.chunk_id(pool[i].0),
+                score: pool[i].1,
+                rank: self.ranking.rank_base + rank,
+            })
+            .collect()
+    }
+
+    /// A document's bag of words: each indexed term with its count, sorted by term
+    ///
+    /// Reconstructed by scanning every term's frequencies, since the index
+    /// keeps no per-document term list, so this is O(vocabulary). Meant for
+    /// offline feature extraction rather than the query path. Empty for an
+    /// unknown document; terms removed by `build_pruned` are missing.
+    pub fn document_terms(&self, doc_id: u32) -> Vec<(String, u32)> {
+        let mut terms: Vec<(String, u32)> = self
+            .term_freqs
+            .iter()
+            .filter_map(|(term, docs)| Some((term.clone(), *docs.get(&doc_id)?)))
+            .collect();
+        terms.sort_unstable();
+        terms
+    }
+
+    /// Search with several weighted queries, summing per-document scores
+    ///
+    /// Each query is scored independently and its contribution is multiplied
+    /// by its weight, so a document matched by multiple variants accumulates
+    /// score across all of them.
+    pub fn search_multi(&self, queries: &[(&str, f32)], k: usize) -> Vec<SearchResult> {
+        let scores = self.score_candidates(queries);
+        self.rank(scores.into_iter().collect(), k)
+    }
+
+    /// Score every document matched by any of the weighted queries
+    pub(crate) fn score_candidates(&self, queries: &[(&str, f32)]) -> AHashMap<u32, f32> {
+        let scorer = self.scorer();
+        let mut scores: AHashMap<u32, f32> = AHashMap::new();
+
+        for &(query, weight) in queries {
+            let query = self.analyze_query(query, &scorer);
+            for (doc_id, query_terms) in self.analyzed_candidates(&query) {
+                let score = self.score_document(doc_id, query_terms, &scorer);
+                *scores.entry(doc_id).or_insert(0.0) += weight * score;
+            }
+        }
+
+        scores
+    }
+
+    /// Language a document was analyzed in, if detected
+    pub fn doc_language(&self, doc_id: u32) -> Option<Language> {
+        self.doc_meta(doc_id)?.language
+    }
+
+    /// Search while recording how long each phase takes
+    ///
+    /// Diagnostic variant of `search`; the regular search path
+    /// does not pay for the timing calls.
+    pub fn search_timed(&self, query: &str, k: usize) -> (Vec<SearchResult>, SearchTimings) {
+        let start = Instant::now();
+        let scorer = self.scorer();
+        let query = self.analyze_query(query, &scorer);
+        let tokenized = Instant::now();
+
+        let candidates = self.analyzed_candidates(&query);
+        let collected = Instant::now();
+
+        let scores: Vec<(u32, f32)> = candidates
+            .into_iter()
+            .map(|(doc_id, terms)| (doc_id, self.score_document(doc_id, terms, &scorer)))
+            .collect();
+        let scored = Instant::now();
+
+        let results = self.rank(scores, k);
+        let sorted = Instant::now();
+
+        let timings = SearchTimings {
+            tokenize: tokenized - start,
+            candidates: collected - tokenized,
+            scoring: scored - collected,
+            sort: sorted - scored,
+            total: sorted - start,
+        };
+        (results, timings)
+    }
+
+    /// Search within a time budget, returning the best results found so far
+    ///
+    /// The clock is checked every 256 scored candidates; once `budget` has
+    /// passed, scoring stops and the top-k of the documents scored so far is
+    /// returned with the flag set to `true` (partial). Candidates are scored
+    /// in no particular order, so a partial result may miss better
+    /// documents. Complete results match `search`.
+    pub fn search_deadline(&self, query: &str, k: usize, budget: Duration) -> (Vec<SearchResult>, bool) {
+        let start = Instant::now();
+        let scorer = self.scorer();
+        let query = self.analyze_query(query, &scorer);
+
+        let mut partial = false;
+        let mut scores: Vec<(u32, f32)> = Vec::new();
+        for (i, (doc_id, terms)) in self.analyzed_candidates(&query).into_iter().enumerate() {
+            if i > 0 && i % DEADLINE_CHECK_INTERVAL == 0 && start.elapsed() >= budget {
+                partial = true;
+                break;
+            }
+            scores.push((doc_id, self.score_document(doc_id, terms, &scorer)));
+        }
+        (self.rank(scores, k), partial)
+    }
+
+    /// Measure search latency over a query log
+    ///
+    /// Runs every query `iterations` times and aggregates the individual
+    /// latencies; percentiles use the nearest-rank method. An empty query
+    /// list or zero iterations yields an all-zero report.
+    pub fn benchmark(&self, queries: &[&str], k: usize, iterations: usize) -> BenchReport {
+        let mut latencies: Vec<Duration> = Vec::with_capacity(queries.len() * iterations);
+        for _ in 0..iterations {
+            for query in queries {
+                let start = Instant::now();
+                std::hint::black_box(self.search(query, k));
+                latencies.push(start.elapsed());
+            }
+        }
+        if latencies.is_empty() {
+            return BenchReport::default();
+        }
+
+        latencies.sort_unstable();
+        let percentile = |p: f64| {
+            let rank = (p * latencies.len() as f64).ceil() as usize;
+            latencies[rank.clamp(1, latencies.len()) - 1]
+        };
+        BenchReport {
+            mean: latencies.iter().sum::<Duration>() / latencies.len() as u32,
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+        }
+    }
+
+    /// Collect every document containing at least one query term
+    ///
+    /// With `max_candidates` set, terms are visited rarest first and
+    /// collection stops at the cap.
+    fn collect_candidates(&self, query_terms: &[(String, f32)]) -> AHashSet<u32> {
+        let mut candidates = AHashSet::new();
+        let Some(max_candidates) = self.max_candidates else {
+            for (term, _) in query_terms {
+                if let Some(term_docs) = self.term_freqs.get(term) {
+                    candidates.extend(term_docs.keys().copied());
+                }
+            }
+            return candidates;
+        };
+
+        let mut term_docs: Vec<&HashMap<u32, u32>> =
+            query_terms.iter().filter_map(|(term, _)| self.term_freqs.get(term)).collect();
+        term_docs.sort_by_key(|docs| docs.len());
+        for docs in term_docs {
+            for &doc_id in docs.keys() {
+                if candidates.len() >= max_candidates {
+                    return candidates;
+                }
+                candidates.insert(doc_id);
+            }
+        }
+        candidates
+    }
+
+    /// Analyze a query per document language, as `auto_language` indexed them
+    fn analyze_query(&self, query: &str, scorer: &BM25Scorer) -> AnalyzedQuery {
+        AnalyzedQuery::new(query, &self.tokenizer, self.auto_language, self.bigrams, scorer)
+    }
+
+    /// Every document matching a query term, with the terms to score it by
+    fn analyzed_candidates<'q>(&self, query: &'q AnalyzedQuery) -> Vec<(u32, &'q [(String, f32)])> {
+        let mut candidates = Vec::new();
+        for (language, terms) in &query.analyses {
+            for doc_id in self.collect_candidates(terms) {
+                if query.applies(*language, || self.doc_language(doc_id)) {
+                    candidates.push((doc_id, terms.as_slice()));
+                }
+            }
+        }
+        candidates
+    }
+
+    /// Tokenize a query into distinct terms weighted by the query tf mode
+    ///
+    /// Uses the plain tokenizer; see `analyze_query` for `auto_language`.
+    fn query_terms(&self, query: &str, scorer: &BM25Scorer) -> Vec<(String, f32)> {
+        weight_query_tokens(self.query_tokens(self.tokenizer.tokenize(query)), scorer)
+    }
+
+    /// Query tokens followed by their bigrams, if bigrams are indexed
+    fn query_tokens(&self, mut tokens: Vec<String>) -> Vec<String> {
+        if self.bigrams {
+            append_bigrams(&mut tokens);
+        }
+        tokens
+    }
+
+    /// Create a scorer from the current corpus statistics
+    fn scorer(&self) -> BM25Scorer {
+        let avg_doc_len = if self.doc_metas.is_empty() {
+            1.0
+        } else {
+            self.total_doc_len as f32 / self.doc_metas.len() as f32
+        };
+        BM25Scorer::new(self.params, avg_doc_len, self.doc_metas.len())
+    }
+
+    /// Sort scored documents and return the top-k results
+    fn rank(&self, scores: Vec<(u32, f32)>, k: usize) -> Vec<SearchResult> {
+        rank_scores(scores, k, self.ranking, |doc_id| self.chunk_id(doc_id))
+    }
+
+    /// Chunk id reported for a doc_id: its external id, or `chunk_<doc_id>`
+    fn chunk_id(&self, doc_id: u32) -> String {
+        match self.external_ids.get(&doc_id) {
+            Some(external_id) => external_id.clone(),
+            None => format!("chunk_{}", doc_id),
+        }
+    }
+
+    /// Score a single document for a query
+    fn score_document(&self, doc_id: u32, query_terms: &[(String, f32)], scorer: &BM25Scorer) -> f32 {
+        let doc_len = self.doc_len_or_default(doc_id);
+        let score = query_terms
+            .iter()
+            .map(|(term, weight)| weight * self.term_score(doc_id, term, doc_len, scorer))
+            .sum::<f32>()
+            * self.exact_match_factor(doc_id, query_terms, self.params.exact_match_boost);
+        self.params.prior_mode.apply(score, self.priors.get(&doc_id).copied())
+    }
+
+    /// `boost` if the document is short and contains every query term, else 1.0
+    fn exact_match_factor(&self, doc_id: u32, query_terms: &[(String, f32)], boost: f32) -> f32 {
+        if boost == 1.0 || query_terms.is_empty() {
+            return 1.0;
+        }
+        let has_all = query_terms
+            .iter()
+            .all(|(term, _)| self.term_freqs.get(term).is_some_and(|docs| docs.contains_key(&doc_id)));
+        let short = || {
+            self.doc_meta(doc_id)
+                .is_some_and(|m| m.unique_terms(&self.long_docs) as usize <= 2 * query_terms.len())
+        };
+        if has_all && short() {
+            boost
+        } else {
+            1.0
+        }
+    }
+
+    /// BM25 contribution of one term to a document, 0.0 if it doesn't occur
+    fn term_score(&self, doc_id: u32, term: &str, doc_len: f32, scorer: &BM25Scorer) -> f32 {
+        let Some(term_docs) = self.term_freqs.get(term) else {
+            return 0.0;
+        };
+        let Some(&term_freq) = term_docs.get(&doc_id) else {
+            return 0.0;
+        };
+        if let Some(custom) = &self.custom_scorer {
+            let ctx = CorpusStats {
+                doc_count: self.doc_metas.len(),
+                avg_doc_len: scorer.avg_doc_len,
+            };
+            return custom.score(term_freq as f32, doc_len, term_docs.len(), &ctx);
+        }
+        let idf = match self.warm.as_ref().and_then(|warm| warm.idf.get(term)) {
+            Some(&idf) => idf,
+            None => scorer.idf(term_docs.len()),
+        };
+        scorer.score_term_with_idf(idf, term_freq as f32, doc_len)
+    }
+
+    /// Save the index to a file
+    ///
+    /// The file holds one serialized snapshot per line: the base index
+    /// followed by any segments appended with `save_segment`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.save_snapshot(path, !self.postings.is_empty())
+    }
+
+    /// Save the index marked as built without building it first
+    ///
+    /// `load` then builds the postings, so a writer can skip holding them
+    /// in memory alongside the term frequencies.
+    pub(crate) fn save_built(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.save_snapshot(path, true)
+    }
+
+    fn save_snapshot(&self, path: impl AsRef<Path>, built: bool) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_snapshot(&mut writer, 0, built)?;
+        writer.flush()?;
+        self.saved_docs.store(self.insertion_order.len(), Ordering::Relaxed);
+        self.saved_stale.store(false, Ordering::Relaxed);
+        if let Some(wal) = &self.wal {
+            wal.truncate()?;
+        }
+        Ok(())
+    }
+
+    /// Append the documents added since the last save as a new segment
+    ///
+    /// Only documents added since this index was last saved, loaded or
+    /// segmented are written, so small updates don't rewrite the base. A
+    /// segment can't express changes to documents already in the file, so
+    /// if any were removed, appended to, decayed, reindexed or pruned by
+    /// `build_pruned` since, the whole file is rewritten with `save` instead. Segments are merged
+    /// into the base by `load`, a segment's copy of a doc_id replacing the
+    /// earlier one; run `compact_segments` periodically to fold them into a
+    /// single snapshot.
+    pub fn save_segment(&self, path: impl AsRef<Path>) -> Result<()> {
+        if self.saved_stale.load(Ordering::Relaxed) {
+            return self.save(path);
+        }
+        let saved_docs = self.saved_docs.load(Ordering::Relaxed);
+        if saved_docs == self.insertion_order.len() {
+            return Ok(());
+        }
+        let file = OpenOptions::new().append(true).open(path)?;
+        let mut writer = BufWriter::new(file);
+        self.write_snapshot(&mut writer, saved_docs, !self.postings.is_empty())?;
+        writer.flush()?;
+        self.saved_docs.store(self.insertion_order.len(), Ordering::Relaxed);
+        if let Some(wal) = &self.wal {
+            wal.truncate()?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite an index file so the base and all segments form one snapshot
+    pub fn compact_segments(path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        Self::load(path)?.save(path)
+    }
+
+    /// Load an index from a file, merging any appended segments
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Serialize the index to a writer as a single snapshot line
+    pub fn write_to(&self, writer: impl Write) -> Result<()> {
+        self.write_snapshot(writer, 0, !self.postings.is_empty())
+    }
+
+    /// Write a snapshot of the documents from `from` on in insertion order
+    ///
+    /// Per-document data of earlier documents is left out, so a non-zero
+    /// `from` writes a segment. Maps are serialized straight from the index
+    /// rather than copied into an `IndexSnapshot` first. `built` tells `load`
+    /// whether to build the postings.
+    fn write_snapshot(&self, mut writer: impl Write, from: usize, built: bool) -> Result<()> {
+        let docs: Option<AHashSet<u32>> = (from > 0).then(|| self.insertion_order.range(from..).copied().collect());
+        let keep = |doc_id: &u32| docs.as_ref().is_none_or(|docs| docs.contains(doc_id));
+        let doc_map = |docs| DocMap { docs, keep: &keep };
+        let snapshot = SnapshotRef {
+            format_version: FORMAT_VERSION,
+            tokenizer: &self.tokenizer,
+            params: self.params,
+            doc_metas: SavedDocMetas { index: self, from },
+            term_freqs: TermFreqs {
+                term_freqs: &self.term_freqs,
+                keep: &keep,
+            },
+            built,
+            external_ids: doc_map(&self.external_ids),
+            groups: doc_map(&self.groups),
+            priors: DocMap {
+                docs: &self.priors,
+                keep: &keep,
+            },
+            payloads: doc_map(&self.payloads),
+            store_text: self.store_text,
+            texts: doc_map(&self.texts),
+            rank_base: self.ranking.rank_base,
+            score_epsilon: self.ranking.score_epsilon,
+            raw_postings: !self.compress_postings,
+            highlight_markers: Some((&self.highlight_markers.0, &self.highlight_markers.1)),
+            hashed_keys: doc_map(&self.hashed_keys),
+            sorted_term_dict: self.sorted_term_dict,
+            vocabulary: &self.vocabulary,
+            auto_language: self.auto_language,
+            bigrams: self.bigrams,
+            position_gap: self.position_gap,
+            max_docs: self.max_docs,
+            max_candidates: self.max_candidates,
+            capacity_policy: self.capacity_policy,
+            field_starts: DocMap {
+                docs: &self.field_starts,
+                keep: &keep,
+            },
+            #[cfg(feature = "vectors")]
+            vectors: DocMap {
+                docs: &self.vectors,
+                keep: &keep,
+            },
+        };
+        serde_json::to_writer(&mut writer, &snapshot)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Format version of serialized index data, read from its first snapshot
+    ///
+    /// Data written before versioning reports version 1.
+    pub fn format_version(data: &[u8]) -> Result<u32> {
+        let header = serde_json::Deserializer::from_slice(data)
+            .into_iter::<SnapshotHeader>()
+            .next()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "empty index file"))??;
+        Ok(header.format_version)
+    }
+
+    /// Deserialize an index from a reader, merging any segments after the base
+    ///
+    /// Snapshots from older format versions are migrated on load; versions
+    /// newer than `FORMAT_VERSION` fail with `IndexError::UnsupportedVersion`.
+    pub fn read_from(reader: impl Read) -> Result<Self> {
+        let mut snapshots = serde_json::Deserializer::from_reader(reader)
+            .into_iter::<IndexSnapshot>()
+            .map(|snapshot| snapshot.map_err(IndexError::from).and_then(check_format_version));
+        let base = snapshots.next().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "empty index file")
+        })??;
+
+        let mut index = Self::with_tokenizer(base.params, base.tokenizer.clone())
+            .with_rank_base(base.rank_base)
+            .with_score_epsilon(base.score_epsilon)
+            .with_position_gap(base.position_gap)
+            .with_sorted_term_dict(base.sorted_term_dict)
+            .with_auto_language(base.auto_language)
+            .with_bigrams(base.bigrams)
+            .with_compress_postings(!base.raw_postings);
+        if let Some((open, close)) = &base.highlight_markers {
+            index.set_highlight_markers(open, close);
+        }
+        index.import_vocabulary(&base.vocabulary);
+        index.max_docs = base.max_docs;
+        index.max_candidates = base.max_candidates;
+        index.capacity_policy = base.capacity_policy;
+        let mut built = index.merge_snapshot(base);
+        for segment in snapshots {
+            built |= index.merge_snapshot(segment?);
+        }
+        if built {
+            index.build();
+        }
+        *index.saved_docs.get_mut() = index.insertion_order.len();
+        Ok(index)
+    }
+
+    /// Merge a snapshot's documents into this index, returning its built flag
+    fn merge_snapshot(&mut self, snapshot: IndexSnapshot) -> bool {
+        let existing: AHashSet<u32> = self.doc_metas.iter().map(|m| m.doc_id).collect();
+        for saved in snapshot.doc_metas {
+            // A later segment's copy of a document replaces the earlier one
+            if existing.contains(&saved.doc_id) {
+                self.remove_document(saved.doc_id);
+            }
+            self.total_doc_len += saved.doc_len as u64;
+            let mut meta = DocMeta::new(saved.doc_id, saved.doc_len, saved.unique_terms, &mut self.long_docs);
+            meta.language = saved.language;
+            self.doc_metas.push(meta);
+            self.insertion_order.push_back(saved.doc_id);
+        }
+        let track = self.tracks_doc_terms();
+        for (term, docs) in snapshot.term_freqs {
+            if track {
+                for &doc_id in docs.keys() {
+                    self.doc_terms.entry(doc_id).or_default().push(term.clone());
+                }
+            }
+            self.term_freqs.entry(term).or_default().extend(docs);
+        }
+        for (doc_id, external_id) in snapshot.external_ids {
+            self.doc_ids.insert(external_id.clone(), doc_id);
+            self.external_ids.insert(doc_id, external_id);
+        }
+        self.groups.extend(snapshot.groups);
+        self.priors.extend(snapshot.priors);
+        self.payloads.extend(snapshot.payloads);
+        self.hashed_keys.extend(snapshot.hashed_keys);
+        self.store_text |= snapshot.store_text;
+        self.field_starts.extend(snapshot.field_starts);
+        for (doc_id, text) in snapshot.texts {
+            self.store_document_text(doc_id, text);
+        }
+        #[cfg(feature = "vectors")]
+        self.vectors.extend(snapshot.vectors);
+        snapshot.built
+    }
+
+    /// Approximate heap bytes used by postings, term frequencies and doc metadata
+    ///
+    /// An estimate for capacity planning: hash map overhead is approximated
+    /// and stored text and caches are not counted.
+    pub fn memory_usage(&self) -> usize {
+        let postings: usize = self
+            .postings
+            .iter()
+            .map(|(term, list)| term.len() + list.heap_bytes())
+            .sum();
+        let term_freqs: usize = self
+            .term_freqs
+            .iter()
+            .map(|(term, docs)| term.len() + docs.capacity() * (std::mem::size_of::<(u32, u32)>() + 1))
+            .sum();
+        let long_docs = self.long_docs.capacity() * (std::mem::size_of::<(u32, (u32, u32))>() + 1);
+        postings + term_freqs + self.doc_metas.capacity() * std::mem::size_of::<DocMeta>() + long_docs
+    }
+
+    /// BM25 parameters used by `search`
+    pub fn params(&self) -> BM25Params {
+        self.params
+    }
+
+    /// Tokenizer used for documents and queries
+    pub fn tokenizer(&self) -> &Tokenizer {
+        &self.tokenizer
+    }
+
+    /// Total number of tokens indexed across all documents
+    pub fn total_tokens(&self) -> u64 {
+        self.total_doc_len
+    }
+
+    /// Human-readable summary of the index for debugging
+    ///
+    /// Lists document and term counts, average document length, postings
+    /// compression ratio, the 20 most frequent terms by document frequency
+    /// and a histogram of postings list lengths in power-of-two buckets.
+    /// Terms left with no documents, as in a corrupt index, are counted in
+    /// a bucket of their own.
+    pub fn report(&self) -> String {
+        use std::fmt::Write as _;
+
+        let stats = self.stats();
+        let mut out = String::new();
+        writeln!(out, "BM25 index report").unwrap();
+        writeln!(out, "  documents:          {}", stats.num_docs).unwrap();
+        writeln!(out, "  terms:              {}", self.term_freqs.len()).unwrap();
+        writeln!(out, "  avg doc length:     {:.2}", stats.avg_doc_len).unwrap();
+
+        let compressed: usize = self.postings.values().map(PostingsList::heap_bytes).sum();
+        if compressed > 0 {
+            let raw: usize = self.postings.values().map(|p| p.len() * 4).sum();
+            writeln!(
+                out,
+                "  compression ratio:  {:.2}x ({} bytes vs {} raw)",
+                raw as f64 / compressed as f64,
+                compressed,
+                raw
+            )
+            .unwrap();
+        } else {
+            writeln!(out, "  compression ratio:  n/a (not built)").unwrap();
+        }
+
+        let mut terms: Vec<(&str, usize)> = self.vocabulary().collect();
+        terms.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        writeln!(out, "\nTop terms by document frequency:").unwrap();
+        for (term, df) in terms.iter().take(20) {
+            writeln!(out, "  {:<24} {}", term, df).unwrap();
+        }
+
+        // None buckets zero-df terms, ahead of the power-of-two buckets
+        let mut histogram: BTreeMap<Option<u32>, usize> = BTreeMap::new();
+        for &(_, df) in &terms {
+            *histogram.entry(df.checked_ilog2()).or_insert(0) += 1;
+        }
+        writeln!(out, "\nPostings list lengths:").unwrap();
+        for (bucket, count) in histogram {
+            let Some(bucket) = bucket else {
+                writeln!(out, "  {:<24} {}", 0, count).unwrap();
+                continue;
+            };
+            let (low, high) = (1usize << bucket, (1usize << (bucket + 1)) - 1);
+            let range = if low == high { low.to_string() } else { format!("{}-{}", low, high) };
+            writeln!(out, "  {:<24} {}", range, count).unwrap();
+        }
+        out
+    }
+
+    /// Check the index's internal invariants
+    ///
+    /// Verifies that `total_doc_len` matches the document lengths, that every
+    /// built postings list decodes to strictly increasing doc_ids, and that
+    /// every document referenced by a term exists. Useful after loading an
+    /// index that may have been partially written.
+    pub fn verify(&self) -> std::result::Result<(), IntegrityError> {
+        let computed: u64 = self.doc_metas.iter().map(|m| m.doc_len(&self.long_docs) as u64).sum();
+        if computed != self.total_doc_len {
+            return Err(IntegrityError::TotalDocLen {
+                stored: self.total_doc_len,
+                computed,
+            });
+        }
+
+        for (term, list) in &self.postings {
+            let doc_ids: Vec<u32> = list.iter().collect();
+            if doc_ids.windows(2).any(|pair| pair[0] >= pair[1]) {
+                return Err(IntegrityError::UnsortedPostings { term: term.clone() });
+            }
+        }
+
+        let known: AHashSet<u32> = self.doc_metas.iter().map(|m| m.doc_id).collect();
+        for (term, docs) in &self.term_freqs {
+            if let Some(&doc_id) = docs.keys().find(|doc_id| !known.contains(doc_id)) {
+                return Err(IntegrityError::UnknownDocument {
+                    term: term.clone(),
+                    doc_id,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Get index statistics
+    pub fn stats(&self) -> IndexStats {
+        IndexStats {
+            num_docs: self.doc_metas.len(),
+            num_terms: self.term_freqs.len(),
+            avg_doc_len: if self.doc_metas.is_empty() {
+                0.0
+            } else {
+                self.total_doc_len as f32 / self.doc_metas.len() as f32
+            },
+        }
+    }
+}
+
+/// Reject snapshots written by an unknown format version
+fn check_format_version(snapshot: IndexSnapshot) -> Result<IndexSnapshot> {
+    if (1..=FORMAT_VERSION).contains(&snapshot.format_version) {
+        Ok(snapshot)
+    } else {
+        Err(IndexError::UnsupportedVersion {
+            found: snapshot.format_version,
+            supported: FORMAT_VERSION,
+        })
+    }
+}
+
+/// Stable doc_id for a string key: 32-bit FNV-1a over its UTF-8 bytes
+pub fn hash_doc_key(key: &str) -> u32 {
+    key.bytes().fold(0x811c_9dc5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
+
+/// Smallest token distance between two position lists sorted by token
+fn min_token_distance(a: &[TermPosition], b: &[TermPosition]) -> usize {
+    let (mut i, mut j) = (0, 0);
+    let mut best = usize::MAX;
+    while i < a.len() && j < b.len() {
+        let (x, y) = (a[i].token, b[j].token);
+        best = best.min(x.abs_diff(y) as usize);
+        if x < y {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    best
+}
+
+/// A document's token count and per-term frequencies
+type TermCounts = (u32, HashMap<String, u32>);
+
+/// Count term frequencies as tokens are produced
+///
+/// With `bigrams`, adjacent token pairs are counted too but not added to
+/// the document length.
+fn count_terms(tokens: impl IntoIterator<Item = String>, bigrams: bool) -> TermCounts {
+    let mut doc_len = 0;
+    let mut term_counts: HashMap<String, u32> = HashMap::new();
+    let mut prev: Option<String> = None;
+    for term in tokens {
+        doc_len += 1;
+        if bigrams {
+            if let Some(prev) = prev.replace(term.clone()) {
+                *term_counts.entry(bigram(&prev, &term)).or_insert(0) += 1;
+            }
+        }
+        *term_counts.entry(term).or_insert(0) += 1;
+    }
+    (doc_len, term_counts)
+}
+
+/// Append the bigram of every adjacent pair of tokens
+fn append_bigrams(tokens: &mut Vec<String>) {
+    let bigrams: Vec<String> = tokens.windows(2).map(|pair| bigram(&pair[0], &pair[1])).collect();
+    tokens.extend(bigrams);
+}
+
+/// Term indexing two adjacent tokens
+fn bigram(first: &str, second: &str) -> String {
+    format!("{}_{}", first, second)
+}
+
+/// Sort a term's dense ids into a postings list, block-compressed if `compress`
+fn build_postings(doc_freqs: &HashMap<u32, u32>, dense_ids: &AHashMap<u32, u32>, compress: bool) -> PostingsList {
+    let mut doc_ids: Vec<u32> = doc_freqs.keys().map(|doc_id| dense_ids[doc_id]).collect();
+    doc_ids.sort_unstable();
+    if compress {
+        PostingsList::Compressed(encode_block_postings(&doc_ids, DEFAULT_BLOCK_SIZE))
+    } else {
+        PostingsList::Raw(doc_ids)
+    }
+}
+
+/// Tokenize a query into distinct terms weighted by the query tf mode
+///
+/// Terms keep the order of their first occurrence.
+pub(crate) fn weighted_query_terms(tokenizer: &Tokenizer, query: &str, scorer: &BM25Scorer) -> Vec<(String, f32)> {
+    weight_query_tokens(tokenizer.tokenize(query), scorer)
+}
+
+/// Distinct query tokens weighted by the scorer's query tf mode
+fn weight_query_tokens(tokens: Vec<String>, scorer: &BM25Scorer) -> Vec<(String, f32)> {
+    let mut terms: Vec<(String, f32)> = Vec::new();
+    for token in tokens {
+        match terms.iter_mut().find(|(term, _)| *term == token) {
+            Some((_, count)) => *count += 1.0,
+            None => terms.push((token, 1.0)),
+        }
+    }
+    for (_, weight) in &mut terms {
+        *weight = scorer.query_term_weight(*weight);
+    }
+    terms
+}
+
+/// Distinct query terms with their query tf weights
+type QueryTerms = Vec<(String, f32)>;
+
+/// A query's weighted terms, analyzed the way the documents they match were
+///
+/// With `auto_language`, the query is analyzed once per language (and once
+/// with the plain tokenizer), and each analysis only applies to documents
+/// of that language. Otherwise a single analysis applies to every document.
+pub(crate) struct AnalyzedQuery {
+    pub(crate) analyses: Vec<(Option<Language>, QueryTerms)>,
+    per_language: bool,
+}
+
+impl AnalyzedQuery {
+    /// Analyze `query`, appending bigrams if they are indexed
+    pub(crate) fn new(query: &str, tokenizer: &Tokenizer, auto_language: bool, bigrams: bool, scorer: &BM25Scorer) -> Self {
+        let terms = |language: Option<Language>| {
+            let tokens = tokenizer.tokenize(query);
+            let mut tokens = match language {
+                Some(language) => language.analyze_terms(tokens),
+                None => tokens,
+            };
+            if bigrams {
+                append_bigrams(&mut tokens);
+            }
+            weight_query_tokens(tokens, scorer)
+        };
+        let languages: Vec<Option<Language>> = match auto_language {
+            true => Language::ALL.into_iter().map(Some).chain([None]).collect(),
+            false => vec![None],
+        };
+        Self {
+            analyses: languages.into_iter().map(|language| (language, terms(language))).collect(),
+            per_language: auto_language,
+        }
+    }
+
+    /// Whether the analysis for `language` applies to a document
+    ///
+    /// The document's language is only looked up with `auto_language`.
+    pub(crate) fn applies(&self, language: Option<Language>, doc_language: impl FnOnce() -> Option<Language>) -> bool {
+        !self.per_language || language == doc_language()
+    }
+
+    /// Terms to score a document with, looking up its language only if needed
+    pub(crate) fn terms_for(&self, doc_language: impl FnOnce() -> Option<Language>) -> &[(String, f32)] {
+        if !self.per_language {
+            return &self.analyses[0].1;
+        }
+        let doc_language = doc_language();
+        self.analyses
+            .iter()
+            .find(|(language, _)| *language == doc_language)
+            .map_or(&[], |(_, terms)| terms)
+    }
+}
+
+/// How ranked results are ordered and numbered
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Ranking {
+    /// Rank reported for the top result
+    pub(crate) rank_base: usize,
+    /// Scores closer than this are ties broken by doc_id
+    pub(crate) score_epsilon: f32,
+}
+
+/// Sort by score descending, breaking ties by doc_id for stable output
+///
+/// With a positive `epsilon`, scores less than it below the highest score
+/// of their group are also treated as ties. Groups are anchored on that
+/// highest score rather than chained through neighbours, so scores further
+/// than `epsilon` apart never tie. Groups are found after an exact sort so
+/// the comparator stays a total order.
+pub(crate) fn sort_scores(scores: &mut [(u32, f32)], epsilon: f32) {
+    scores.sort_by(|a, b| {
+        b.1.partial_cmp(&a.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.0.cmp(&b.0))
+    });
+    if epsilon <= 0.0 {
+        return;
+    }
+    let mut start = 0;
+    for end in 1..=scores.len() {
+        if end == scores.len() || scores[start].1 - scores[end].1 >= epsilon {
+            scores[start..end].sort_by_key(|&(doc_id, _)| doc_id);
+            start = end;
+        }
+    }
+}
+
+/// Sort scored documents and return the top-k results
+pub(crate) fn rank_scores(
+    mut scores: Vec<(u32, f32)>,
+    k: usize,
+    ranking: Ranking,
+    chunk_id: impl Fn(u32) -> String,
+) -> Vec<SearchResult> {
+    sort_scores(&mut scores, ranking.score_epsilon);
+
+    // Return top-k results
+    scores
+        .into_iter()
+        .take(k)
+        .enumerate()
+        .map(|(rank, (doc_id, score))| SearchResult {
+            doc_id,
+            chunk_id: chunk_id(doc_id),
+            score,
+            rank: ranking.rank_base + rank,
+        })
+        .collect()
+}
+
+#[cfg(feature = "vectors")]
+impl BM25Index {
+    /// Store a dense vector for a document
+    pub fn add_document_vector(&mut self, doc_id: u32, vector: Vec<f32>) {
+        self.mark_saved_changed(doc_id);
+        if let Some(wal) = &mut self.wal {
+            wal.append(&WalEntry::Vector {
+                doc_id,
+                vector: vector.clone(),
+            });
+        }
+        self.vectors.insert(doc_id, vector);
+    }
+
+    /// BM25 retrieval reranked by cosine similarity to a query vector
+    ///
+    /// BM25 scores are normalized by the best candidate's score, then combined
+    /// as `(1 - alpha) * bm25 + alpha * cosine`. Candidates without a stored
+    /// vector get a cosine of 0. `alpha = 0` keeps the pure BM25 ordering.
+    pub fn search_hybrid(&self, query: &str, query_vec: &[f32], k: usize, alpha: f32) -> Vec<SearchResult> {
+        let scores = self.score_candidates(&[(query, 1.0)]);
+        let max_score = scores.values().copied().fold(0.0f32, f32::max);
+
+        let combined = scores
+            .into_iter()
+            .map(|(doc_id, score)| {
+                let bm25 = if max_score > 0.0 { score / max_score } else { 0.0 };
+                let cosine = self
+                    .vectors
+                    .get(&doc_id)
+                    .map_or(0.0, |vector| cosine_similarity(query_vec, vector));
+                (doc_id, (1.0 - alpha) * bm25 + alpha * cosine)
+            })
+            .collect();
+
+        self.rank(combined, k)
+    }
+}
+
+/// Cosine similarity between two vectors (0 if either has zero norm)
+#[cfg(feature = "vectors")]
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+impl Default for BM25Index {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per-phase timing breakdown of a search
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchTimings {
+    pub tokenize: Duration,
+    pub candidates: Duration,
+    pub scoring: Duration,
+    pub sort: Duration,
+    pub total: Duration,
+}
+
+/// Latency distribution from `BM25Index::benchmark`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BenchReport {
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+/// Document count and per-term document frequencies summed across shards
+#[derive(Debug, Clone, Default)]
+pub struct GlobalStats {
+    pub doc_count: usize,
+    pub df: AHashMap<String, usize>,
+}
+
+impl GlobalStats {
+    /// Add another shard's statistics into these
+    pub fn merge(&mut self, other: &GlobalStats) {
+        self.doc_count += other.doc_count;
+        for (term, &df) in &other.df {
+            *self.df.entry(term.clone()).or_insert(0) += df;
+        }
+    }
+}
+
+/// Index statistics
+#[derive(Debug, Clone)]
+pub struct IndexStats {
+    pub num_docs: usize,
+    pub num_terms: usize,
+    pub avg_doc_len: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::HyphenMode;
+
+    #[test]
+    fn test_bm25_scorer() {
+        let scorer = BM25Scorer::new(BM25Params::default(), 10.0, 100);
+        let score = scorer.score_term(2.0, 10.0, 50);
+        assert!(score > 0.0);
+    }
+
+    #[test]
+    fn test_degenerate_scorer_is_finite() {
+        for idf in [IdfVariant::Robertson, IdfVariant::LuceneClassic, IdfVariant::Classic] {
+            let params = BM25Params { idf, ..BM25Params::default() };
+            let scorer = BM25Scorer::new(params, 0.0, 0);
+            assert!(scorer.score_term(1.0, 5.0, 1).is_finite());
+            assert!(scorer.score_term(1.0, 0.0, 0).is_finite());
+        }
+    }
+
+    #[test]
+    fn test_idf_variants() {
+        let idf = |idf: IdfVariant| {
+            let params = BM25Params { idf, ..BM25Params::default() };
+            BM25Scorer::new(params, 10.0, 100).idf(20)
+        };
+        // ln(80.5 / 20.5 + 1)
+        assert!((idf(IdfVariant::Robertson) - 1.5947).abs() < 1e-4);
+        // 1 + ln(101 / 21)
+        assert!((idf(IdfVariant::LuceneClassic) - 2.5706).abs() < 1e-4);
+        // Indexes saved before the rename still load
+        let old: IdfVariant = serde_json::from_str("\"Lucene\"").unwrap();
+        assert_eq!(old, IdfVariant::LuceneClassic);
+        // ln(100 / 20)
+        assert!((idf(IdfVariant::Classic) - 1.6094).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_query_tf_modes() {
+        let search = |query_tf: QueryTfMode| {
+            let params = BM25Params { query_tf, ..BM25Params::default() };
+            let mut index = BM25Index::with_params(params);
+            index.add_document(1, "python programming language");
+            index.add_document(2, "rust systems programming");
+            index.build();
+            (index.search("python", 1)[0].score, index.search("python python", 1)[0].score)
+        };
+
+        let (single, repeated) = search(QueryTfMode::Linear);
+        assert!((repeated - 2.0 * single).abs() < 1e-5);
+
+        let (single, repeated) = search(QueryTfMode::Binary);
+        assert!((repeated - single).abs() < 1e-5);
+
+        // (k3 + 1) * 2 / (k3 + 2) with k3 = 1
+        let (single, repeated) = search(QueryTfMode::Saturated { k3: 1.0 });
+        assert!((repeated - single * 4.0 / 3.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_index_build() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "the quick brown fox");
+        index.add_document(2, "the lazy dog");
+        index.add_document(3, "quick brown dog");
+        index.build();
+
+        let stats = index.stats();
+        assert_eq!(stats.num_docs, 3);
+        assert!(stats.num_terms > 0);
+    }
+
+    #[test]
+    fn test_empty_document_rejected() {
+        let mut index = BM25Index::new();
+        assert!(index.add_document(1, "quick brown fox"));
+        assert!(!index.add_document(2, "a b c"));
+        assert!(!index.add_document(3, ""));
+        assert!(!index.add_document_with_external_id(4, "empty", "!"));
+        index.build();
+
+        let stats = index.stats();
+        assert_eq!(stats.num_docs, 1);
+        assert_eq!(stats.avg_doc_len, 3.0);
+        assert_eq!(index.resolve_id("empty"), None);
+    }
+
+    #[test]
+    fn test_index_jsonl() {
+        let jsonl = concat!(
+            r#"{"id": 1, "text": "Python programming language"}"#, "\n",
+            r#"{"id": 2, "text": "Rust systems programming", "title": "Rust"}"#, "\n",
+            "\n",
+            r#"{"id": 3, "body": "missing text field"}"#, "\n",
+            "not json\n",
+            r#"{"id": 4, "text": "a"}"#, "\n",
+        );
+
+        let mut index = BM25Index::new();
+        let count = index.index_jsonl(jsonl.as_bytes(), "id", "text", true).unwrap();
+        assert_eq!(count, 2);
+        index.build();
+        assert_eq!(index.search("programming", 10).len(), 2);
+
+        let mut index = BM25Index::new();
+        let err = index.index_jsonl(jsonl.as_bytes(), "id", "text", false).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 4"));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_build_in_pool() {
+        let docs = [
+            (1, "Python programming language"),
+            (2, "Rust systems programming"),
+            (3, "Python data science"),
+        ];
+        let mut serial = BM25Index::new();
+        let mut pooled = BM25Index::new();
+        for (doc_id, text) in docs {
+            serial.add_document(doc_id, text);
+            pooled.add_document(doc_id, text);
+        }
+        serial.sync_dense_ids();
+        for (term, doc_freqs) in &serial.term_freqs {
+            serial.postings.insert(term.clone(), build_postings(doc_freqs, &serial.dense_ids, true));
+        }
+
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        pooled.build_in_pool(&pool);
+
+        assert_eq!(pooled.postings, serial.postings);
+        assert_eq!(pooled.search("python programming", 10), serial.search("python programming", 10));
+    }
+
+    #[test]
+    fn test_search() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.build();
+
+        let results = index.search("Python programming", 2);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].doc_id, 1);
+        assert_eq!(results[0].chunk_id, "chunk_1"); // Best match
+        assert!(results[0].score > results[1].score);
+    }
+
+    #[test]
+    fn test_compress_postings_option() {
+        let build = |compress| {
+            let mut index = BM25Index::new().with_compress_postings(compress);
+            for doc_id in 0..500 {
+                let text = format!("document {} about rust and python number{}", doc_id, doc_id % 7);
+                index.add_document(doc_id, &text);
+            }
+            index.build();
+            index
+        };
+        let (compressed, raw) = (build(true), build(false));
+        assert!(matches!(compressed.postings["rust"], PostingsList::Compressed(_)));
+        assert!(matches!(raw.postings["rust"], PostingsList::Raw(_)));
+
+        for query in ["rust", "python number3", "document 42", "missing"] {
+            assert_eq!(compressed.search(query, 10), raw.search(query, 10));
+            assert_eq!(compressed.candidate_count(query), raw.candidate_count(query));
+        }
+        assert_eq!(raw.verify(), Ok(()));
+        assert!(raw.memory_usage() > compressed.memory_usage());
+
+        // The frozen index encodes raw postings on the way in
+        let expected = raw.search("python", 5);
+        assert_eq!(raw.freeze().search("python", 5), expected);
+    }
+
+    #[test]
+    fn test_long_document_len() {
+        let long_text = "word ".repeat(70_000);
+        let mut index = BM25Index::new();
+        index.add_document(1, &long_text);
+        index.add_document(2, "short word list");
+        index.build();
+
+        assert_eq!(index.doc_len(1), Some(70_000));
+        assert_eq!(index.doc_len(2), Some(3));
+        assert_eq!(index.doc_len(3), None);
+        assert_eq!(index.verify(), Ok(()));
+
+        // Appending past the cap and reloading keep the exact length
+        index.append_to_document(2, &"more ".repeat(65_535));
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        let loaded = BM25Index::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.doc_len(1), Some(70_000));
+        assert_eq!(loaded.doc_len(2), Some(65_538));
+        assert_eq!(loaded.total_tokens(), 135_538);
+        assert_eq!(loaded.search("word", 2), index.search("word", 2));
+    }
+
+    #[test]
+    fn test_exact_match_boost() {
+        let build = |exact_match_boost: f32| {
+            let params = BM25Params { exact_match_boost, ..BM25Params::default() };
+            let mut index = BM25Index::with_params(params);
+            index.add_document(1, "rust compiler");
+            index.add_document(2, "rust rust rust rust language");
+            index.add_document(3, "rust compiler internals explained in a long and detailed guide");
+            // "compiler" is common, so a document that repeats "rust" outranks plain BM25
+            for doc_id in 10..110 {
+                index.add_document(doc_id, "compiler design");
+            }
+            index.build();
+            index
+        };
+
+        let top = |index: &BM25Index| index.search("rust compiler", 10)[0].doc_id;
+        assert_eq!(top(&build(1.0)), 2);
+        let boosted = build(3.0);
+        assert_eq!(top(&boosted), 1);
+        // The long document with both terms is not boosted
+        let results = boosted.search("rust compiler", 10);
+        let long = results.iter().find(|r| r.doc_id == 3).unwrap().score;
+        assert_eq!(long, build(1.0).search("rust compiler", 10).iter().find(|r| r.doc_id == 3).unwrap().score);
+
+        // Freezing keeps the boost
+        let frozen = build(5.0);
+        let expected = frozen.search("rust compiler", 10);
+        assert_eq!(frozen.freeze().search("rust compiler", 10), expected);
+    }
+
+    #[test]
+    fn test_payloads() {
+        let mut index = BM25Index::new();
+        let payload = r#"{"url": "https://example.org/Rust", "section": "History"}"#;
+        assert!(index.add_document_with_payload(1, "Rust systems programming", payload.to_string()));
+        index.add_document(2, "Python programming language");
+        index.build();
+
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        let loaded = BM25Index::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.payload(1), Some(payload));
+        assert_eq!(loaded.payload(2), None);
+
+        let results = loaded.search_with_payload("programming rust", 10);
+        assert_eq!(results[0].doc_id, 1);
+        assert_eq!(results[0].payload.as_deref(), Some(payload));
+        assert_eq!(results[1].payload, None);
+        assert_eq!(results[0].score, loaded.search("programming rust", 10)[0].score);
+    }
+
+    #[test]
+    fn test_estimate_selectivity() {
+        // "even" and "fifth" occur independently; "teen" always co-occurs with "even"
+        let mut index = BM25Index::new();
+        for doc_id in 0..1000u32 {
+            let mut text = format!("doc{}", doc_id);
+            if doc_id % 2 == 0 {
+                text.push_str(" even");
+            }
+            if doc_id % 5 == 0 {
+                text.push_str(" fifth");
+            }
+            if doc_id % 20 == 0 {
+                text.push_str(" teen");
+            }
+            index.add_document(doc_id, &text);
+        }
+        index.build();
+
+        let actual = |query: &str| index.candidate_count(query) as f32 / 1000.0;
+        for query in ["even", "fifth", "even fifth", "even fifth even"] {
+            assert!((index.estimate_selectivity(query) - actual(query)).abs() < 1e-6, "{}", query);
+        }
+        // Correlated terms are overestimated
+        assert!(index.estimate_selectivity("even teen") > actual("even teen"));
+        assert_eq!(index.estimate_selectivity("missing"), 0.0);
+        assert_eq!(BM25Index::new().estimate_selectivity("even"), 0.0);
+    }
+
+    #[test]
+    fn test_phrase_slop() {
+        let mut index = BM25Index::new().with_store_text(true);
+        index.add_document(1, "machine deep learning");
+        index.add_document(2, "learning about machine tools");
+        index.add_document(3, "machine learning basics");
+
+        let phrase_docs = |index: &BM25Index, query: &str, slop: u32| -> Vec<u32> {
+            let mut docs: Vec<u32> = index.search_phrase(query, 10, slop).iter().map(|r| r.doc_id).collect();
+            docs.sort();
+            docs
+        };
+        assert_eq!(phrase_docs(&index, "machine learning", 0), vec![3]);
+        assert_eq!(phrase_docs(&index, "machine learning", 1), vec![1, 3]);
+        // Order is preserved however large the slop
+        assert_eq!(phrase_docs(&index, "machine learning", 10), vec![1, 3]);
+        // Slop is shared across the whole phrase
+        index.add_document(4, "machine big learning big basics");
+        assert_eq!(phrase_docs(&index, "machine learning basics", 1), vec![3]);
+        assert_eq!(phrase_docs(&index, "machine learning basics", 2), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_position_gap() {
+        let fields = ["Guide to New", "York pizza places"];
+        let build = |gap: u32| {
+            let mut index = BM25Index::new().with_store_text(true).with_position_gap(gap);
+            index.add_document_fields(1, &fields);
+            index.add_document(2, "New York pizza guide");
+            index
+        };
+
+        let phrase_docs = |index: &BM25Index| -> Vec<u32> {
+            index.search_phrase("new york", 10, 0).iter().map(|r| r.doc_id).collect()
+        };
+        let mut docs = phrase_docs(&build(0));
+        docs.sort();
+        assert_eq!(docs, vec![1, 2]);
+
+        // The gap keeps the phrase from spanning title end and body start
+        let mut index = build(100);
+        assert_eq!(phrase_docs(&index), vec![2]);
+        assert_eq!(index.search_phrase("pizza places", 10, 0)[0].doc_id, 1);
+        assert_eq!(index.stored_text(1), Some("Guide to New York pizza places"));
+        assert_eq!(index.match_positions(1, "york"), vec![(13, 17)]);
+
+        // Gaps survive appends and a save/load round trip
+        index.append_to_document(1, "new york");
+        assert_eq!(phrase_docs(&index).len(), 2);
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        let loaded = BM25Index::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.positions["york"][&1].iter().map(|p| p.token).collect::<Vec<_>>(), vec![103, 107]);
+        assert_eq!(loaded.search_phrase("to new york", 10, 0).len(), 0);
+    }
+
+    #[test]
+    fn test_sorted_term_dict() {
+        let mut index = BM25Index::new().with_sorted_term_dict(true);
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        assert_eq!(index.term_id("python"), None);
+        assert!(index.contains_term("python"));
+
+        index.build();
+        assert_eq!(index.term_dict, index.sorted_terms());
+        assert_eq!(index.term_id("data"), Some(0));
+        assert_eq!(index.term_id("python"), Some(3));
+        assert_eq!(index.term_id("missing"), None);
+        assert!(index.contains_term("rust") && !index.contains_term("ruby"));
+        assert_eq!(index.terms_with_prefix("pro"), vec!["programming"]);
+        assert_eq!(index.terms_with_prefix("s"), vec!["science", "systems"]);
+
+        // New terms fall back to the hash map until the next build
+        index.add_document(4, "Ruby scripting language");
+        assert!(index.contains_term("ruby"));
+        assert_eq!(index.terms_with_prefix("s"), vec!["science", "scripting", "systems"]);
+        index.build_incremental();
+        assert_eq!(index.term_id("ruby"), Some(4));
+    }
+
+    /// Run with `cargo test --release -p pocketwiki-core -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_term_lookup() {
+        use std::time::Instant;
+
+        let mut hashed = BM25Index::new();
+        let mut sorted = BM25Index::new().with_sorted_term_dict(true);
+        for doc_id in 0..2_000u32 {
+            let text: Vec<String> = (0..100).map(|i| format!("term{}x{}", doc_id, i)).collect();
+            hashed.add_document(doc_id, &text.join(" "));
+            sorted.add_document(doc_id, &text.join(" "));
+        }
+        hashed.build();
+        sorted.build();
+        let queries: Vec<String> = (0..200_000u32).map(|i| format!("term{}x{}", i % 2_500, i % 100)).collect();
+
+        let start = Instant::now();
+        let hash_hits = queries.iter().filter(|q| hashed.contains_term(q)).count();
+        let hash_time = start.elapsed();
+        let start = Instant::now();
+        let sorted_hits = queries.iter().filter(|q| sorted.contains_term(q)).count();
+        let sorted_time = start.elapsed();
+
+        assert_eq!(hash_hits, sorted_hits);
+        println!(
+            "{} terms, {} lookups: hash map {:?}, sorted array {:?}",
+            sorted.term_dict.len(),
+            queries.len(),
+            hash_time,
+            sorted_time
+        );
+    }
+
+    #[test]
+    fn test_search_with_params() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "apple apple apple apple apple apple");
+        index.add_document(2, "apple banana");
+        index.add_document(3, "cherry");
+        index.add_document(4, "grape");
+        index.build();
+
+        let top = |k1: f32| {
+            let params = BM25Params { k1, b: 0.0, ..index.params };
+            index.search_with_params("apple banana", 1, params)[0].doc_id
+        };
+        // Near-zero k1 ignores tf; a large k1 rewards the repeated term
+        assert_eq!(top(0.01), 2);
+        assert_eq!(top(100.0), 1);
+        assert_eq!(index.search_with_params("apple banana", 10, index.params), index.search("apple banana", 10));
+        assert_eq!(index.params.k1, 1.5);
+    }
+
+    #[test]
+    fn test_auto_language_query_paths() {
+        let mut index = BM25Index::new().with_auto_language(true).with_store_text(true);
+        index.add_document(1, "The cats are running and sleeping on the warm mat");
+        index.add_document(2, "Les chats courent et dorment sur le tapis");
+        index.add_document(3, "Plain words without any clear language");
+        index.build();
+        let languages: Vec<_> = [1, 2, 3].iter().map(|&doc_id| index.doc_language(doc_id)).collect();
+        assert_eq!(languages, vec![Some(Language::English), Some(Language::French), None]);
+
+        let queries = ["running cats", "chats courent", "sleeping", "plain words", "cats"];
+        let all: AHashSet<u32> = [1, 2, 3].into();
+        let mut expected_results = Vec::new();
+        for query in queries {
+            let expected = index.search(query, 10);
+            assert!(!expected.is_empty(), "{query}");
+            let ids: Vec<u32> = expected.iter().map(|r| r.doc_id).collect();
+            assert_eq!(index.search_timed(query, 10).0, expected, "{query}");
+            assert_eq!(index.search_deadline(query, 10, Duration::from_secs(60)).0, expected, "{query}");
+            assert_eq!(index.search_within(query, 10, &all), expected, "{query}");
+            assert_eq!(index.search_msm(query, 10, 1), expected, "{query}");
+            assert_eq!(index.search_boolean(query, 10), expected, "{query}");
+            assert_eq!(index.search_with_params(query, 10, index.params()), expected, "{query}");
+            assert_eq!(index.search_with_global_stats(query, 10, &index.global_stats()), expected, "{query}");
+            let mut sorted = ids.clone();
+            sorted.sort_unstable();
+            assert_eq!(index.candidates(query), sorted, "{query}");
+            for result in &expected {
+                assert_eq!(index.score_pair(query, result.doc_id), Some(result.score), "{query}");
+            }
+            expected_results.push(expected);
+        }
+        // "running cats" matches the English document through its stems
+        assert_eq!(index.search("running cats", 10)[0].doc_id, 1);
+
+        // Exclusions are analyzed like the query: "sleeping" removes the English "sleep"
+        assert!(index.search_boolean("cats -sleeping", 10).is_empty());
+        // Phrases match stored tokens but score like `search`
+        let phrase = index.search_phrase("chats courent", 10, 0);
+        assert_eq!(phrase, expected_results[1]);
+
+        let frozen = index.freeze();
+        for (query, expected) in queries.iter().zip(expected_results) {
+            assert_eq!(frozen.search(query, 10), expected, "{query}");
+        }
+    }
+
+    #[test]
+    fn test_auto_language() {
+        let mut index = BM25Index::new().with_auto_language(true);
+        index.add_document(1, "The cats are sleeping on the warm mat");
+        index.add_document(2, "On dort avec les chats sur le tapis");
+        index.build();
+
+        assert_eq!(index.doc_language(1), Some(Language::English));
+        assert_eq!(index.doc_language(2), Some(Language::French));
+
+        // English stopwords are dropped from the English doc only
+        assert_eq!(index.docs_for_term("on"), vec![2]);
+        assert!(index.docs_for_term("the").is_empty());
+        // French stopwords are dropped from the French doc
+        for stopword in ["les", "sur", "le", "avec"] {
+            assert!(index.docs_for_term(stopword).is_empty(), "{stopword}");
+        }
+        assert_eq!(index.docs_for_term("cat"), vec![1]);
+        assert_eq!(index.docs_for_term("chat"), vec![2]);
+
+        assert_eq!(index.search("sleeping cats", 10)[0].doc_id, 1);
+        assert_eq!(index.search("les chats", 10)[0].doc_id, 2);
+
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        let loaded = BM25Index::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.doc_language(2), Some(Language::French));
+        assert_eq!(loaded.search("sleeping cats", 10), index.search("sleeping cats", 10));
+    }
+
+    #[test]
+    fn test_search_into() {
+        #[derive(Debug, PartialEq)]
+        struct Hit(u32, f32, usize);
+
+        let mut index = BM25Index::new().with_rank_base(1);
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.build();
+
+        for k in [0, 2, 10] {
+            let expected: Vec<Hit> = index
+                .search("python programming", k)
+                .into_iter()
+                .map(|r| Hit(r.doc_id, r.score, r.rank))
+                .collect();
+            assert_eq!(index.search_into("python programming", k, Hit), expected);
+        }
+        assert!(index.search_into("missing", 10, Hit).is_empty());
+    }
+
+    #[test]
+    fn test_search_soa() {
+        let mut index = BM25Index::new().with_score_epsilon(1e-3);
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.add_document(4, "Python python tutorial");
+        index.build();
+
+        for k in [0, 2, 10] {
+            let results = index.search("python programming", k);
+            let soa = index.search_soa("python programming", k);
+            assert_eq!(soa.doc_ids, results.iter().map(|r| r.doc_id).collect::<Vec<_>>());
+            assert_eq!(soa.scores, results.iter().map(|r| r.score).collect::<Vec<_>>());
+        }
+        assert_eq!(index.search_soa("missing", 10), SearchResultsSoA::default());
+    }
+
+    #[test]
+    fn test_kth_score() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.add_document(4, "Python python tutorial");
+        index.build();
+
+        let query = "python programming";
+        for k in 1..=4 {
+            assert_eq!(index.kth_score(query, k), index.search(query, k).last().map(|r| r.score));
+        }
+        assert_eq!(index.kth_score(query, 5), None);
+        assert_eq!(index.kth_score(query, 0), None);
+        assert_eq!(index.kth_score("missing", 1), None);
+    }
+
+    #[test]
+    fn test_search_fast() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.add_document(4, "Cooking recipes");
+        index.build();
+
+        let query = "python programming";
+        let fast = index.search_fast(query, 10);
+        let mut fast_ids: Vec<u32> = fast.iter().map(|r| r.doc_id).collect();
+        assert_eq!(fast_ids, vec![1, 2, 3]);
+        assert_eq!(fast[0].score, 2.0);
+        assert_eq!(fast[1].score, 1.0);
+
+        let mut full_ids: Vec<u32> = index.search(query, 10).iter().map(|r| r.doc_id).collect();
+        fast_ids.sort();
+        full_ids.sort();
+        assert_eq!(fast_ids, full_ids);
+        assert!(index.search_fast("missing", 10).is_empty());
+    }
+
+    #[test]
+    fn test_score_pair() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document_with_prior(3, "Python data science", 1.5);
+        index.build();
+
+        for result in index.search("python programming", 10) {
+            assert_eq!(index.score_pair("python programming", result.doc_id), Some(result.score));
+        }
+        assert_eq!(index.score_pair("python", 2), Some(0.0));
+        assert_eq!(index.score_pair("python", 42), None);
+    }
+
+    #[test]
+    fn test_search_deadline() {
+        let mut index = BM25Index::new();
+        for doc_id in 0..20_000 {
+            index.add_document(doc_id, &format!("common word{}", doc_id % 97));
+        }
+        index.build();
+
+        let (results, partial) = index.search_deadline("common word5", 10, Duration::ZERO);
+        assert!(partial);
+        assert_eq!(results.len(), 10);
+
+        let (results, partial) = index.search_deadline("common word5", 10, Duration::from_secs(60));
+        assert!(!partial);
+        assert_eq!(results, index.search("common word5", 10));
+    }
+
+    #[test]
+    fn test_search_calibrated() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science python");
+        index.build();
+
+        let raw = index.search("python programming", 10);
+        let calibrated = index.search_calibrated("python programming", 10, (0.8, -1.0));
+        assert_eq!(raw.len(), calibrated.len());
+        for (raw, calibrated) in raw.iter().zip(&calibrated) {
+            assert_eq!((raw.doc_id, raw.rank), (calibrated.doc_id, calibrated.rank));
+            assert!(calibrated.score > 0.0 && calibrated.score < 1.0);
+            let expected = 1.0 / (1.0 + (-(0.8 * raw.score - 1.0)).exp());
+            assert!((calibrated.score - expected).abs() < 1e-6);
+        }
+        assert!(calibrated.windows(2).all(|pair| pair[0].score >= pair[1].score));
+    }
+
+    #[test]
+    fn test_union_postings() {
+        let mut index = BM25Index::new();
+        for doc_id in (0..300).rev() {
+            let mut text = String::from("filler");
+            for (divisor, word) in [(2, " even"), (3, " third"), (7, " seventh")] {
+                if doc_id % divisor == 0 {
+                    text.push_str(word);
+                }
+            }
+            index.add_document(doc_id, &text);
+        }
+        index.build();
+        index.add_document(1000, "even third"); // not yet built
+
+        for terms in [vec!["even", "third", "seventh"], vec!["seventh"], vec!["third", "missing", "third"], vec![]] {
+            let mut expected: Vec<u32> = terms
+                .iter()
+                .filter_map(|term| index.term_freqs.get(*term))
+                .flat_map(|docs| docs.keys().copied())
+                .collect::<AHashSet<u32>>()
+                .into_iter()
+                .collect();
+            expected.sort_unstable();
+            assert_eq!(index.union_postings(&terms).collect::<Vec<_>>(), expected, "{:?}", terms);
+        }
+    }
+
+    #[test]
+    fn test_max_candidates() {
+        let mut index = BM25Index::new().with_max_candidates(50);
+        for doc_id in 0..1000 {
+            let text = if doc_id % 100 == 7 { "common rare" } else { "common filler" };
+            index.add_document(doc_id, text);
+        }
+        index.build();
+
+        let scorer = index.scorer();
+        for query in ["common", "common rare", "rare common filler"] {
+            let query_terms = index.query_terms(query, &scorer);
+            assert!(index.collect_candidates(&query_terms).len() <= 50, "{}", query);
+        }
+        let results = index.search("common rare", 10);
+        assert_eq!(results.len(), 10);
+        assert!(results.iter().all(|r| r.doc_id % 100 == 7));
+        assert_eq!(index.candidate_count("rare"), 10);
+
+        // The cap is kept across a save/load round trip
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        let loaded = BM25Index::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.search("common", 1000).len(), 50);
+    }
+
+    #[test]
+    fn test_doc_similarity() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "rust borrow checker ownership rules");
+        index.add_document(2, "rust borrow checker lifetimes");
+        index.add_document(3, "rust cooking recipes");
+        index.add_document(4, "gardening tips");
+        index.build();
+
+        for a in 1..=4 {
+            let own = index.doc_similarity(a, a);
+            assert!(own > 0.0);
+            for b in (1..=4).filter(|&b| b != a) {
+                assert!(own > index.doc_similarity(a, b), "{} vs {}", a, b);
+            }
+        }
+        // Sharing three terms beats sharing one, which beats sharing none
+        assert!(index.doc_similarity(1, 2) > index.doc_similarity(1, 3));
+        assert!(index.doc_similarity(1, 3) > index.doc_similarity(1, 4));
+        assert_eq!(index.doc_similarity(1, 4), 0.0);
+        assert_eq!(index.doc_similarity(1, 42), 0.0);
+    }
+
+    #[test]
+    fn test_shared_vocabulary() {
+        let mut a = BM25Index::new();
+        a.add_document(1, "rust borrow checker");
+        let mut b = BM25Index::new();
+        b.add_document(1, "python garbage collector");
+        b.add_document(2, "rust ownership");
+
+        // A coordinator merges the shards' vocabularies
+        let mut vocab = a.export_vocabulary();
+        vocab.extend(b.export_vocabulary());
+        vocab.sort();
+        vocab.dedup();
+        a.import_vocabulary(&vocab);
+        b.import_vocabulary(&vocab);
+
+        for term in &vocab {
+            assert!(a.term_id(term).is_some());
+            assert_eq!(a.term_id(term), b.term_id(term), "{}", term);
+        }
+        assert_eq!(a.term_id("rust"), Some(vocab.iter().position(|t| t == "rust").unwrap() as TermId));
+        assert_eq!(a.term_id("python"), b.term_id("python")); // absent from `a`, same id
+        assert_eq!(a.term_id("missing"), None);
+        assert_eq!(a.export_vocabulary(), vocab);
+
+        let mut buf = Vec::new();
+        a.write_to(&mut buf).unwrap();
+        let loaded = BM25Index::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.term_id("ownership"), b.term_id("ownership"));
+    }
+
+    #[test]
+    fn test_document_terms() {
+        let text = "The cat sat on the mat; the cat slept.";
+        let mut index = BM25Index::new();
+        index.add_document(1, text);
+        index.add_document(2, "A dog sat too");
+
+        let mut expected: BTreeMap<String, u32> = BTreeMap::new();
+        for token in Tokenizer::default().tokenize(text) {
+            *expected.entry(token).or_insert(0) += 1;
+        }
+        let terms = index.document_terms(1);
+        assert_eq!(terms, expected.into_iter().collect::<Vec<_>>());
+        assert_eq!(terms[0], ("cat".to_string(), 2));
+        assert_eq!(terms.iter().map(|(_, count)| count).sum::<u32>(), index.doc_len(1).unwrap());
+        assert!(index.document_terms(42).is_empty());
+    }
+
+    #[test]
+    fn test_search_batch() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.build();
+
+        let queries = ["python", "programming rust", "", "missing", "data science python"];
+        let batched = index.search_batch(&queries, 2);
+        assert_eq!(batched.len(), queries.len());
+        for (query, results) in queries.iter().zip(batched) {
+            assert_eq!(results, index.search(query, 2));
+        }
+    }
+
+    #[test]
+    fn test_custom_scorer() {
+        /// Ignores tf and length, scoring each matched term by ln(N / df)
+        struct PureIdf;
+        impl Scorer for PureIdf {
+            fn score(&self, _tf: f32, _doc_len: f32, doc_freq: usize, ctx: &CorpusStats) -> f32 {
+                (ctx.doc_count as f32 / doc_freq as f32).ln()
+            }
+        }
+
+        let docs = [
+            (1, "python python python python"),
+            (2, "python rust and a long list of other filler words"),
+            (3, "java"),
+            (4, "golang"),
+        ];
+        let mut bm25 = BM25Index::new();
+        let mut idf_only = BM25Index::new().with_scorer(PureIdf);
+        for (doc_id, text) in docs {
+            bm25.add_document(doc_id, text);
+            idf_only.add_document(doc_id, text);
+        }
+        bm25.build();
+        idf_only.build();
+
+        // BM25 rewards the repeated term; pure IDF rewards matching both terms
+        assert_eq!(bm25.search("python rust", 2)[0].doc_id, 1);
+        let results = idf_only.search("python rust", 2);
+        assert_eq!(results[0].doc_id, 2);
+        assert!((results[0].score - (2.0f32.ln() + 4.0f32.ln())).abs() < 1e-5);
+        assert!((results[1].score - 2.0f32.ln()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_format_version_migration() {
+        let v1 = include_bytes!("../tests/fixtures/index_v1.jsonl");
+        assert_eq!(BM25Index::format_version(v1).unwrap(), 1);
+
+        let index = BM25Index::read_from(&v1[..]).unwrap();
+        assert_eq!(index.stats().num_docs, 2);
+        assert_eq!(index.search("python", 1)[0].chunk_id, "enwiki:Python#0");
+        assert_eq!(index.highlight_markers, ("<em>".to_string(), "</em>".to_string()));
+
+        let mut current = Vec::new();
+        index.write_to(&mut current).unwrap();
+        assert_eq!(BM25Index::format_version(&current).unwrap(), FORMAT_VERSION);
+
+        let future = String::from_utf8(current).unwrap().replacen(
+            &format!("\"format_version\":{}", FORMAT_VERSION),
+            "\"format_version\":99",
+            1,
+        );
+        assert_eq!(BM25Index::format_version(future.as_bytes()).unwrap(), 99);
+        match BM25Index::read_from(future.as_bytes()) {
+            Err(IndexError::UnsupportedVersion { found: 99, supported }) => assert_eq!(supported, FORMAT_VERSION),
+            other => panic!("expected UnsupportedVersion, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_fallback_ignore_stopwords() {
+        use crate::analyzer::{Analyzer, StopwordFilter};
+
+        let build = |fallback: bool| {
+            let analyzer = Analyzer::standard().with_filter(StopwordFilter::english());
+            let tokenizer = Tokenizer::default()
+                .with_analyzer(analyzer)
+                .with_fallback_ignore_stopwords(fallback);
+            let mut index = BM25Index::with_tokenizer(BM25Params::default(), tokenizer);
+            index.add_document(1, "To be or not to be");
+            index.add_document(2, "The tragedy of Hamlet");
+            index.build();
+            index
+        };
+
+        assert!(build(false).search("to be or not to be", 10).is_empty());
+        let results = build(true).search("to be or not to be", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc_id, 1);
+        // Queries with a non-stopword are unaffected
+        assert_eq!(build(true).search("the hamlet", 10)[0].doc_id, 2);
+    }
+
+    #[test]
+    fn test_docs_for_term() {
+        let mut index = BM25Index::new();
+        index.add_document(42, "Python programming language");
+        index.add_document(7, "Rust systems programming");
+        index.add_document(19, "Python data science");
+        assert_eq!(index.docs_for_term("python"), vec![19, 42]);
+
+        index.build();
+        assert_eq!(index.docs_for_term("python"), vec![19, 42]);
+        assert_eq!(index.docs_for_term("programming"), vec![7, 42]);
+        assert!(index.docs_for_term("missing").is_empty());
+    }
+
+    #[test]
+    fn test_search_with_global_stats() {
+        // "zebra" is in 3 of 4 docs in shard A but 3 of 100 docs overall
+        let mut shard_a = BM25Index::new();
+        for doc_id in 0..3 {
+            shard_a.add_document(doc_id, "zebra stripes savanna");
+        }
+        shard_a.add_document(3, "lion savanna");
+        shard_a.build();
+        let mut shard_b = BM25Index::new();
+        for doc_id in 100..196 {
+            shard_b.add_document(doc_id, "lion savanna grass");
+        }
+        shard_b.build();
+
+        let mut global = GlobalStats::default();
+        global.merge(&shard_a.global_stats());
+        global.merge(&shard_b.global_stats());
+        assert_eq!(global.doc_count, 100);
+        assert_eq!(global.df["zebra"], 3);
+        assert_eq!(global.df["savanna"], 100);
+
+        let local = shard_a.search("zebra", 1);
+        let results = shard_a.search_with_global_stats("zebra", 1, &global);
+        let scorer = BM25Scorer::new(shard_a.params, 11.0 / 4.0, 100);
+        let expected = scorer.score_term(1.0, 3.0, 3);
+        assert!((results[0].score - expected).abs() < 1e-5);
+        assert!(results[0].score > local[0].score);
+    }
+
+    #[test]
+    fn test_sparse_doc_ids() {
+        let mut index = BM25Index::new();
+        index.add_document(1000, "Python programming language");
+        index.add_document(5, "Rust systems programming");
+        index.add_document(99999, "Python data science");
+        index.build();
+
+        // Postings use dense ids in insertion order
+        let dense: Vec<u32> = index.postings["python"].iter().collect();
+        assert_eq!(dense, vec![0, 2]);
+        let dense: Vec<u32> = index.postings["programming"].iter().collect();
+        assert_eq!(dense, vec![0, 1]);
+
+        let doc_ids: Vec<u32> = index.search("python programming", 10).iter().map(|r| r.doc_id).collect();
+        assert_eq!(doc_ids, vec![1000, 5, 99999]);
+        assert_eq!(index.candidate_count("python rust"), 3);
+        assert_eq!(index.verify(), Ok(()));
+
+        // Documents added later get the next dense ids
+        index.add_document(7, "Python tutorial");
+        index.build_incremental();
+        let dense: Vec<u32> = index.postings["python"].iter().collect();
+        assert_eq!(dense, vec![0, 2, 3]);
+
+        let expected = index.search("python", 10);
+        let frozen = index.freeze();
+        assert_eq!(frozen.search("python", 10), expected);
+    }
+
+    #[test]
+    fn test_posting_lens() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "the quick brown fox");
+        index.add_document(2, "the lazy dog");
+        index.add_document(3, "quick brown dog");
+        index.build();
+
+        assert_eq!(index.posting_len("the"), 2);
+        assert_eq!(index.posting_len("quick"), 2);
+        assert_eq!(index.posting_len("fox"), 1);
+        assert_eq!(index.posting_len("cat"), 0);
+
+        let lens = index.posting_lens(&["dog", "lazy", "cat"]);
+        assert_eq!(lens.len(), 3);
+        assert_eq!(lens["dog"], 2);
+        assert_eq!(lens["lazy"], 1);
+        assert_eq!(lens["cat"], 0);
+    }
+
+    #[test]
+    fn test_search_structured_occur() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "python programming language");
+        index.add_document(2, "python snake species");
+        index.add_document(3, "rust programming language");
+        index.add_document(4, "cooking recipes");
+        index.build();
+
+        let ids = |clauses: &[QueryTerm]| {
+            let mut ids: Vec<u32> = index.search_structured(clauses, 10).iter().map(|r| r.doc_id).collect();
+            ids.sort_unstable();
+            ids
+        };
+        let must = |text: &str| QueryTerm::new(text, Occur::Must);
+        let should = |text: &str| QueryTerm::new(text, Occur::Should);
+        let must_not = |text: &str| QueryTerm::new(text, Occur::MustNot);
+
+        assert_eq!(ids(&[should("python"), should("rust")]), vec![1, 2, 3]);
+        assert_eq!(ids(&[must("python"), must("programming")]), vec![1]);
+        assert_eq!(ids(&[must("programming language")]), vec![1, 3]);
+        assert_eq!(ids(&[must("programming"), should("python")]), vec![1, 3]);
+        assert_eq!(ids(&[should("python"), must_not("snake")]), vec![1]);
+        assert_eq!(ids(&[must("language"), must_not("rust")]), vec![1]);
+        assert_eq!(ids(&[must("language"), should("snake"), must_not("python")]), vec![3]);
+        assert!(ids(&[must_not("python")]).is_empty());
+        assert!(ids(&[must("python"), must("missing")]).is_empty());
+
+        // An optional clause only reorders the required matches
+        let results = index.search_structured(&[must("programming"), should("rust")], 10);
+        assert_eq!(results[0].doc_id, 3);
+        assert_eq!(
+            index.search_structured(&[should("python programming")], 10),
+            index.search("python programming", 10)
+        );
+    }
+
+    #[test]
+    fn test_search_structured_repeated_terms() {
+        for query_tf in [QueryTfMode::Binary, QueryTfMode::Linear] {
+            let params = BM25Params {
+                query_tf,
+                ..BM25Params::default()
+            };
+            let mut index = BM25Index::with_params(params);
+            index.add_document(1, "python programming language");
+            index.add_document(2, "python snake");
+            index.add_document(3, "rust programming");
+            index.build();
+
+            let should = |text: &str| QueryTerm::new(text, Occur::Should);
+            let expected = index.search("python python programming", 10);
+            assert_eq!(index.search_structured(&[should("python python programming")], 10), expected);
+            assert_eq!(
+                index.search_structured(&[should("python"), should("python"), should("programming")], 10),
+                expected
+            );
+            assert_eq!(
+                index.search_structured(&[QueryTerm::new("python", Occur::Must), should("python programming")], 10),
+                index
+                    .search("python python programming", 10)
+                    .into_iter()
+                    .filter(|r| r.doc_id != 3)
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_search_structured_boost() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "python tutorial");
+        index.add_document(2, "rust tutorial");
+        index.build();
+
+        let plain = [QueryTerm::new("python", Occur::Should), QueryTerm::new("rust", Occur::Should)];
+        let results = index.search_structured(&plain, 10);
+        assert_eq!(results[0].score, results[1].score);
+
+        let boosted = [
+            QueryTerm::new("python", Occur::Should),
+            QueryTerm::new("rust", Occur::Should).with_boost(3.0),
+        ];
+        let results = index.search_structured(&boosted, 10);
+        assert_eq!(results[0].doc_id, 2);
+        assert!((results[0].score - 3.0 * results[1].score).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_search_boolean() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language tutorial");
+        index.add_document(2, "The python is a large snake, not a programming language");
+        index.add_document(3, "Snake charming");
+        index.build();
+
+        let ids = |results: Vec<SearchResult>| results.iter().map(|r| r.doc_id).collect::<Vec<_>>();
+        assert_eq!(ids(index.search_boolean("python -snake", 10)), vec![1]);
+        assert_eq!(ids(index.search_boolean("python -Snake -charming", 10)), vec![1]);
+        assert_eq!(
+            index.search_boolean("python programming", 10),
+            index.search("python programming", 10)
+        );
+        assert_eq!(ids(index.search_boolean("python - snake", 10)).len(), 3);
+        assert!(index.search_boolean("-python", 10).is_empty());
+
+        // Plain search reads the dash literally, so "-snake" still matches
+        assert_eq!(ids(index.search("python -snake", 10)).len(), 3);
+    }
+
+    #[test]
+    fn test_candidate_count() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.add_document(4, "Cooking recipes");
+        index.build();
+
+        assert_eq!(index.candidate_count("python"), 2);
+        assert_eq!(index.candidate_count("python programming"), 3);
+        assert_eq!(index.candidate_count("python rust missing"), 3);
+        assert_eq!(index.candidate_count("missing"), 0);
+        assert_eq!(index.candidate_count("python programming"), index.search("python programming", 10).len());
+        assert_eq!(index.candidates("python programming"), vec![1, 2, 3]);
+        assert!(index.candidates("missing").is_empty());
+
+        // Stale postings fall back to the term frequency maps
+        index.add_document(5, "Python cooking");
+        assert_eq!(index.candidate_count("python cooking"), 4);
+    }
+
+    #[test]
+    fn test_build_pruned() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "the quick brown fox");
+        index.add_document(2, "the lazy dog");
+        index.add_document(3, "quick brown dog");
+        index.build_pruned(2);
+
+        assert_eq!(index.posting_len("fox"), 0);
+        assert!(index.search("fox", 10).is_empty());
+        assert_eq!(index.search("quick", 10).len(), 2);
+        // the, quick, brown, dog
+        assert_eq!(index.stats().num_terms, 4);
+    }
+
+    #[test]
+    fn test_sample_docs_seeded() {
+        let mut index = BM25Index::new();
+        for doc_id in 0..100 {
+            index.add_document(doc_id, "sample document");
+        }
+
+        let sample = index.sample_docs(10, 42);
+        assert_eq!(sample.len(), 10);
+        assert_eq!(sample, index.sample_docs(10, 42));
+        assert_ne!(sample, index.sample_docs(10, 43));
+
+        let mut unique = sample.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), 10);
+
+        assert_eq!(index.sample_docs(1000, 42).len(), 100);
+    }
+
+    #[test]
+    fn test_vocabulary() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "the quick brown fox");
+        index.add_document(2, "the lazy dog");
+        index.build();
+
+        assert_eq!(index.vocabulary().count(), index.stats().num_terms);
+        let vocab: HashMap<&str, usize> = index.vocabulary().collect();
+        assert_eq!(vocab["the"], 2);
+        assert_eq!(vocab["fox"], 1);
+    }
+
+    #[test]
+    fn test_contains_term() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.build();
+
+        assert!(index.contains_term("python"));
+        assert!(!index.contains_term("Python"));
+        assert!(!index.contains_term("rust"));
+
+        assert!(index.contains_tokenized_query("Python"));
+        assert!(index.contains_tokenized_query("Rust or Python"));
+        assert!(!index.contains_tokenized_query("Rust systems"));
+        assert!(!index.contains_tokenized_query(""));
+    }
+
+    #[test]
+    fn test_search_timed() {
+        let mut index = BM25Index::new();
+        for doc_id in 0..200 {
+            index.add_document(doc_id, &format!("document {} about python and rust", doc_id));
+        }
+        index.build();
+
+        let (results, timings) = index.search_timed("python rust", 10);
+        assert_eq!(results, index.search("python rust", 10));
+
+        let phases = timings.tokenize + timings.candidates + timings.scoring + timings.sort;
+        assert_eq!(phases, timings.total);
+    }
+
+    #[test]
+    fn test_benchmark() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.build();
+
+        let report = index.benchmark(&["python", "rust programming", "missing"], 10, 20);
+        assert!(report.p50 > Duration::ZERO);
+        assert!(report.p50 <= report.p95);
+        assert!(report.p95 <= report.p99);
+        assert!(report.mean > Duration::ZERO);
+
+        let empty = index.benchmark(&[], 10, 20);
+        assert_eq!(empty.p99, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_empty_query() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "test document");
+        index.build();
+
+        let results = index.search("", 10);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_no_results() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming");
+        index.build();
+
+        let results = index.search("JavaScript", 10);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_search_multi_accumulates() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "alpha beta");
+        index.add_document(2, "gamma delta");
+        index.add_document(3, "epsilon zeta");
+        index.build();
+
+        let results = index.search_multi(&[("alpha", 1.0), ("beta", 1.0), ("gamma", 1.5)], 10);
+        assert_eq!(results.len(), 2);
+
+        // Every term occurs once in a two-token doc with df=1, so each
+        // single-term score is identical: doc 1 gets 2x, doc 2 gets 1.5x.
+        let single = index.search("alpha", 1)[0].score;
+        assert_eq!(results[0].chunk_id, "chunk_1");
+        assert!((results[0].score - 2.0 * single).abs() < 1e-5);
+        assert_eq!(results[1].chunk_id, "chunk_2");
+        assert!((results[1].score - 1.5 * single).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_emoji_search() {
+        let tokenizer = Tokenizer::default().with_keep_symbols(true);
+        let mut index = BM25Index::with_tokenizer(BM25Params::default(), tokenizer);
+        index.add_document(1, "the crowd reacted with 🔥 everywhere");
+        index.add_document(2, "the crowd was quiet");
+        index.build();
+
+        let results = index.search("🔥", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].chunk_id, "chunk_1");
+    }
+
+    #[test]
+    fn test_split_on_search() {
+        let tokenizer = Tokenizer::default().with_split_on(vec!['_']);
+        let mut index = BM25Index::with_tokenizer(BM25Params::default(), tokenizer);
+        index.add_document(1, "error in request_handler");
+        index.add_document(2, "request completed");
+        index.build();
+
+        let results = index.search("handler", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].chunk_id, "chunk_1");
+    }
+
+    #[test]
+    fn test_hyphen_mode_search() {
+        let build = |hyphen_mode: HyphenMode| {
+            let tokenizer = Tokenizer::default().with_hyphen_mode(hyphen_mode);
+            let mut index = BM25Index::with_tokenizer(BM25Params::default(), tokenizer);
+            index.add_document(1, "a state-of-the-art retriever");
+            index.add_document(2, "a classic retriever");
+            index.build();
+            index
+        };
+
+        let index = build(HyphenMode::Both);
+        assert_eq!(index.search("stateoftheart", 10)[0].chunk_id, "chunk_1");
+        assert_eq!(index.search("state-of-the-art", 10)[0].chunk_id, "chunk_1");
+
+        let index = build(HyphenMode::Split);
+        assert_eq!(index.search("art", 10)[0].chunk_id, "chunk_1");
+        assert!(index.search("stateoftheart", 10).is_empty());
+    }
+
+    #[test]
+    fn test_search_grouped() {
+        let mut index = BM25Index::new();
+        index.add_document_grouped(1, "Python", "python python language");
+        index.add_document_grouped(2, "Python", "python python python interpreter");
+        index.add_document_grouped(3, "Python", "python typing");
+        index.add_document_grouped(4, "Monty", "monty python comedy");
+        index.add_document(5, "python snake");
+        index.build();
+
+        assert_eq!(index.search("python", 10).len(), 5);
+
+        let results = index.search_grouped("python", 10, 1);
+        assert_eq!(results.len(), 3);
+        let python_chunks = results
+            .iter()
+            .filter(|r| ["chunk_1", "chunk_2", "chunk_3"].contains(&r.chunk_id.as_str()))
+            .count();
+        assert_eq!(python_chunks, 1);
+        assert_eq!(results.iter().map(|r| r.rank).collect::<Vec<_>>(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_search_top_percentile() {
+        // Equal-length docs where doc i contains the query term i times, so
+        // scores strictly increase with doc_id; doc 11 ties doc 10.
+        let mut index = BM25Index::new();
+        for i in 1..=10 {
+            index.add_document(i, &format!("{}{}", "xx ".repeat(i as usize), "pad ".repeat(10 - i as usize)));
+        }
+        index.add_document(11, &"xx ".repeat(10));
+        index.build();
+
+        let ids = |percentile: f32| {
+            let mut ids: Vec<u32> = index.search_top_percentile("xx", percentile).iter().map(|r| r.doc_id).collect();
+            ids.sort_unstable();
+            ids
+        };
+
+        assert_eq!(ids(0.0).len(), 11);
+        assert_eq!(ids(0.8), vec![9, 10, 11]);
+        // Cutoff lands on doc 11's score; tied doc 10 is included
+        assert_eq!(ids(0.95), vec![10, 11]);
+        assert_eq!(ids(1.0), vec![10, 11]);
+        assert!(index.search_top_percentile("missing", 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_warm_up() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.build();
+        let cold = index.search("python programming", 10);
+
+        index.warm_up();
+        let warm = index.warm.as_ref().unwrap();
+        assert_eq!(warm.idf.len(), index.term_freqs.len());
+        assert_eq!(warm.upper_bounds.len(), index.term_freqs.len());
+        assert!(warm.upper_bounds.values().all(|&bound| bound > 0.0));
+        assert_eq!(warm.sorted_terms.first().map(String::as_str), Some("data"));
+        assert_eq!(warm.doc_positions.len(), 3);
+        assert_eq!(index.sorted_terms().len(), index.term_freqs.len());
+        assert!(index.term_upper_bound("python") > 0.0);
+        assert_eq!(index.term_upper_bound("missing"), 0.0);
+        assert_eq!(index.search("python programming", 10), cold);
+
+        index.add_document(4, "Go programming");
+        assert!(index.warm.is_none());
+    }
+
+    #[test]
+    fn test_search_msm() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "python programming language");
+        index.add_document(2, "python snake");
+        index.add_document(3, "rust programming language");
+        index.build();
+
+        let ids = |msm: usize| {
+            let mut ids: Vec<u32> = index
+                .search_msm("python programming language", 10, msm)
+                .iter()
+                .map(|r| r.doc_id)
+                .collect();
+            ids.sort_unstable();
+            ids
+        };
+        assert_eq!(ids(1), vec![1, 2, 3]);
+        assert_eq!(ids(2), vec![1, 3]);
+        assert_eq!(ids(3), vec![1]);
+    }
+
+    #[test]
+    fn test_search_within() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "python python python");
+        index.add_document(2, "python programming language");
+        index.add_document(3, "python snake");
+        index.add_document(4, "rust programming");
+        index.build();
+        assert_eq!(index.search("python", 1)[0].doc_id, 1);
+
+        let allowed: AHashSet<u32> = [2, 3, 4].into_iter().collect();
+        let results = index.search_within("python", 2, &allowed);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.doc_id != 1));
+        assert_eq!(results[0].rank, 0);
+    }
+
+    #[test]
+    fn test_search_set_similarity() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "machine learning");
+        index.add_document(2, "machine learning for robotics and vision");
+        index.add_document(3, "robotics");
+        index.build();
+
+        let results = index.search_set_similarity("Machine Learning", 10, SetSimilarity::Jaccard);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].doc_id, 1);
+        assert!((results[0].score - 1.0).abs() < 1e-6);
+        // 2 shared of 6 distinct terms (min_length drops nothing here)
+        assert!((results[1].score - 2.0 / 6.0).abs() < 1e-6);
+
+        let results = index.search_set_similarity("robotics", 10, SetSimilarity::Overlap);
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| (r.score - 1.0).abs() < 1e-6));
+
+        let results = index.search_set_similarity("machine learning", 10, SetSimilarity::Dice);
+        assert!((results[1].score - 4.0 / 8.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_document_priors() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Rust programming language");
+        index.add_document_with_prior(2, "Rust programming language", 2.0);
+        index.add_document(3, "Python programming language");
+        index.build();
+
+        let results = index.search("rust", 10);
+        assert_eq!(results[0].doc_id, 2);
+        assert!((results[0].score - 2.0 * results[1].score).abs() < 1e-5);
+
+        let params = BM25Params { prior_mode: PriorMode::Additive, ..BM25Params::default() };
+        let mut index = BM25Index::with_params(params);
+        index.add_document_with_prior(1, "Rust programming language", -0.5);
+        index.add_document(2, "Rust programming language");
+        index.build();
+
+        let results = index.search("rust", 10);
+        assert_eq!(results[0].doc_id, 2);
+        assert!((results[0].score - results[1].score - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_match_positions() {
+        let text = "Rust is fast. Rust, unlike C, is memory-safe; rusty tools aside.";
+        let mut index = BM25Index::new().with_store_text(true);
+        index.add_document(1, text);
+        index.add_document(2, "Python is slow");
+        index.build();
+
+        let spans = index.match_positions(1, "rust memory");
+        assert_eq!(spans.len(), 3);
+        assert!(spans.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let query_tokens = index.tokenizer.tokenize_unique("rust memory");
+        for &(start, end) in &spans {
+            let token = index.tokenizer.tokenize(&text[start..end]);
+            assert_eq!(token.len(), 1);
+            assert!(query_tokens.contains(&token[0]));
+        }
+        assert!(index.match_positions(2, "rust").is_empty());
+
+        // Spans are rebuilt from the stored text on load
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        let loaded = BM25Index::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.match_positions(1, "rust memory"), spans);
+        assert_eq!(loaded.stored_text(1), Some(text));
+
+        // Appended text extends the stored text and its positions
+        let mut index = BM25Index::new().with_store_text(true);
+        index.add_document(1, text);
+        index.append_to_document(1, "Rust again");
+        let full = index.stored_text(1).unwrap();
+        let spans = index.match_positions(1, "rust");
+        assert_eq!(spans.len(), 3);
+        assert_eq!(&full[spans[2].0..spans[2].1], "Rust");
+
+        // Without store_text nothing is kept
+        let mut index = BM25Index::new();
+        index.add_document(1, text);
+        assert!(index.match_positions(1, "rust").is_empty());
+    }
+
+    #[test]
+    fn test_remove_document() {
+        let mut index = BM25Index::new().with_store_text(true);
+        index.add_document_with_external_id(1, "c1", "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Go concurrency language");
+        index.build();
+
+        assert!(index.remove_document(1));
+        assert!(!index.remove_document(1));
+        assert!(!index.contains_term("python"));
+        assert_eq!(index.resolve_id("c1"), None);
+        assert_eq!(index.stored_text(1), None);
+        assert_eq!(index.total_doc_len, 6);
+        index.build_incremental();
+        assert_eq!(index.verify(), Ok(()));
+        assert_eq!(index.docs_for_term("language"), vec![3]);
+        assert_eq!(index.search("language", 10)[0].doc_id, 3);
+        assert_eq!(index.search("programming", 10)[0].doc_id, 2);
+    }
+
+    #[test]
+    fn test_bigrams() {
+        let mut index = BM25Index::new().with_bigrams(true);
+        index.add_document(1, "I moved to New York last year");
+        index.add_document(2, "York is older than anything new in town");
+        index.add_document(3, "Boston in the fall");
+        index.build();
+
+        assert!(index.contains_term("new_york"));
+        assert_eq!(index.doc_len(1), Some(6));
+        assert_eq!(index.stats().avg_doc_len, (6 + 8 + 4) as f32 / 3.0);
+        let results = index.search("new york", 10);
+        assert_eq!(results.iter().map(|r| r.doc_id).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(results[0].score > 2.0 * results[1].score);
+
+        let mut plain = BM25Index::new();
+        plain.add_document(1, "I moved to New York last year");
+        plain.add_document(2, "York is older than anything new in town");
+        assert!(!plain.contains_term("new_york"));
+
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        let loaded = BM25Index::read_from(&buf[..]).unwrap();
+        assert_eq!(loaded.search("new york", 10), results);
+    }
+
+    #[test]
+    fn test_search_mmr() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "rust borrow checker ownership rules");
+        index.add_document(2, "rust borrow checker ownership rules explained");
+        index.add_document(3, "rust async runtime with tokio");
+        index.add_document(4, "python garbage collection");
+        index.build();
+
+        let ids = |results: Vec<SearchResult>| results.iter().map(|r| r.doc_id).collect::<Vec<_>>();
+        assert_eq!(ids(index.search("rust borrow", 10)), vec![1, 2, 3]);
+        assert_eq!(index.search_mmr("rust borrow", 10, 1.0), index.search("rust borrow", 10));
+
+        // The near-duplicate of the top result drops below the distinct match
+        let results = index.search_mmr("rust borrow", 10, 0.5);
+        assert_eq!(ids(results.clone()), vec![1, 3, 2]);
+        assert_eq!(results.iter().map(|r| r.rank).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(ids(index.search_mmr("rust borrow", 2, 0.5)), vec![1, 3]);
+        assert!(index.search_mmr("haskell", 10, 0.5).is_empty());
+    }
+
+    #[test]
+    fn test_drop_postings() {
         let mut index = BM25Index::new();
-        index.add_document(1, "the quick brown fox");
-        index.add_document(2, "the lazy dog");
-        index.add_document(3, "quick brown dog");
+        index.add_document(1, "rust ownership and borrowing");
+        index.add_document(2, "python garbage collection");
         index.build();
+        let before = index.search("rust borrowing", 10);
 
         let stats = index.stats();
-        assert_eq!(stats.num_docs, 3);
-        assert!(stats.num_terms > 0);
+        index.drop_postings();
+        assert!(index.postings.is_empty());
+        assert!(!index.is_built());
+        assert_eq!(index.stats().num_terms, stats.num_terms);
+        assert_eq!(index.stats().num_docs, stats.num_docs);
+        assert_eq!(index.search("rust borrowing", 10), before);
+        index.add_document(3, "rust compiler");
+        assert_eq!(index.search("rust", 10).len(), 2);
+
+        index.build();
+        assert!(index.is_built());
+        assert_eq!(index.docs_for_term("rust"), vec![1, 3]);
+        assert_eq!(index.verify(), Ok(()));
     }
 
     #[test]
-    fn test_search() {
+    fn test_decay() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "chat chat chat chat chat chat chat chat hello");
+        index.add_document(2, "chat chat world");
+        index.build();
+
+        index.decay(0.5);
+        assert_eq!(index.term_freqs["chat"][&1], 4);
+        assert_eq!(index.term_freqs["chat"][&2], 1);
+        assert!(!index.contains_term("hello"));
+        assert!(!index.contains_term("world"));
+
+        index.decay(0.5);
+        assert_eq!(index.term_freqs["chat"][&1], 2);
+        assert!(!index.is_built());
+        index.build_incremental();
+        assert!(index.is_built());
+        assert_eq!(index.docs_for_term("chat"), vec![1]);
+        assert_eq!(index.search("chat", 10).len(), 1);
+
+        for _ in 0..2 {
+            index.decay(0.5);
+        }
+        assert!(index.term_freqs.is_empty());
+        assert!(index.search("chat", 10).is_empty());
+        assert_eq!(index.verify(), Ok(()));
+
+        // A term decayed out of one document leaves its positions and counts too
+        let mut index = BM25Index::new().with_store_text(true);
+        index.add_document(1, "alpha alpha beta");
+        index.add_document(2, "alpha beta beta");
+        assert_eq!(index.search_phrase("alpha beta", 10, 0).len(), 2);
+        index.decay(0.5);
+        index.build();
+        assert_eq!(index.search("beta", 10)[0].doc_id, 2);
+        assert_eq!(index.search("beta", 10).len(), 1);
+        assert!(index.search_phrase("alpha beta", 10, 0).is_empty());
+        assert!(index.match_positions(1, "beta").is_empty());
+        assert_eq!(index.highlight(1, "beta").as_deref(), Some("alpha alpha beta"));
+        assert_eq!(index.highlight(1, "alpha").as_deref(), Some("<em>alpha</em> <em>alpha</em> beta"));
+        assert_eq!(index.doc_meta(1).unwrap().unique_terms(&index.long_docs), 1);
+        assert_eq!(index.doc_meta(2).unwrap().unique_terms(&index.long_docs), 1);
+        assert_eq!(index.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_add_document_counts() {
+        let docs = [
+            (1, "the cat sat on the mat"),
+            (2, "the dog chased the cat"),
+            (3, "birds sing at dawn"),
+        ];
+        let mut from_text = BM25Index::new();
+        let mut from_counts = BM25Index::new();
+        for (doc_id, text) in docs {
+            from_text.add_document(doc_id, text);
+            let (doc_len, counts) = count_terms(from_text.tokenizer().tokenize(text), false);
+            let counts: Vec<(&str, u32)> = counts.iter().map(|(term, &count)| (term.as_str(), count)).collect();
+            assert!(from_counts.add_document_counts(doc_id, &counts, doc_len));
+        }
+        from_text.build();
+        from_counts.build();
+
+        for query in ["cat", "the cat", "dawn birds", "missing"] {
+            assert_eq!(from_counts.search(query, 10), from_text.search(query, 10));
+        }
+        assert_eq!(from_counts.stats().avg_doc_len, from_text.stats().avg_doc_len);
+
+        assert!(from_counts.add_document_counts(4, &[("cat", 1), ("cat", 2), ("dog", 0)], 10));
+        assert_eq!(from_counts.document_terms(4), vec![("cat".to_string(), 3)]);
+        assert_eq!(from_counts.doc_len(4), Some(10));
+        assert!(!from_counts.add_document_counts(5, &[("cat", 1)], 0));
+        assert!(!from_counts.add_document_counts(5, &[("cat", 0)], 3));
+    }
+
+    #[test]
+    fn test_add_mode_replace() {
+        let mut index = BM25Index::new();
+        index.add_document_with_payload(1, "python programming language", "old".to_string());
+        index.add_document(2, "rust programming");
+        index.build();
+
+        assert_eq!(index.add_document_mode(1, "snake species", AddMode::Replace), Ok(true));
+        assert_eq!(index.stats().num_docs, 2);
+        assert_eq!(index.doc_len(1), Some(2));
+        assert!(!index.contains_term("python"));
+        assert_eq!(index.docs_for_term("programming"), vec![2]);
+        assert_eq!(index.payload(1), None);
+        assert_eq!(index.search("snake", 10)[0].doc_id, 1);
+        assert_eq!(index.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_add_mode_accumulate() {
+        let mut index = BM25Index::new();
+        assert_eq!(index.add_document_mode(1, "python programming", AddMode::Accumulate), Ok(true));
+        assert_eq!(index.add_document_mode(1, "python snakes", AddMode::Accumulate), Ok(true));
+        index.build();
+
+        assert_eq!(index.stats().num_docs, 1);
+        assert_eq!(index.doc_len(1), Some(4));
+        assert_eq!(index.term_freqs["python"][&1], 2);
+        assert_eq!(index.document_terms(1).len(), 3);
+        assert_eq!(index.stats().avg_doc_len, 4.0);
+        assert_eq!(index.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_add_mode_reject() {
+        let mut index = BM25Index::new();
+        assert_eq!(index.add_document_mode(1, "python programming", AddMode::Reject), Ok(true));
+        assert_eq!(
+            index.add_document_mode(1, "snake species", AddMode::Reject),
+            Err(DuplicateDocument { doc_id: 1 })
+        );
+        assert_eq!(index.stats().num_docs, 1);
+        assert!(!index.contains_term("snake"));
+        assert_eq!(index.doc_len(1), Some(2));
+    }
+
+    #[test]
+    fn test_reindex_with() {
+        let mut index = BM25Index::new().with_store_text(true);
+        index.add_document_with_external_id(1, "enwiki:Go#0", "Go is an open source programming language");
+        index.add_document(2, "Rust is a systems language");
+        index.add_document(3, "C or Go");
+        index.build();
+        let vocab_before = index.vocabulary().count();
+
+        index.reindex_with(Tokenizer::new(4)).unwrap();
+        assert!(index.vocabulary().count() < vocab_before);
+        assert!(!index.contains_term("go"));
+        assert!(!index.contains_term("is"));
+        assert!(index.contains_term("language"));
+        assert_eq!(index.doc_len(1), Some(4));
+        assert_eq!(index.doc_len(3), None); // nothing left of it
+        assert_eq!(index.stats().num_docs, 2);
+        assert_eq!(index.search("programming", 10)[0].chunk_id, "enwiki:Go#0");
+        assert_eq!(index.verify(), Ok(()));
+        assert!(index.is_built());
+
+        let mut unstored = BM25Index::new();
+        unstored.add_document(7, "Python programming");
+        assert_eq!(unstored.reindex_with(Tokenizer::default()), Err(TextNotStored { doc_id: 7 }));
+        assert!(unstored.contains_term("python"));
+    }
+
+    #[test]
+    fn test_recompute_stats() {
+        let texts = ["alpha beta", "beta gamma delta", "gamma", "alpha alpha epsilon", "delta beta zeta eta"];
+        let mut index = BM25Index::new();
+        for round in 0..50u32 {
+            for (i, text) in texts.iter().enumerate() {
+                index.add_document(round * 10 + i as u32, text);
+            }
+            index.build_incremental();
+            index.warm_up();
+            for i in 0..texts.len() as u32 {
+                if round < 49 || i % 2 == 1 {
+                    index.remove_document(round * 10 + i);
+                }
+            }
+        }
+        index.total_doc_len += 7; // simulate accumulated drift
+        index.recompute_stats();
+        index.build_incremental();
+
+        let mut fresh = BM25Index::new();
+        for i in (0..texts.len() as u32).filter(|i| i % 2 == 0) {
+            fresh.add_document(490 + i, texts[i as usize]);
+        }
+        fresh.build();
+
+        assert_eq!(index.total_doc_len, fresh.total_doc_len);
+        assert_eq!(index.stats().num_docs, fresh.stats().num_docs);
+        assert_eq!(index.stats().num_terms, fresh.stats().num_terms);
+        assert_eq!(index.stats().avg_doc_len, fresh.stats().avg_doc_len);
+        assert_eq!(index.verify(), Ok(()));
+        for query in ["alpha", "beta gamma", "delta zeta", "epsilon"] {
+            assert_eq!(index.query_idfs(query), fresh.query_idfs(query));
+            assert_eq!(index.search(query, 10), fresh.search(query, 10));
+        }
+    }
+
+    #[test]
+    fn test_evict_oldest_removes_doc_terms() {
+        let docs = [
+            "Python programming language",
+            "Rust programming",
+            "Go language",
+            "Java programming language",
+        ];
+        let mut index = BM25Index::new()
+            .with_store_text(true)
+            .with_max_docs(2, CapacityPolicy::EvictOldest);
+        let mut expected = BM25Index::new().with_store_text(true);
+        for (doc_id, text) in docs.iter().enumerate() {
+            index.add_document(doc_id as u32, text);
+            index.build_incremental();
+        }
+        index.append_to_document(3, "coffee");
+        for (doc_id, text) in docs.iter().enumerate().skip(2) {
+            expected.add_document(doc_id as u32, text);
+        }
+        expected.append_to_document(3, "coffee");
+        expected.build();
+
+        assert_eq!(index.verify(), Ok(()));
+        assert_eq!(index.doc_terms.len(), 2);
+        assert_eq!(index.doc_terms[&3].len(), 4);
+        let (stats, expected_stats) = (index.stats(), expected.stats());
+        assert_eq!(stats.num_docs, expected_stats.num_docs);
+        assert_eq!(stats.num_terms, expected_stats.num_terms);
+        assert_eq!(stats.avg_doc_len, expected_stats.avg_doc_len);
+        for query in ["python", "rust", "language", "programming coffee"] {
+            assert_eq!(index.search(query, 10), expected.search(query, 10));
+            assert_eq!(index.search_phrase(query, 10, 0), expected.search_phrase(query, 10, 0));
+        }
+        assert!(index.positions.values().all(|docs| docs.keys().all(|&doc_id| doc_id >= 2)));
+        assert!(!index.positions.contains_key("python"));
+
+        // Without a cap no term lists are kept
+        assert!(expected.doc_terms.is_empty());
+    }
+
+    #[test]
+    fn test_max_docs() {
+        // Reject: the add fails and the index is unchanged
+        let mut index = BM25Index::new().with_max_docs(2, CapacityPolicy::Reject);
+        assert!(index.add_document(1, "Python programming"));
+        assert_eq!(index.try_add_document(2, "Rust programming"), Ok(true));
+        assert!(!index.add_document(3, "Go programming"));
+        assert_eq!(index.try_add_document(3, "Go programming"), Err(DocLimitReached { max_docs: 2 }));
+        index.build();
+        assert_eq!(index.stats().num_docs, 2);
+        assert!(index.search("go", 10).is_empty());
+
+        // EvictOldest: each add past the cap drops the earliest document
+        let mut index = BM25Index::new().with_max_docs(2, CapacityPolicy::EvictOldest);
+        index.add_document(1, "Python programming");
+        index.add_document(2, "Rust programming language");
+        index.build();
+        assert_eq!(index.try_add_document(3, "Go programming"), Ok(true));
+        index.build_incremental();
+        let stats = index.stats();
+        assert_eq!(stats.num_docs, 2);
+        assert_eq!(stats.avg_doc_len, 2.5);
+        assert!(index.search("python", 10).is_empty());
+        assert_eq!(index.search("go", 10)[0].doc_id, 3);
+        assert!(!index.add_document(4, "a")); // empty documents never evict
+        index.add_document(4, "Java programming");
+        index.build_incremental();
+        assert_eq!(index.docs_for_term("programming"), vec![3, 4]);
+        assert_eq!(index.verify(), Ok(()));
+
+        // The cap and eviction order survive a reload
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        let mut loaded = BM25Index::read_from(buf.as_slice()).unwrap();
+        loaded.add_document(5, "Zig programming");
+        loaded.build_incremental();
+        assert_eq!(loaded.docs_for_term("programming"), vec![4, 5]);
+    }
+
+    #[test]
+    fn test_is_built() {
+        let mut index = BM25Index::new();
+        assert!(!index.is_built());
+        index.build();
+        assert!(!index.is_built()); // nothing to build
+
+        index.add_document(1, "Python programming language");
+        assert!(!index.is_built());
+        index.build();
+        assert!(index.is_built());
+
+        index.add_document(2, "Rust systems programming");
+        assert!(!index.is_built());
+        index.build_incremental();
+        assert!(index.is_built());
+        index.remove_document(1);
+        assert!(!index.is_built());
+        index.build_incremental();
+        assert!(index.is_built());
+    }
+
+    #[test]
+    fn test_best_span() {
+        let text = "Intro about the weather and travel plans for the summer. \
+                    Rust ownership rules make the Rust borrow checker strict. \
+                    Closing remarks on cooking and gardening with friends.";
+        let mut index = BM25Index::new().with_store_text(true);
+        index.add_document(1, text);
+        index.add_document(2, "Unrelated filler text");
+        index.build();
+
+        let (start, end, score) = index.best_span(1, "rust borrow checker", 8).unwrap();
+        assert_eq!(&text[start..end], "Rust ownership rules make the Rust borrow checker");
+        assert!(score > 0.0);
+
+        // A narrower window keeps only the densest cluster
+        let (start, end, _) = index.best_span(1, "rust borrow checker", 3).unwrap();
+        assert_eq!(&text[start..end], "Rust borrow checker");
+
+        assert_eq!(index.best_span(1, "python", 8), None);
+        assert_eq!(index.best_span(2, "rust", 8), None);
+    }
+
+    #[test]
+    fn test_append_to_document() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.build();
+        assert!(index.search("borrow", 10).is_empty());
+
+        assert!(index.append_to_document(2, "borrow checker for rust"));
+        assert_eq!(index.dirty_terms.len(), 4);
+        index.build_incremental();
+        assert!(index.dirty_terms.is_empty());
+
+        let meta = index.doc_metas.iter().find(|m| m.doc_id == 2).unwrap();
+        assert_eq!(index.doc_len(2), Some(7));
+        assert_eq!(meta.unique_terms(&index.long_docs), 6);
+        assert_eq!(index.total_doc_len, 10);
+        assert_eq!(index.term_freqs["rust"][&2], 2);
+        assert_eq!(index.search("borrow", 10)[0].doc_id, 2);
+        assert_eq!(index.candidate_count("borrow checker"), 1);
+        assert_eq!(index.verify(), Ok(()));
+
+        assert!(!index.append_to_document(9, "unknown document"));
+        assert!(!index.append_to_document(1, "a"));
+    }
+
+    #[test]
+    fn test_query_idfs() {
         let mut index = BM25Index::new();
         index.add_document(1, "Python programming language");
         index.add_document(2, "Rust systems programming");
         index.add_document(3, "Python data science");
+        index.add_document(4, "Cooking recipes");
         index.build();
 
-        let results = index.search("Python programming", 2);
+        // Robertson: ln((N - df + 0.5) / (df + 0.5) + 1) with N = 4
+        let robertson = |df: f32| ((4.0 - df + 0.5) / (df + 0.5) + 1.0).ln();
+        let idfs = index.query_idfs("Rust programming rust missing");
+        let terms: Vec<&str> = idfs.iter().map(|(term, _)| term.as_str()).collect();
+        assert_eq!(terms, vec!["rust", "programming", "missing"]);
+        assert!((idfs[0].1 - robertson(1.0)).abs() < 1e-6);
+        assert!((idfs[1].1 - robertson(2.0)).abs() < 1e-6);
+        assert_eq!(idfs[2].1, 0.0);
+    }
+
+    #[test]
+    fn test_all_idfs() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.add_document(4, "Cooking recipes");
+        index.build();
+
+        let idfs = index.all_idfs();
+        assert_eq!(idfs.len(), index.stats().num_terms);
+        assert!(idfs.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        let programming = idfs.iter().find(|(term, _)| term == "programming").unwrap().1;
+        assert!((programming - ((4.0f32 - 2.0 + 0.5) / (2.0 + 0.5) + 1.0).ln()).abs() < 1e-6);
+        assert_eq!(index.query_idfs("programming")[0].1, programming);
+
+        index.warm_up();
+        assert_eq!(index.all_idfs(), idfs);
+    }
+
+    #[test]
+    fn test_add_document_hashed() {
+        // FNV-1a reference values
+        assert_eq!(hash_doc_key(""), 0x811c_9dc5);
+        assert_eq!(hash_doc_key("a"), 0xe40c_292c);
+
+        let mut index = BM25Index::new();
+        let id = index.add_document_hashed("enwiki:Rust", "Rust systems programming").unwrap();
+        assert_eq!(id, hash_doc_key("enwiki:Rust"));
+        index.build();
+        assert_eq!(index.search("rust", 1)[0].doc_id, id);
+
+        // Re-adding the same key is reported rather than duplicated
+        let err = index.add_document_hashed("enwiki:Rust", "Rust again").unwrap_err();
+        assert_eq!(err.doc_id, id);
+
+        // Force a collision by claiming the id another key will hash to
+        let taken = hash_doc_key("enwiki:Python");
+        index.hashed_keys.insert(taken, "enwiki:Other".to_string());
+        let err = index.add_document_hashed("enwiki:Python", "Python language").unwrap_err();
+        assert_eq!(
+            err,
+            HashCollision {
+                key: "enwiki:Python".to_string(),
+                doc_id: taken,
+                existing: "enwiki:Other".to_string(),
+            }
+        );
+        assert_eq!(index.stats().num_docs, 1);
+    }
+
+    #[test]
+    fn test_candidate_features() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.build();
+
+        let features = index.candidate_features("python programming python missing", 2);
+        let results = index.search("python programming python missing", 2);
+        assert_eq!(features.len(), 2);
+        for ((doc_id, vector), result) in features.iter().zip(&results) {
+            assert_eq!(*doc_id, result.doc_id);
+            assert_eq!(vector.len(), 3); // python, programming, missing
+            assert_eq!(vector[2], 0.0);
+            assert!((vector.iter().sum::<f32>() - result.score).abs() < 1e-5);
+        }
+        assert!(features[0].1[0] > 0.0 && features[0].1[1] > 0.0);
+    }
+
+    #[test]
+    fn test_rank_base() {
+        let mut index = BM25Index::new().with_rank_base(1);
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.build();
+
+        let results = index.search("python programming", 10);
+        let ranks: Vec<usize> = results.iter().map(|r| r.rank).collect();
+        assert_eq!(ranks, vec![1, 2, 3]);
+
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        let loaded = BM25Index::read_from(buf.as_slice()).unwrap();
+        assert_eq!(loaded.search("python", 1)[0].rank, 1);
+        assert_eq!(loaded.freeze().search("python", 1)[0].rank, 1);
+        assert_eq!(BM25Index::new().ranking.rank_base, 0);
+    }
+
+    #[test]
+    fn test_score_epsilon() {
+        let mut scores = vec![(1, 1.0), (2, 1.0005), (3, 0.5), (4, 0.9995), (5, 2.0)];
+        sort_scores(&mut scores, 0.0);
+        let order: Vec<u32> = scores.iter().map(|&(doc_id, _)| doc_id).collect();
+        assert_eq!(order, vec![5, 2, 1, 4, 3]);
+
+        sort_scores(&mut scores, 0.001);
+        let order: Vec<u32> = scores.iter().map(|&(doc_id, _)| doc_id).collect();
+        assert_eq!(order, vec![5, 1, 2, 4, 3]);
+
+        // A chain of near-ties splits once it drifts epsilon from the top score
+        let mut scores = vec![(1, 0.92), (2, 0.96), (3, 1.0), (4, 0.88), (5, 0.84)];
+        sort_scores(&mut scores, 0.05);
+        let order: Vec<u32> = scores.iter().map(|&(doc_id, _)| doc_id).collect();
+        assert_eq!(order, vec![2, 3, 1, 4, 5]);
+
+        // Length normalization makes doc 1 score slightly lower than doc 2
+        let build = |epsilon| {
+            let mut index = BM25Index::new().with_score_epsilon(epsilon);
+            index.add_document(1, "rust memory safety and the borrow checker explained again");
+            index.add_document(2, "rust memory safety and the borrow checker explained");
+            index.add_document(3, "python data science");
+            index.build();
+            index
+        };
+        let exact = build(0.0).search("rust", 10);
+        assert_eq!(exact[0].doc_id, 2);
+        assert!(exact[0].score - exact[1].score < 0.1);
+
+        // Within epsilon the near-tie is ordered by doc_id
+        let results = build(0.1).search("rust", 10);
+        let order: Vec<u32> = results.iter().map(|r| r.doc_id).collect();
+        assert_eq!(order, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_total_tokens() {
+        let texts = ["Python programming language", "Rust systems programming, in a nutshell", "a"];
+        let mut index = BM25Index::new();
+        for (doc_id, text) in texts.iter().enumerate() {
+            index.add_document(doc_id as u32, text);
+        }
+        let expected: usize = texts.iter().map(|text| index.tokenizer.tokenize(text).len()).sum();
+        assert_eq!(index.total_tokens(), expected as u64);
+        assert_eq!(index.total_tokens(), 8);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.build();
+
+        let report = index.report();
+        assert!(report.contains("documents:          3"));
+        assert!(report.contains("Top terms by document frequency:"));
+        assert!(report.contains("Postings list lengths:"));
+        assert!(report.contains("compression ratio:"));
+        // programming and python appear in 2 docs; the rest in 1
+        assert!(report.contains("  2-3                      2\n"));
+        assert!(report.contains("  1                        5\n"));
+
+        // A term with an empty doc map gets its own bucket instead of panicking
+        let mut corrupt = BM25Index::read_from(snapshot_bytes(&index).as_slice()).unwrap();
+        corrupt.term_freqs.insert("ghost".to_string(), HashMap::new());
+        let report = corrupt.report();
+        assert!(report.contains("  0                        1\n  1                        5\n"));
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.build();
+        assert_eq!(index.verify(), Ok(()));
+
+        let mut corrupt = BM25Index::read_from(snapshot_bytes(&index).as_slice()).unwrap();
+        corrupt.total_doc_len += 1;
+        assert_eq!(
+            corrupt.verify(),
+            Err(IntegrityError::TotalDocLen { stored: 7, computed: 6 })
+        );
+
+        let mut corrupt = BM25Index::read_from(snapshot_bytes(&index).as_slice()).unwrap();
+        corrupt.postings.insert("rust".to_string(), PostingsList::Raw(vec![2, 2]));
+        assert_eq!(
+            corrupt.verify(),
+            Err(IntegrityError::UnsortedPostings { term: "rust".to_string() })
+        );
+
+        let mut corrupt = BM25Index::read_from(snapshot_bytes(&index).as_slice()).unwrap();
+        corrupt.term_freqs.get_mut("rust").unwrap().insert(9, 1);
+        assert_eq!(
+            corrupt.verify(),
+            Err(IntegrityError::UnknownDocument { term: "rust".to_string(), doc_id: 9 })
+        );
+    }
+
+    fn snapshot_bytes(index: &BM25Index) -> Vec<u8> {
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_highlight() {
+        let mut index = BM25Index::with_tokenizer(
+            BM25Params::default(),
+            Tokenizer::default().with_hyphen_mode(HyphenMode::Both),
+        )
+        .with_store_text(true);
+        index.add_document(1, "Rust is memory-safe; rusty RUST.");
+        index.add_document(2, "no stored match");
+
+        assert_eq!(
+            index.highlight(1, "rust memory").unwrap(),
+            "<em>Rust</em> is <em>memory</em>-safe; rusty <em>RUST</em>."
+        );
+
+        index.set_highlight_markers("**", "**");
+        assert_eq!(index.highlight(1, "rust").unwrap(), "**Rust** is memory-safe; rusty **RUST**.");
+        // The joined compound overlaps its parts and is marked once
+        assert_eq!(index.highlight(1, "memorysafe safe").unwrap(), "Rust is **memory-safe**; rusty RUST.");
+        assert_eq!(index.highlight(2, "rust").unwrap(), "no stored match");
+        assert_eq!(index.highlight(3, "rust"), None);
+    }
+
+    #[test]
+    fn test_search_proximity() {
+        let mut index = BM25Index::new().with_store_text(true);
+        index.add_document(1, "rust is one language and after many other words comes memory");
+        index.add_document(2, "rust memory is one language and after many other words comes");
+        index.add_document(3, "python data science notebooks");
+        index.build();
+
+        // Same terms and lengths: plain BM25 ties, broken by doc_id
+        let plain = index.search("rust memory", 10);
+        assert_eq!(plain[0].doc_id, 1);
+        assert!((plain[0].score - plain[1].score).abs() < 1e-6);
+
+        let results = index.search_proximity("rust memory", 10, 3);
         assert_eq!(results.len(), 2);
-        assert_eq!(results[0].chunk_id, "chunk_1"); // Best match
-        assert!(results[0].score > results[1].score);
+        assert_eq!(results[0].doc_id, 2);
+        assert!(results[0].score > plain[0].score);
+        // Terms 10 tokens apart are outside the window
+        assert!((results[1].score - plain[1].score).abs() < 1e-6);
     }
 
     #[test]
-    fn test_empty_query() {
+    fn test_save_load_roundtrip() {
         let mut index = BM25Index::new();
-        index.add_document(1, "test document");
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
         index.build();
 
-        let results = index.search("", 10);
-        assert!(results.is_empty());
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        let loaded = BM25Index::read_from(buf.as_slice()).unwrap();
+
+        assert_eq!(loaded.stats().num_docs, 2);
+        assert_eq!(loaded.stats().num_terms, index.stats().num_terms);
+        assert_eq!(loaded.search("programming", 10), index.search("programming", 10));
     }
 
     #[test]
-    fn test_no_results() {
+    fn test_external_ids_roundtrip() {
         let mut index = BM25Index::new();
-        index.add_document(1, "Python programming");
+        index.add_document_with_external_id(7, "enwiki:Python#0", "Python programming language");
+        index.add_document(8, "Rust systems programming");
         index.build();
 
-        let results = index.search("JavaScript", 10);
-        assert!(results.is_empty());
+        let mut buf = Vec::new();
+        index.write_to(&mut buf).unwrap();
+        let loaded = BM25Index::read_from(buf.as_slice()).unwrap();
+
+        assert_eq!(loaded.resolve_id("enwiki:Python#0"), Some(7));
+        assert_eq!(loaded.external_id(7), Some("enwiki:Python#0"));
+        assert_eq!(loaded.external_id(8), None);
+        assert_eq!(loaded.resolve_id("missing"), None);
+
+        let results = loaded.search("python", 10);
+        assert_eq!(results[0].chunk_id, "enwiki:Python#0");
+    }
+
+    #[cfg(feature = "vectors")]
+    #[test]
+    fn test_search_hybrid() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science programming");
+        index.add_document_vector(1, vec![1.0, 0.0]);
+        index.add_document_vector(2, vec![0.0, 1.0]);
+        index.add_document_vector(3, vec![0.7, 0.7]);
+        index.build();
+
+        let ids = |results: Vec<SearchResult>| results.into_iter().map(|r| r.chunk_id).collect::<Vec<_>>();
+        let bm25 = ids(index.search("python programming", 10));
+
+        assert_eq!(ids(index.search_hybrid("python programming", &[0.0, 1.0], 10, 0.0)), bm25);
+        assert_eq!(index.search_hybrid("python programming", &[0.0, 1.0], 10, 1.0)[0].chunk_id, "chunk_2");
+    }
+
+    /// Run `ops` on a logged index, then check replaying its log rebuilds it
+    fn assert_wal_replays(name: &str, ops: impl Fn(&mut BM25Index)) {
+        let path = std::env::temp_dir().join(format!("pocketwiki-wal-{}-{}.log", name, std::process::id()));
+        let new = || BM25Index::new().with_store_text(true);
+        let snapshot = |index: &BM25Index| {
+            let mut buf = Vec::new();
+            index.write_to(&mut buf).unwrap();
+            buf
+        };
+
+        let mut logged = new().attach_wal(&path).unwrap();
+        ops(&mut logged);
+        assert!(logged.take_wal_error().is_none());
+        let expected = snapshot(&logged);
+        drop(logged); // crash before saving
+
+        let replayed = new().attach_wal(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(String::from_utf8(snapshot(&replayed)).unwrap(), String::from_utf8(expected).unwrap(), "{name}");
+    }
+
+    #[test]
+    fn test_wal_replays_every_op() {
+        assert_wal_replays("append", |index| {
+            index.add_document(1, "Python programming");
+            index.append_to_document(1, "language guide");
+            assert_eq!(index.add_document_mode(1, "snakes", AddMode::Accumulate), Ok(true));
+        });
+        assert_wal_replays("fields", |index| {
+            index.add_document_fields(1, &["Python", "A programming language"]);
+        });
+        assert_wal_replays("metadata", |index| {
+            index.add_document_with_external_id(1, "enwiki:Python#0", "Python programming");
+            index.add_document_grouped(2, "enwiki:Rust", "Rust programming");
+            index.add_document_with_prior(3, "Go programming", 2.5);
+            index.add_document_with_payload(4, "Java programming", r#"{"lang":"java"}"#.to_string());
+            index.add_document_hashed("zig", "Zig programming").unwrap();
+            #[cfg(feature = "vectors")]
+            index.add_document_vector(1, vec![0.5, 0.5]);
+        });
+        assert_wal_replays("counts", |index| {
+            assert!(index.add_document_counts(1, &[("python", 3), ("programming", 1), ("python", 1)], 7));
+            index.add_document(2, "Rust programming");
+        });
+        assert_wal_replays("decay", |index| {
+            index.add_document(1, "python python python programming");
+            index.add_document(2, "rust rust programming");
+            index.decay(0.5);
+        });
+        assert_wal_replays("reindex", |index| {
+            index.add_document(1, "Python programming language");
+            index.add_document(2, "Go is fun");
+            index.reindex_with(Tokenizer::new(4)).unwrap();
+            index.add_document(3, "Rust systems programming");
+        });
+        assert_wal_replays("replace", |index| {
+            index.add_document(1, "Python programming");
+            assert_eq!(index.add_document_mode(1, "Rust programming", AddMode::Replace), Ok(true));
+            index.remove_document(1);
+            index.add_document(2, "Go programming");
+        });
+    }
+
+    #[test]
+    fn test_wal_replay() {
+        let dir = std::env::temp_dir();
+        let wal_path = dir.join(format!("pocketwiki-wal-{}.log", std::process::id()));
+        let index_path = dir.join(format!("pocketwiki-wal-{}.json", std::process::id()));
+
+        let mut index = BM25Index::open_with_wal(&wal_path).unwrap();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Go programming language");
+        index.remove_document(2);
+        drop(index); // crash before saving
+
+        // A crash mid-write leaves a torn last entry
+        let mut file = OpenOptions::new().append(true).open(&wal_path).unwrap();
+        file.write_all(br#"{"op":"add","doc_id":4,"te"#).unwrap();
+        drop(file);
+
+        let mut index = BM25Index::open_with_wal(&wal_path).unwrap();
+        index.build();
+        assert_eq!(index.stats().num_docs, 2);
+        assert_eq!(index.docs_for_term("programming"), vec![1, 3]);
+        index.add_document(4, "Java programming language");
+        assert!(index.take_wal_error().is_none());
+        drop(index);
+
+        let mut index = BM25Index::open_with_wal(&wal_path).unwrap();
+        assert_eq!(index.docs_for_term("programming"), vec![1, 3, 4]);
+        index.save(&index_path).unwrap();
+        assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), 0);
+        index.add_document(5, "Haskell functional programming");
+        drop(index);
+
+        let recovered = BM25Index::load(&index_path).unwrap().attach_wal(&wal_path).unwrap();
+        std::fs::remove_file(&wal_path).unwrap();
+        std::fs::remove_file(&index_path).unwrap();
+        assert_eq!(recovered.docs_for_term("programming"), vec![1, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_segments_hold_new_documents() {
+        let path = std::env::temp_dir().join(format!("pocketwiki-new-segments-{}.json", std::process::id()));
+        let segment_docs = |line: &str| {
+            let snapshot: serde_json::Value = serde_json::from_str(line).unwrap();
+            snapshot["doc_metas"].as_array().unwrap().iter().map(|m| m["doc_id"].as_u64().unwrap()).collect::<Vec<_>>()
+        };
+
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.build();
+        index.save(&path).unwrap();
+        index.add_document(3, "Go programming language");
+        index.build_incremental();
+        index.save_segment(&path).unwrap();
+        index.save_segment(&path).unwrap(); // nothing new
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(segment_docs(lines[1]), vec![3]);
+        assert!(!lines[1].contains("python"));
+        let loaded = BM25Index::load(&path).unwrap();
+        assert_eq!(loaded.stats().num_docs, 3);
+        assert_eq!(loaded.search("programming language", 10), index.search("programming language", 10));
+
+        // A segment re-adding a saved doc_id replaces it instead of counting it twice
+        let mut replacement = BM25Index::new();
+        replacement.add_document(2, "Rust programming language guide");
+        replacement.save_segment(&path).unwrap();
+        let mut expected = BM25Index::new();
+        expected.add_document(1, "Python programming language");
+        expected.add_document(3, "Go programming language");
+        expected.add_document(2, "Rust programming language guide");
+        expected.build();
+        let loaded = BM25Index::load(&path).unwrap();
+        assert_eq!(loaded.stats().num_docs, 3);
+        assert_eq!(loaded.stats().avg_doc_len, expected.stats().avg_doc_len);
+        assert_eq!(loaded.search("programming language", 10), expected.search("programming language", 10));
+        assert!(loaded.search("systems", 10).is_empty());
+
+        // Removing a saved document can't be a segment, so the file is rewritten
+        let mut loaded = loaded;
+        loaded.remove_document(1);
+        loaded.add_document(4, "Java programming");
+        loaded.save_segment(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        assert_eq!(segment_docs(&contents), vec![3, 2, 4]);
+
+        // So does pruning terms out of saved documents
+        let mut index = BM25Index::new();
+        index.add_document(1, "quick brown fox");
+        index.add_document(2, "quick brown dog");
+        index.save(&path).unwrap();
+        index.build_pruned(2);
+        index.add_document(3, "quick brown cat");
+        index.save_segment(&path).unwrap();
+        assert!(index.search("fox", 10).is_empty());
+        let loaded = BM25Index::load(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        assert!(loaded.search("fox", 10).is_empty());
+        assert_eq!(loaded.stats().num_docs, 3);
+    }
+
+    #[test]
+    fn test_segments() {
+        let path = std::env::temp_dir().join(format!("pocketwiki-segments-{}.json", std::process::id()));
+
+        let mut base = BM25Index::new();
+        base.add_document(1, "Python programming language");
+        base.add_document(2, "Rust systems programming");
+        base.build();
+        base.save(&path).unwrap();
+
+        let mut segment = BM25Index::new();
+        segment.add_document_with_external_id(3, "enwiki:Go#0", "Go programming language");
+        segment.build();
+        segment.save_segment(&path).unwrap();
+
+        let mut full = BM25Index::new();
+        full.add_document(1, "Python programming language");
+        full.add_document(2, "Rust systems programming");
+        full.add_document_with_external_id(3, "enwiki:Go#0", "Go programming language");
+        full.build();
+
+        let loaded = BM25Index::load(&path).unwrap();
+        assert_eq!(loaded.stats().num_docs, 3);
+        assert_eq!(loaded.search("programming language", 10), full.search("programming language", 10));
+        assert_eq!(loaded.resolve_id("enwiki:Go#0"), Some(3));
+
+        BM25Index::compact_segments(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
     }
 }
@@ -0,0 +1,62 @@
+//! Error types for index persistence and integrity checks
+
+use thiserror::Error;
+
+/// Errors raised while saving or loading an index
+#[derive(Debug, Error)]
+pub enum IndexError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("unsupported index format version {found} (supported up to {supported})")]
+    UnsupportedVersion { found: u32, supported: u32 },
+}
+
+/// Broken invariant found by `BM25Index::verify`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum IntegrityError {
+    #[error("total_doc_len is {stored} but document lengths sum to {computed}")]
+    TotalDocLen { stored: u64, computed: u64 },
+
+    #[error("postings for term {term:?} are not strictly increasing")]
+    UnsortedPostings { term: String },
+
+    #[error("term {term:?} references unknown document {doc_id}")]
+    UnknownDocument { term: String, doc_id: u32 },
+}
+
+/// Two keys passed to `BM25Index::add_document_hashed` share a doc_id
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("key {key:?} hashes to doc_id {doc_id}, already used by key {existing:?}")]
+pub struct HashCollision {
+    pub key: String,
+    pub doc_id: u32,
+    pub existing: String,
+}
+
+/// `BM25Index::try_add_document` hit the `max_docs` cap under `CapacityPolicy::Reject`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("index is full: max_docs is {max_docs}")]
+pub struct DocLimitReached {
+    pub max_docs: usize,
+}
+
+/// `BM25Index::add_document_mode` got an indexed doc_id under `AddMode::Reject`
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("document {doc_id} is already indexed")]
+pub struct DuplicateDocument {
+    pub doc_id: u32,
+}
+
+/// `BM25Index::reindex_with` found a document without stored text
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("document {doc_id} has no stored text to reindex; enable store_text before adding documents")]
+pub struct TextNotStored {
+    pub doc_id: u32,
+}
+
+/// Result alias for index operations
+pub type Result<T> = std::result::Result<T, IndexError>;
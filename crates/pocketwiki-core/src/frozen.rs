@@ -0,0 +1,174 @@
+//! Read-only frozen BM25 index
+//!
+//! A frozen index keeps only the compressed postings plus a compact term
+//! frequency array aligned with each postings list. The per-term hash maps
+//! used while indexing are dropped, trading mutability for lower memory.
+
+use ahash::AHashMap;
+
+use crate::block::decode_block_postings;
+use crate::bm25::{rank_scores, AnalyzedQuery, BM25Params, BM25Scorer, DocMeta, IndexStats, Ranking, SearchResult};
+use crate::tokenizer::Tokenizer;
+
+/// Compressed postings for one term
+struct FrozenPostings {
+    /// Block-compressed dense ids (positions in `doc_metas`)
+    doc_ids: Vec<u8>,
+    /// Term frequency for each doc_id, in postings order
+    freqs: Vec<u32>,
+}
+
+/// Immutable BM25 index produced by `BM25Index::freeze`
+pub struct FrozenBM25Index {
+    tokenizer: Tokenizer,
+    auto_language: bool, // analyze queries per document language
+    bigrams: bool,       // expand queries with adjacent token pairs
+    params: BM25Params,
+    postings: AHashMap<String, FrozenPostings>,
+    doc_metas: Vec<DocMeta>, // indexed by dense id
+    long_docs: AHashMap<u32, (u32, u32)>, // counts of documents past the DocMeta cap
+    external_ids: AHashMap<u32, String>,
+    priors: AHashMap<u32, f32>,
+    total_doc_len: u64,
+    ranking: Ranking,
+}
+
+impl FrozenBM25Index {
+    /// Assemble a frozen index from a built index's parts
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn from_parts(
+        tokenizer: Tokenizer,
+        auto_language: bool,
+        bigrams: bool,
+        params: BM25Params,
+        term_postings: impl Iterator<Item = (String, Vec<u8>, Vec<u32>)>,
+        doc_metas: Vec<DocMeta>,
+        long_docs: AHashMap<u32, (u32, u32)>,
+        external_ids: AHashMap<u32, String>,
+        priors: AHashMap<u32, f32>,
+        ranking: Ranking,
+    ) -> Self {
+        let total_doc_len = doc_metas.iter().map(|m| m.doc_len(&long_docs) as u64).sum();
+        let postings = term_postings
+            .map(|(term, doc_ids, freqs)| (term, FrozenPostings { doc_ids, freqs }))
+            .collect();
+        Self {
+            tokenizer,
+            auto_language,
+            bigrams,
+            params,
+            postings,
+            doc_metas,
+            long_docs,
+            external_ids,
+            priors,
+            total_doc_len,
+            ranking,
+        }
+    }
+
+    /// Search the index
+    pub fn search(&self, query: &str, k: usize) -> Vec<SearchResult> {
+        self.search_multi(&[(query, 1.0)], k)
+    }
+
+    /// Search with several weighted queries, summing per-document scores
+    pub fn search_multi(&self, queries: &[(&str, f32)], k: usize) -> Vec<SearchResult> {
+        let scorer = self.scorer();
+        let mut scores: AHashMap<u32, f32> = AHashMap::new();
+
+        for &(query, weight) in queries {
+            let query = AnalyzedQuery::new(query, &self.tokenizer, self.auto_language, self.bigrams, &scorer);
+
+            // Score term-at-a-time over each decoded postings list, counting
+            // matched terms per dense id for the exact-match boost
+            let mut query_scores: AHashMap<u32, (f32, usize)> = AHashMap::new();
+            for (language, query_terms) in &query.analyses {
+                for (term, term_weight) in query_terms {
+                    let Some(postings) = self.postings.get(term) else {
+                        continue;
+                    };
+                    let dense_ids = decode_block_postings(&postings.doc_ids);
+                    let doc_freq = dense_ids.len();
+                    for (dense, &term_freq) in dense_ids.into_iter().zip(&postings.freqs) {
+                        let meta = &self.doc_metas[dense as usize];
+                        if !query.applies(*language, || meta.language) {
+                            continue;
+                        }
+                        let score = scorer.score_term(term_freq as f32, meta.doc_len(&self.long_docs) as f32, doc_freq);
+                        let entry = query_scores.entry(dense).or_insert((0.0, 0));
+                        entry.0 += term_weight * score;
+                        entry.1 += 1;
+                    }
+                }
+            }
+
+            for (dense, (score, matched)) in query_scores {
+                let meta = &self.doc_metas[dense as usize];
+                let doc_id = meta.doc_id;
+                // Same rule as `BM25Index`: short documents with every query term
+                let boost = self.params.exact_match_boost;
+                let query_len = query.terms_for(|| meta.language).len();
+                let exact = matched == query_len && meta.unique_terms(&self.long_docs) as usize <= 2 * query_len;
+                let score = if boost != 1.0 && exact { score * boost } else { score };
+                let score = self.params.prior_mode.apply(score, self.priors.get(&doc_id).copied());
+                *scores.entry(doc_id).or_insert(0.0) += weight * score;
+            }
+        }
+
+        rank_scores(scores.into_iter().collect(), k, self.ranking, |doc_id| match self.external_ids.get(&doc_id) {
+            Some(external_id) => external_id.clone(),
+            None => format!("chunk_{}", doc_id),
+        })
+    }
+
+    /// Get index statistics
+    pub fn stats(&self) -> IndexStats {
+        IndexStats {
+            num_docs: self.doc_metas.len(),
+            num_terms: self.postings.len(),
+            avg_doc_len: if self.doc_metas.is_empty() {
+                0.0
+            } else {
+                self.total_doc_len as f32 / self.doc_metas.len() as f32
+            },
+        }
+    }
+
+    /// Create a scorer from the corpus statistics
+    fn scorer(&self) -> BM25Scorer {
+        let avg_doc_len = if self.doc_metas.is_empty() {
+            1.0
+        } else {
+            self.total_doc_len as f32 / self.doc_metas.len() as f32
+        };
+        BM25Scorer::new(self.params, avg_doc_len, self.doc_metas.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bm25::BM25Index;
+
+    #[test]
+    fn test_freeze_matches_mutable() {
+        let mut index = BM25Index::new();
+        index.add_document(1, "Python programming language");
+        index.add_document(2, "Rust systems programming");
+        index.add_document(3, "Python data science");
+        index.add_document_with_external_id(4, "enwiki:Rust#2", "Rust programming and Python bindings");
+        index.add_document_with_prior(5, "Python tutorial", 1.5);
+        index.build();
+
+        let queries = ["Python programming", "rust", "data science python", "missing"];
+        let expected: Vec<_> = queries.iter().map(|q| index.search(q, 10)).collect();
+        let stats = index.stats();
+
+        let frozen = index.freeze();
+        for (query, expected) in queries.iter().zip(expected) {
+            assert_eq!(frozen.search(query, 10), expected);
+        }
+        assert_eq!(frozen.stats().num_docs, stats.num_docs);
+        assert_eq!(frozen.stats().num_terms, stats.num_terms);
+    }
+}
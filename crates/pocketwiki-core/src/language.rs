@@ -0,0 +1,104 @@
+//! Lightweight language detection for per-document analysis
+//!
+//! Detection counts how many of a text's words are stopwords of each
+//! supported language and picks the language with the most hits. It is
+//! crude next to n-gram models but needs no data files and works well on
+//! paragraph-sized chunks.
+
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A language with its own stopword list and stemmer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Language {
+    English,
+    French,
+    German,
+    Spanish,
+}
+
+impl Language {
+    /// Every supported language
+    pub const ALL: [Language; 4] = [Language::English, Language::French, Language::German, Language::Spanish];
+
+    /// Common stopwords, lowercase
+    pub fn stopwords(&self) -> &'static [&'static str] {
+        match self {
+            Language::English => &[
+                "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it", "no",
+                "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these", "they", "this",
+                "to", "was", "will", "with",
+            ],
+            Language::French => &[
+                "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en", "est", "et", "il", "ils",
+                "je", "la", "le", "les", "leur", "mais", "ne", "nous", "par", "pas", "pour", "qui", "que", "sa",
+                "se", "ses", "son", "sont", "sur", "un", "une", "vous",
+            ],
+            Language::German => &[
+                "auf", "aus", "bei", "das", "dem", "den", "der", "des", "die", "ein", "eine", "einen", "er", "es",
+                "für", "ist", "im", "mit", "nicht", "noch", "sich", "sie", "sind", "und", "von", "war", "wie",
+                "zu", "zum", "zur",
+            ],
+            Language::Spanish => &[
+                "al", "como", "con", "de", "del", "el", "en", "es", "la", "las", "lo", "los", "más", "no", "para",
+                "pero", "por", "que", "se", "son", "su", "sus", "un", "una", "y",
+            ],
+        }
+    }
+
+    /// Snowball stemming algorithm for the language
+    pub fn stem_algorithm(&self) -> Algorithm {
+        match self {
+            Language::English => Algorithm::English,
+            Language::French => Algorithm::French,
+            Language::German => Algorithm::German,
+            Language::Spanish => Algorithm::Spanish,
+        }
+    }
+
+    /// Dominant language of a text, or `None` if no language clearly wins
+    pub fn detect(text: &str) -> Option<Language> {
+        let mut hits = [0usize; Language::ALL.len()];
+        for word in text.unicode_words() {
+            let word = word.to_lowercase();
+            for (count, language) in hits.iter_mut().zip(Language::ALL) {
+                if language.stopwords().contains(&word.as_str()) {
+                    *count += 1;
+                }
+            }
+        }
+        let best = *hits.iter().max()?;
+        let mut winners = Language::ALL.iter().zip(hits).filter(|&(_, count)| count == best);
+        match (winners.next(), winners.next()) {
+            (Some((&language, _)), None) if best > 0 => Some(language),
+            _ => None,
+        }
+    }
+
+    /// Drop the language's stopwords from lowercase terms and stem the rest
+    pub fn analyze_terms(&self, mut terms: Vec<String>) -> Vec<String> {
+        let stopwords = self.stopwords();
+        terms.retain(|term| !stopwords.contains(&term.as_str()));
+        let stemmer = Stemmer::create(self.stem_algorithm());
+        for term in &mut terms {
+            *term = stemmer.stem(term).into_owned();
+        }
+        terms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Language::detect("The cat is on the mat"), Some(Language::English));
+        assert_eq!(Language::detect("Le chat est sur le tapis"), Some(Language::French));
+        assert_eq!(Language::detect("Die Katze ist auf der Matte"), Some(Language::German));
+        assert_eq!(Language::detect("El gato está en la alfombra y duerme"), Some(Language::Spanish));
+        assert_eq!(Language::detect("quantum chromodynamics"), None);
+        assert_eq!(Language::detect(""), None);
+    }
+}
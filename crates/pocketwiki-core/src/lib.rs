@@ -3,9 +3,28 @@
 //! This library provides efficient sparse retrieval using BM25 algorithm
 //! with compressed inverted index storage.
 
+pub mod analyzer;
+pub mod block;
 pub mod bm25;
+pub mod error;
+pub mod frozen;
+pub mod language;
+pub mod multifield;
+mod rng;
 pub mod tokenizer;
 pub mod varint;
+mod wal;
+pub mod writer;
 
-pub use bm25::{BM25Index, BM25Scorer, SearchResult};
-pub use tokenizer::Tokenizer;
+pub use analyzer::{Analyzer, Token, TokenFilter};
+pub use bm25::{
+    AddMode, BenchReport, BM25Index, BM25Scorer, CapacityPolicy, CorpusStats, GlobalStats, IdfVariant, Occur, PriorMode,
+    QueryTerm, QueryTfMode, SearchResult, Scorer, SearchResultWithPayload, SearchResultsSoA, SearchTimings,
+    SetSimilarity, TermId, FORMAT_VERSION,
+};
+pub use error::{DocLimitReached, DuplicateDocument, HashCollision, IndexError, IntegrityError, Result, TextNotStored};
+pub use frozen::FrozenBM25Index;
+pub use language::Language;
+pub use multifield::{FieldParams, MultiFieldIndex};
+pub use tokenizer::{HyphenMode, TokenStats, Tokenizer};
+pub use writer::BM25IndexWriter;
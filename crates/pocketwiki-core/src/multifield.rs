@@ -0,0 +1,251 @@
+//! Fielded search over one BM25 index per field
+//!
+//! Each field (title, body, tags, ...) gets its own `BM25Index`, so length
+//! normalization and IDF are computed per field. Query-time weights combine
+//! the per-field scores linearly, letting callers search a single field or
+//! favor some fields over others without reindexing.
+//!
+//! `search_bm25f` ranks with BM25F instead: each field's term frequency is
+//! length-normalized with that field's `b`, the weighted frequencies are
+//! summed, and `k1` saturation is applied once to the total. Summing
+//! separately saturated field scores overrates terms repeated across fields.
+
+use std::collections::{BTreeMap, HashMap};
+
+use ahash::{AHashMap, AHashSet};
+
+use crate::bm25::{rank_scores, weighted_query_terms, BM25Index, BM25Params, BM25Scorer, Ranking, SearchResult};
+use crate::tokenizer::Tokenizer;
+
+/// Weight and length normalization of one field under BM25F
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldParams {
+    /// Multiplier on the field's normalized term frequency
+    pub weight: f32,
+    /// Length normalization of the field (0 = none, 1 = full)
+    pub b: f32,
+}
+
+impl Default for FieldParams {
+    fn default() -> Self {
+        Self { weight: 1.0, b: 0.75 }
+    }
+}
+
+/// Documents split into named fields, each indexed separately
+pub struct MultiFieldIndex {
+    params: BM25Params,
+    tokenizer: Tokenizer,
+    fields: BTreeMap<String, BM25Index>, // field name -> index of that field's text
+    doc_ids: AHashSet<u32>,               // documents indexed in at least one field
+}
+
+impl MultiFieldIndex {
+    /// Create an empty index with default parameters
+    pub fn new() -> Self {
+        Self::with_tokenizer(BM25Params::default(), Tokenizer::default())
+    }
+
+    /// Create an index whose fields all use the given parameters and tokenizer
+    pub fn with_tokenizer(params: BM25Params, tokenizer: Tokenizer) -> Self {
+        Self {
+            params,
+            tokenizer,
+            fields: BTreeMap::new(),
+            doc_ids: AHashSet::new(),
+        }
+    }
+
+    /// Add a document's fields, creating field indexes as new names appear
+    ///
+    /// Re-adding a `doc_id` replaces all of its earlier fields, so it is
+    /// still counted once. Returns `false` if no field had any tokens.
+    pub fn add_document(&mut self, doc_id: u32, fields: &HashMap<String, String>) -> bool {
+        if self.doc_ids.remove(&doc_id) {
+            for index in self.fields.values_mut() {
+                index.remove_document(doc_id);
+            }
+        }
+        let mut added = false;
+        for (name, text) in fields {
+            let index = self
+                .fields
+                .entry(name.clone())
+                .or_insert_with(|| BM25Index::with_tokenizer(self.params, self.tokenizer.clone()));
+            added |= index.add_document(doc_id, text);
+        }
+        if added {
+            self.doc_ids.insert(doc_id);
+        }
+        added
+    }
+
+    /// Build every field's postings
+    pub fn build(&mut self) {
+        for index in self.fields.values_mut() {
+            index.build();
+        }
+    }
+
+    /// Names of the fields seen so far, sorted
+    pub fn field_names(&self) -> Vec<&str> {
+        self.fields.keys().map(String::as_str).collect()
+    }
+
+    /// The index of a single field, if any document had it
+    pub fn field(&self, name: &str) -> Option<&BM25Index> {
+        self.fields.get(name)
+    }
+
+    /// Search with each field's BM25 score multiplied by its weight and summed
+    ///
+    /// Fields missing from `field_weights` (or weighted 0) are not searched,
+    /// so a single entry queries one field. Unknown field names are ignored.
+    pub fn search(&self, query: &str, field_weights: &HashMap<String, f32>, k: usize) -> Vec<SearchResult> {
+        let mut scores: AHashMap<u32, f32> = AHashMap::new();
+        for (name, &weight) in field_weights {
+            let Some(index) = self.fields.get(name) else {
+                continue;
+            };
+            if weight == 0.0 {
+                continue;
+            }
+            for (doc_id, score) in index.score_candidates(&[(query, weight)]) {
+                *scores.entry(doc_id).or_insert(0.0) += score;
+            }
+        }
+        rank_scores(scores.into_iter().collect(), k, Ranking::default(), |doc_id| {
+            format!("chunk_{}", doc_id)
+        })
+    }
+
+    /// Search with BM25F: per-field normalized frequencies, saturated once
+    ///
+    /// For each query term, a document's pseudo-frequency is the sum over
+    /// fields of `weight * tf / (1 - b + b * len / avg_len)`, and it scores
+    /// `idf * tf' * (k1 + 1) / (k1 + tf')` with the index's `k1`. IDF counts
+    /// documents having the term in any searched field. Fields missing from
+    /// `field_params` (or weighted 0) are not searched.
+    pub fn search_bm25f(&self, query: &str, field_params: &HashMap<String, FieldParams>, k: usize) -> Vec<SearchResult> {
+        let fields: Vec<(&BM25Index, FieldParams, f32)> = field_params
+            .iter()
+            .filter(|(_, params)| params.weight != 0.0)
+            .filter_map(|(name, &params)| {
+                let index = self.fields.get(name)?;
+                let avg_len = index.stats().avg_doc_len;
+                Some((index, params, if avg_len > 0.0 { avg_len } else { 1.0 }))
+            })
+            .collect();
+        let k1 = self.params.k1;
+        let scorer = BM25Scorer::new(self.params, 1.0, self.doc_ids.len());
+
+        let mut scores: AHashMap<u32, f32> = AHashMap::new();
+        for (term, query_weight) in weighted_query_terms(&self.tokenizer, query, &scorer) {
+            let mut pseudo_tfs: AHashMap<u32, f32> = AHashMap::new();
+            for &(index, params, avg_len) in &fields {
+                let Some(docs) = index.term_doc_freqs(&term) else {
+                    continue;
+                };
+                for (&doc_id, &tf) in docs {
+                    let len = index.doc_len(doc_id).unwrap_or(0) as f32;
+                    let norm = 1.0 - params.b + params.b * len / avg_len;
+                    *pseudo_tfs.entry(doc_id).or_insert(0.0) += params.weight * tf as f32 / norm;
+                }
+            }
+            let idf = scorer.idf(pseudo_tfs.len());
+            for (doc_id, tf) in pseudo_tfs {
+                *scores.entry(doc_id).or_insert(0.0) += query_weight * idf * tf * (k1 + 1.0) / (k1 + tf);
+            }
+        }
+        rank_scores(scores.into_iter().collect(), k, Ranking::default(), |doc_id| {
+            format!("chunk_{}", doc_id)
+        })
+    }
+}
+
+impl Default for MultiFieldIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(title: &str, body: &str) -> HashMap<String, String> {
+        HashMap::from([("title".to_string(), title.to_string()), ("body".to_string(), body.to_string())])
+    }
+
+    #[test]
+    fn test_bm25f_differs_from_field_sum() {
+        let mut index = MultiFieldIndex::new();
+        index.add_document(1, &doc("Rust", "rust rust guide"));
+        index.add_document(2, &doc("Cooking", "pans"));
+        index.build();
+
+        let title = FieldParams { weight: 2.0, b: 0.75 };
+        let body = FieldParams { weight: 1.0, b: 0.75 };
+        let params = HashMap::from([("title".to_string(), title), ("body".to_string(), body)]);
+        let bm25f = index.search_bm25f("rust", &params, 10);
+        assert_eq!(bm25f.len(), 1);
+
+        // idf = ln 2 (N=2, df=1); title len 1/avg 1, body len 3/avg 2
+        let tf = 2.0 * 1.0 + 1.0 * 2.0 / (0.25 + 0.75 * 1.5);
+        let expected = 2.0f32.ln() * tf * 2.5 / (1.5 + tf);
+        assert!((bm25f[0].score - expected).abs() < 1e-5);
+        assert!((bm25f[0].score - 1.2082).abs() < 1e-3);
+
+        // Saturating each field separately gives a much higher score
+        let weights = HashMap::from([("title".to_string(), 2.0), ("body".to_string(), 1.0)]);
+        let summed = index.search("rust", &weights, 10);
+        assert!((summed[0].score - 2.2393).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_readd_replaces_document() {
+        let title = FieldParams { weight: 2.0, b: 0.75 };
+        let body = FieldParams { weight: 1.0, b: 0.75 };
+        let params = HashMap::from([("title".to_string(), title), ("body".to_string(), body)]);
+
+        let mut expected = MultiFieldIndex::new();
+        expected.add_document(1, &doc("Rust", "rust rust guide"));
+        expected.add_document(2, &doc("Cooking", "pans"));
+        expected.build();
+
+        let mut index = MultiFieldIndex::new();
+        index.add_document(1, &doc("Gardening", "rust on tools"));
+        index.add_document(2, &doc("Cooking", "pans"));
+        index.add_document(1, &doc("Rust", "rust rust guide"));
+        index.build();
+
+        // N stays 2 and the old fields no longer match
+        assert_eq!(index.search_bm25f("rust", &params, 10), expected.search_bm25f("rust", &params, 10));
+        assert!(index.search_bm25f("gardening", &params, 10).is_empty());
+        assert_eq!(index.field("title").unwrap().stats().num_docs, 2);
+    }
+
+    #[test]
+    fn test_field_weights() {
+        let mut index = MultiFieldIndex::new();
+        index.add_document(1, &doc("Rust", "A language guide covering ownership and borrowing"));
+        index.add_document(2, &doc("Cooking basics", "Rust on cast iron pans and how to remove rust"));
+        index.add_document(3, &doc("Gardening", "Soil and seeds"));
+        index.build();
+        assert_eq!(index.field_names(), vec!["body", "title"]);
+
+        let weights = |title: f32, body: f32| {
+            HashMap::from([("title".to_string(), title), ("body".to_string(), body)])
+        };
+        assert_eq!(index.search("rust", &weights(1.0, 1.0), 10)[0].doc_id, 2);
+        let results = index.search("rust", &weights(5.0, 1.0), 10);
+        assert_eq!(results.iter().map(|r| r.doc_id).collect::<Vec<_>>(), vec![1, 2]);
+
+        // A single field restricts the search to it
+        let title_only = HashMap::from([("title".to_string(), 1.0)]);
+        let results = index.search("rust", &title_only, 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].score, index.field("title").unwrap().search("rust", 1)[0].score);
+        assert!(index.search("rust", &HashMap::from([("tags".to_string(), 1.0)]), 10).is_empty());
+    }
+}
@@ -1,34 +1,336 @@
 //! Simple tokenizer for BM25
 //!
-//! Uses Unicode word boundaries and lowercase normalization.
+//! Uses Unicode word boundaries and lowercase normalization. Optionally keeps
+//! standalone symbol and emoji segments as tokens, can split on extra
+//! separator characters before segmentation, and can index hyphenated words
+//! as a joined form.
 
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+use std::sync::Mutex;
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::analyzer::Analyzer;
+
+/// How hyphenated words such as "state-of-the-art" are tokenized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum HyphenMode {
+    /// Emit only the parts: "state", "of", "the", "art"
+    #[default]
+    Split,
+    /// Emit only the joined form: "stateoftheart"
+    Keep,
+    /// Emit the parts followed by the joined form
+    Both,
+}
+
 /// Simple tokenizer that splits on whitespace and punctuation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Tokenizer {
     /// Minimum token length (default: 2)
     pub min_length: usize,
+    /// Lowercase tokens before filtering (default: true)
+    pub lowercase: bool,
+    /// Tokens dropped after lowercasing and the length filter (default: none)
+    pub stopwords: BTreeSet<String>,
+    /// Emit symbol/emoji segments as tokens alongside words (default: false)
+    pub keep_symbols: bool,
+    /// Extra separator characters applied before word segmentation (default: none)
+    pub split_on: Option<Vec<char>>,
+    /// Handling of hyphenated words (default: split into parts)
+    pub hyphen_mode: HyphenMode,
+    /// Language tag for locale-specific lowercasing, e.g. "tr" (default: none)
+    pub locale: Option<String>,
+    /// Filter pipeline replacing the built-in word handling (default: none)
+    ///
+    /// Not serialized: re-attach it after loading a saved index.
+    #[serde(skip)]
+    pub analyzer: Option<Analyzer>,
+    /// Keep stopwords when removing them would leave no tokens (default: false)
+    pub fallback_ignore_stopwords: bool,
+    /// Texts whose `tokenize` results are cached, least recently used evicted first (default: 0, off)
+    pub cache_capacity: usize,
+    #[serde(skip)]
+    cache: TokenCache,
 }
 
 impl Default for Tokenizer {
     fn default() -> Self {
-        Self { min_length: 2 }
+        Self {
+            min_length: 2,
+            lowercase: true,
+            stopwords: BTreeSet::new(),
+            keep_symbols: false,
+            split_on: None,
+            hyphen_mode: HyphenMode::Split,
+            locale: None,
+            analyzer: None,
+            fallback_ignore_stopwords: false,
+            cache_capacity: 0,
+            cache: TokenCache::default(),
+        }
     }
 }
 
 impl Tokenizer {
     /// Create a new tokenizer with custom minimum length
     pub fn new(min_length: usize) -> Self {
-        Self { min_length }
+        Self {
+            min_length,
+            ..Self::default()
+        }
+    }
+
+    /// Enable or disable emitting symbol/emoji segments as tokens
+    pub fn with_keep_symbols(mut self, keep_symbols: bool) -> Self {
+        self.keep_symbols = keep_symbols;
+        self
+    }
+
+    /// Enable or disable lowercasing (and with it `locale`)
+    pub fn with_lowercase(mut self, lowercase: bool) -> Self {
+        self.lowercase = lowercase;
+        self
+    }
+
+    /// Drop the given words, compared after lowercasing
+    pub fn with_stopwords<S: Into<String>>(mut self, stopwords: impl IntoIterator<Item = S>) -> Self {
+        self.stopwords = stopwords.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Split on the given characters in addition to word boundaries
+    pub fn with_split_on(mut self, separators: Vec<char>) -> Self {
+        self.split_on = Some(separators);
+        self
+    }
+
+    /// Set how hyphenated words are tokenized
+    pub fn with_hyphen_mode(mut self, hyphen_mode: HyphenMode) -> Self {
+        self.hyphen_mode = hyphen_mode;
+        self
+    }
+
+    /// Use locale-specific lowercasing for the given language tag
+    ///
+    /// Only Turkish and Azeri ("tr", "az", with any region suffix) differ from
+    /// the Unicode default; other locales lowercase as usual.
+    pub fn with_locale(mut self, locale: impl Into<String>) -> Self {
+        self.locale = Some(locale.into());
+        self
+    }
+
+    /// Run words through an analyzer pipeline instead of the built-in rules
+    ///
+    /// `split_on` still applies first; `min_length`, `lowercase`,
+    /// `stopwords`, `keep_symbols`, `hyphen_mode` and `locale` are ignored, so add the equivalent filters
+    /// to the analyzer.
+    pub fn with_analyzer(mut self, analyzer: Analyzer) -> Self {
+        self.analyzer = Some(analyzer);
+        self
+    }
+
+    /// Retry without `stopwords` or the analyzer's stopword filters when they empty a text
+    ///
+    /// Applies to documents as well as queries, so a query such as "to be or
+    /// not to be" still matches documents made up only of stopwords.
+    pub fn with_fallback_ignore_stopwords(mut self, fallback: bool) -> Self {
+        self.fallback_ignore_stopwords = fallback;
+        self
+    }
+
+    /// Cache the tokens of up to `capacity` recently tokenized texts
+    ///
+    /// Speeds up `tokenize` (and so searching) for repeated texts such as
+    /// evaluation queries, at the cost of a clone per hit. The cache sits
+    /// behind a mutex, so a shared tokenizer stays usable from several
+    /// threads but they contend on it. Entries aren't invalidated when
+    /// settings change, so configure the tokenizer before using it. Clones
+    /// start with an empty cache.
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    /// Lowercase a token, honoring the configured locale
+    fn lowercase(&self, token: &str) -> String {
+        if !self.lowercase {
+            return token.to_string();
+        }
+        if !self.locale.as_deref().is_some_and(is_turkic) {
+            return token.to_lowercase();
+        }
+        let mut lowered = String::with_capacity(token.len());
+        for c in token.chars() {
+            match c {
+                'I' => lowered.push('ı'),
+                'İ' => lowered.push('i'),
+                _ => lowered.extend(c.to_lowercase()),
+            }
+        }
+        lowered
     }
 
     /// Tokenize text into terms
     pub fn tokenize(&self, text: &str) -> Vec<String> {
-        text.unicode_words()
-            .map(|word| word.to_lowercase())
-            .filter(|word| word.len() >= self.min_length)
-            .collect()
+        if self.cache_capacity == 0 {
+            return self.tokenize_uncached(text);
+        }
+        if let Some(tokens) = self.cache.get(text) {
+            return tokens;
+        }
+        let tokens = self.tokenize_uncached(text);
+        self.cache.insert(text, tokens.clone(), self.cache_capacity);
+        tokens
+    }
+
+    /// `tokenize` without the cache
+    fn tokenize_uncached(&self, text: &str) -> Vec<String> {
+        self.tokenize_spans(text).into_iter().map(|(token, _)| token).collect()
+    }
+
+    /// Tokenize text into terms lazily, without collecting them
+    ///
+    /// Yields the same tokens as `tokenize`. The built-in rules stream
+    /// directly; an analyzer, a `hyphen_mode` other than `Split`, or
+    /// `fallback_ignore_stopwords` with stopwords set need the whole text, so
+    /// those configurations tokenize eagerly and iterate the result.
+    pub fn tokenize_stream<'a>(&'a self, text: &'a str) -> Box<dyn Iterator<Item = String> + 'a> {
+        let needs_whole_text = self.analyzer.is_some()
+            || self.hyphen_mode != HyphenMode::Split
+            || (self.fallback_ignore_stopwords && !self.stopwords.is_empty());
+        if needs_whole_text {
+            return Box::new(self.tokenize(text).into_iter());
+        }
+
+        let separators = self.split_on.as_deref().unwrap_or_default();
+        let keep_symbols = self.keep_symbols;
+        Box::new(
+            text.split(move |c: char| separators.contains(&c))
+                .flat_map(move |piece| -> Box<dyn Iterator<Item = &'a str>> {
+                    if keep_symbols {
+                        Box::new(piece.split_word_bounds().filter(|s| is_word(s) || is_symbol(s)))
+                    } else {
+                        Box::new(piece.unicode_words())
+                    }
+                })
+                .map(|token| self.lowercase(token))
+                .filter(|token| token.len() >= self.min_length && !self.stopwords.contains(token)),
+        )
+    }
+
+    /// Tokenize text into terms along with the byte range each came from
+    ///
+    /// Ranges index into `text`. The joined form of a hyphenated word spans
+    /// from its first part to its last.
+    pub fn tokenize_spans(&self, text: &str) -> Vec<(String, Range<usize>)> {
+        // Pad separators to their byte length so offsets stay valid
+        let text = match &self.split_on {
+            Some(separators) => {
+                let mut padded = String::with_capacity(text.len());
+                for c in text.chars() {
+                    if separators.contains(&c) {
+                        padded.extend(std::iter::repeat_n(' ', c.len_utf8()));
+                    } else {
+                        padded.push(c);
+                    }
+                }
+                Cow::Owned(padded)
+            }
+            None => Cow::Borrowed(text),
+        };
+
+        if let Some(analyzer) = &self.analyzer {
+            let mut tokens = analyzer.analyze(&text);
+            if tokens.is_empty() && self.fallback_ignore_stopwords {
+                tokens = analyzer.analyze_keeping_stopwords(&text);
+            }
+            return tokens
+                .into_iter()
+                .map(|token| (token.text, token.span))
+                .collect();
+        }
+
+        let raw: Vec<(Cow<str>, Range<usize>)> = if self.hyphen_mode != HyphenMode::Split {
+            self.tokenize_compounds(&text)
+        } else if self.keep_symbols {
+            text.split_word_bound_indices()
+                .filter(|(_, segment)| is_word(segment) || is_symbol(segment))
+                .map(|(start, segment)| (Cow::Borrowed(segment), start..start + segment.len()))
+                .collect()
+        } else {
+            text.unicode_word_indices()
+                .map(|(start, word)| (Cow::Borrowed(word), start..start + word.len()))
+                .collect()
+        };
+
+        let tokens: Vec<(String, Range<usize>)> = raw
+            .into_iter()
+            .map(|(token, span)| (self.lowercase(&token), span))
+            .filter(|(token, _)| token.len() >= self.min_length)
+            .collect();
+        if self.stopwords.is_empty() {
+            return tokens;
+        }
+        let filtered: Vec<(String, Range<usize>)> = tokens
+            .iter()
+            .filter(|(token, _)| !self.stopwords.contains(token))
+            .cloned()
+            .collect();
+        if filtered.is_empty() && self.fallback_ignore_stopwords {
+            tokens
+        } else {
+            filtered
+        }
+    }
+
+    /// Split into raw tokens, grouping hyphen-joined words into compounds
+    fn tokenize_compounds<'t>(&self, text: &'t str) -> Vec<(Cow<'t, str>, Range<usize>)> {
+        let mut tokens = Vec::new();
+        let mut parts: Vec<(usize, &str)> = Vec::new();
+        let mut after_hyphen = false;
+
+        for (start, segment) in text.split_word_bound_indices() {
+            if is_word(segment) {
+                if !after_hyphen {
+                    self.push_compound(&mut parts, &mut tokens);
+                }
+                parts.push((start, segment));
+                after_hyphen = false;
+            } else if is_hyphen(segment) && !parts.is_empty() && !after_hyphen {
+                after_hyphen = true;
+            } else {
+                self.push_compound(&mut parts, &mut tokens);
+                after_hyphen = false;
+                if self.keep_symbols && is_symbol(segment) {
+                    tokens.push((Cow::Borrowed(segment), start..start + segment.len()));
+                }
+            }
+        }
+        self.push_compound(&mut parts, &mut tokens);
+        tokens
+    }
+
+    /// Emit a (possibly hyphenated) word according to the hyphen mode
+    fn push_compound<'t>(&self, parts: &mut Vec<(usize, &'t str)>, tokens: &mut Vec<(Cow<'t, str>, Range<usize>)>) {
+        let span = |&(start, part): &(usize, &'t str)| (Cow::Borrowed(part), start..start + part.len());
+        if parts.len() > 1 {
+            if self.hyphen_mode != HyphenMode::Keep {
+                tokens.extend(parts.iter().map(span));
+            }
+            if self.hyphen_mode != HyphenMode::Split {
+                let (first, last) = (parts[0].0, parts[parts.len() - 1]);
+                let joined: String = parts.iter().map(|&(_, part)| part).collect();
+                tokens.push((Cow::Owned(joined), first..last.0 + last.1.len()));
+            }
+        } else {
+            tokens.extend(parts.iter().map(span));
+        }
+        parts.clear();
     }
 
     /// Tokenize text into unique terms (for indexing)
@@ -38,6 +340,133 @@ impl Tokenizer {
         terms.dedup();
         terms
     }
+
+    /// Profile how a set of texts tokenizes, without building an index
+    pub fn analyze(&self, texts: &[&str]) -> TokenStats {
+        let mut stats = TokenStats::default();
+        let mut unique = HashSet::new();
+        for text in texts {
+            for token in self.tokenize(text) {
+                stats.total_tokens += 1;
+                *stats.length_histogram.entry(token.chars().count()).or_insert(0) += 1;
+                unique.insert(token);
+            }
+        }
+        stats.unique_tokens = unique.len();
+        stats
+    }
+}
+
+/// Token statistics for a set of texts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenStats {
+    /// Number of tokens emitted
+    pub total_tokens: usize,
+    /// Number of distinct tokens
+    pub unique_tokens: usize,
+    /// Token length in characters -> number of tokens with that length
+    pub length_histogram: BTreeMap<usize, usize>,
+}
+
+/// Least recently used cache of `Tokenizer::tokenize` results
+#[derive(Default)]
+struct TokenCache(Mutex<LruTokens>);
+
+#[derive(Default)]
+struct LruTokens {
+    entries: HashMap<String, (Vec<String>, u64)>, // text -> (tokens, last use)
+    recency: BTreeMap<u64, String>,                // last use -> text, oldest first
+    clock: u64,
+}
+
+impl TokenCache {
+    /// Cached tokens of a text, marking it most recently used
+    fn get(&self, text: &str) -> Option<Vec<String>> {
+        let mut lru = self.0.lock().ok()?;
+        let lru = &mut *lru;
+        let (tokens, last_use) = lru.entries.get_mut(text)?;
+        lru.clock += 1;
+        lru.recency.remove(last_use);
+        lru.recency.insert(lru.clock, text.to_string());
+        *last_use = lru.clock;
+        Some(tokens.clone())
+    }
+
+    /// Cache a text's tokens, evicting the least recently used past `capacity`
+    fn insert(&self, text: &str, tokens: Vec<String>, capacity: usize) {
+        let Ok(mut lru) = self.0.lock() else {
+            return;
+        };
+        while lru.entries.len() >= capacity {
+            let Some((_, oldest)) = lru.recency.pop_first() else {
+                break;
+            };
+            lru.entries.remove(&oldest);
+        }
+        lru.clock += 1;
+        let clock = lru.clock;
+        lru.recency.insert(clock, text.to_string());
+        if let Some((_, replaced)) = lru.entries.insert(text.to_string(), (tokens, clock)) {
+            lru.recency.remove(&replaced);
+        }
+    }
+}
+
+impl Clone for TokenCache {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for TokenCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let len = self.0.lock().map_or(0, |lru| lru.entries.len());
+        f.debug_struct("TokenCache").field("len", &len).finish()
+    }
+}
+
+/// Whether a language tag selects Turkic dotted/dotless I casing
+fn is_turkic(locale: &str) -> bool {
+    let language = locale.split(['-', '_']).next().unwrap_or("");
+    language.eq_ignore_ascii_case("tr") || language.eq_ignore_ascii_case("az")
+}
+
+/// Whether a word-boundary segment is a word (same rule as `unicode_words`)
+fn is_word(segment: &str) -> bool {
+    segment.chars().any(char::is_alphanumeric)
+}
+
+/// Whether a word-boundary segment is a hyphen joining two words
+fn is_hyphen(segment: &str) -> bool {
+    matches!(segment, "-" | "\u{2010}" | "\u{2011}")
+}
+
+/// Whether a word-boundary segment is a standalone symbol or emoji
+///
+/// Whitespace and punctuation segments are rejected; anything else made of
+/// non-alphanumeric characters (emoji, currency, math symbols) is kept.
+fn is_symbol(segment: &str) -> bool {
+    !is_word(segment) && segment.chars().any(is_symbol_char)
+}
+
+/// Symbol characters, excluding whitespace, controls, joiners and punctuation
+fn is_symbol_char(c: char) -> bool {
+    if c.is_whitespace() || c.is_control() || c.is_alphanumeric() {
+        return false;
+    }
+    if c.is_ascii() {
+        return matches!(c, '$' | '+' | '<' | '=' | '>' | '^' | '`' | '|' | '~');
+    }
+    !matches!(
+        c,
+        // Latin-1 punctuation
+        '\u{00A1}' | '\u{00A7}' | '\u{00AB}' | '\u{00B6}' | '\u{00B7}' | '\u{00BB}' | '\u{00BF}'
+        // General punctuation (dashes, quotes, joiners), CJK punctuation
+        | '\u{2000}'..='\u{206F}'
+        | '\u{3000}'..='\u{303F}'
+        // Variation selectors
+        | '\u{FE00}'..='\u{FE0F}'
+    )
 }
 
 #[cfg(test)]
@@ -71,4 +500,150 @@ mod tests {
         let tokens = tokenizer.tokenize_unique("the quick brown fox jumps over the lazy dog");
         assert_eq!(tokens, vec!["brown", "dog", "fox", "jumps", "lazy", "over", "quick", "the"]);
     }
+
+    #[test]
+    fn test_keep_symbols() {
+        let text = "this is 🔥 news, right?";
+
+        let tokenizer = Tokenizer::default();
+        assert_eq!(tokenizer.tokenize(text), vec!["this", "is", "news", "right"]);
+
+        let tokenizer = Tokenizer::default().with_keep_symbols(true);
+        assert_eq!(tokenizer.tokenize(text), vec!["this", "is", "🔥", "news", "right"]);
+    }
+
+    #[test]
+    fn test_split_on() {
+        let tokenizer = Tokenizer::default();
+        assert_eq!(tokenizer.tokenize("foo_bar/baz"), vec!["foo_bar", "baz"]);
+
+        let tokenizer = Tokenizer::default().with_split_on(vec!['/', ':', '_']);
+        assert_eq!(tokenizer.tokenize("foo_bar/baz"), vec!["foo", "bar", "baz"]);
+    }
+
+    #[test]
+    fn test_analyze() {
+        let tokenizer = Tokenizer::default();
+        let stats = tokenizer.analyze(&["the cat sat", "the dog, the end"]);
+        assert_eq!(stats.total_tokens, 7);
+        assert_eq!(stats.unique_tokens, 5);
+        assert_eq!(stats.length_histogram, BTreeMap::from([(3, 7)]));
+
+        let stats = tokenizer.analyze(&["a bb ccc naïve"]);
+        assert_eq!(stats.total_tokens, 3);
+        assert_eq!(stats.length_histogram, BTreeMap::from([(2, 1), (3, 1), (5, 1)]));
+    }
+
+    #[test]
+    fn test_cache() {
+        let tokenizer = Tokenizer::default().with_cache(2);
+        let fresh = Tokenizer::default();
+        let cached = |text: &str| tokenizer.cache.0.lock().unwrap().entries.contains_key(text);
+
+        for text in ["The Quick fox", "lazy dogs", "The Quick fox"] {
+            assert_eq!(tokenizer.tokenize(text), fresh.tokenize(text));
+        }
+        assert!(cached("The Quick fox") && cached("lazy dogs"));
+
+        // "lazy dogs" is now the least recently used
+        assert_eq!(tokenizer.tokenize("jumps over"), fresh.tokenize("jumps over"));
+        assert!(cached("The Quick fox") && cached("jumps over"));
+        assert!(!cached("lazy dogs"));
+        assert_eq!(tokenizer.tokenize("lazy dogs"), vec!["lazy", "dogs"]);
+        assert_eq!(tokenizer.cache.0.lock().unwrap().entries.len(), 2);
+
+        assert!(format!("{:?}", tokenizer.clone()).contains("TokenCache { len: 0 }"));
+    }
+
+    #[test]
+    fn test_tokenize_stream() {
+        let text = "The Quick-brown fox 🦊 jumps, over a\u{00A0}lazy\tdog/cat. İstanbul x2 42 Ünïcödé";
+        let tokenizers = [
+            Tokenizer::default(),
+            Tokenizer::new(1).with_keep_symbols(true),
+            Tokenizer::default().with_split_on(vec!['/', '-']),
+            Tokenizer::default().with_locale("tr").with_stopwords(["the", "over"]),
+            Tokenizer::default().with_lowercase(false),
+            Tokenizer::default().with_hyphen_mode(HyphenMode::Both),
+            Tokenizer::default().with_analyzer(Analyzer::standard()),
+        ];
+        for tokenizer in &tokenizers {
+            let streamed: Vec<String> = tokenizer.tokenize_stream(text).collect();
+            assert_eq!(streamed, tokenizer.tokenize(text), "{:?}", tokenizer);
+        }
+        assert_eq!(Tokenizer::default().tokenize_stream("").count(), 0);
+    }
+
+    #[test]
+    fn test_lowercase_and_stopwords() {
+        let tokenizer = Tokenizer::default().with_lowercase(false);
+        assert_eq!(tokenizer.tokenize("Rust rust"), vec!["Rust", "rust"]);
+
+        let tokenizer = Tokenizer::default().with_stopwords(["the", "of"]);
+        assert_eq!(tokenizer.tokenize("The Lord of the Rings"), vec!["lord", "rings"]);
+        assert!(tokenizer.tokenize("of the").is_empty());
+        let tokenizer = tokenizer.with_fallback_ignore_stopwords(true);
+        assert_eq!(tokenizer.tokenize("of the"), vec!["of", "the"]);
+    }
+
+    #[test]
+    fn test_locale_lowercasing() {
+        let text = "İstanbul ISTANBUL";
+
+        // Unicode default keeps a combining dot on İ and maps I to i
+        let tokenizer = Tokenizer::default();
+        assert_eq!(tokenizer.tokenize(text), vec!["i\u{307}stanbul", "istanbul"]);
+
+        let tokenizer = Tokenizer::default().with_locale("tr-TR");
+        assert_eq!(tokenizer.tokenize(text), vec!["istanbul", "ıstanbul"]);
+
+        let tokenizer = Tokenizer::default().with_locale("en");
+        assert_eq!(tokenizer.tokenize("ISTANBUL"), vec!["istanbul"]);
+    }
+
+    #[test]
+    fn test_tokenize_spans() {
+        let text = "Café, state-of-the-art/naïve";
+        let tokenizer = Tokenizer::default()
+            .with_split_on(vec!['/'])
+            .with_hyphen_mode(HyphenMode::Both);
+
+        let spans = tokenizer.tokenize_spans(text);
+        let tokens: Vec<&str> = spans.iter().map(|(token, _)| token.as_str()).collect();
+        assert_eq!(tokens, tokenizer.tokenize(text));
+        assert_eq!(&text[spans[0].1.clone()], "Café");
+        assert_eq!(&text[spans[5].1.clone()], "state-of-the-art");
+        assert_eq!(&text[spans[6].1.clone()], "naïve");
+    }
+
+    #[test]
+    fn test_with_analyzer() {
+        use crate::analyzer::{StemFilter, StopwordFilter};
+
+        let analyzer = Analyzer::standard()
+            .with_filter(StopwordFilter::english())
+            .with_filter(StemFilter::english());
+        let tokenizer = Tokenizer::default().with_split_on(vec!['/']).with_analyzer(analyzer);
+        assert_eq!(tokenizer.tokenize("The indexing/searching of documents"), vec!["index", "search", "document"]);
+    }
+
+    #[test]
+    fn test_hyphen_modes() {
+        let text = "a state-of-the-art model";
+
+        let tokenizer = Tokenizer::default();
+        assert_eq!(tokenizer.tokenize(text), vec!["state", "of", "the", "art", "model"]);
+
+        let tokenizer = Tokenizer::default().with_hyphen_mode(HyphenMode::Keep);
+        assert_eq!(tokenizer.tokenize(text), vec!["stateoftheart", "model"]);
+
+        let tokenizer = Tokenizer::default().with_hyphen_mode(HyphenMode::Both);
+        assert_eq!(
+            tokenizer.tokenize(text),
+            vec!["state", "of", "the", "art", "stateoftheart", "model"]
+        );
+
+        // Stray hyphens don't join words
+        assert_eq!(tokenizer.tokenize("pros - cons"), vec!["pros", "cons"]);
+    }
 }
@@ -31,6 +31,13 @@ pub fn decode_varint(reader: &mut impl Read) -> Result<u32> {
     loop {
         reader.read_exact(&mut buf)?;
         let byte = buf[0];
+        // The fifth byte may only carry the top 4 bits of a u32
+        if shift == 28 && byte & 0x70 != 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "varint overflow",
+            ));
+        }
         value |= ((byte & 0x7F) as u32) << shift;
         if byte & 0x80 == 0 {
             break;
@@ -59,21 +66,22 @@ pub fn encode_postings(doc_ids: &[u32]) -> Vec<u8> {
 }
 
 /// Decode delta-compressed postings list
-pub fn decode_postings(data: &[u8]) -> Vec<u32> {
+///
+/// Fails with `InvalidData` on a varint that doesn't fit in a u32 or a
+/// doc_id past `u32::MAX`, and with `UnexpectedEof` on a truncated varint.
+pub fn decode_postings(data: &[u8]) -> Result<Vec<u32>> {
     let mut reader = std::io::Cursor::new(data);
     let mut doc_ids = Vec::new();
     let mut prev = 0u32;
 
     while reader.position() < data.len() as u64 {
-        match decode_varint(&mut reader) {
-            Ok(delta) => {
-                prev += delta;
-                doc_ids.push(prev);
-            }
-            Err(_) => break,
-        }
+        let delta = decode_varint(&mut reader)?;
+        prev = prev.checked_add(delta).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "doc_id overflows u32")
+        })?;
+        doc_ids.push(prev);
     }
-    doc_ids
+    Ok(doc_ids)
 }
 
 #[cfg(test)]
@@ -95,7 +103,7 @@ mod tests {
     fn test_postings_compression() {
         let doc_ids = vec![1, 5, 10, 100, 1000, 10000];
         let compressed = encode_postings(&doc_ids);
-        let decompressed = decode_postings(&compressed);
+        let decompressed = decode_postings(&compressed).unwrap();
         assert_eq!(decompressed, doc_ids);
 
         // Check compression ratio
@@ -107,7 +115,23 @@ mod tests {
     fn test_empty_postings() {
         let doc_ids: Vec<u32> = vec![];
         let compressed = encode_postings(&doc_ids);
-        let decompressed = decode_postings(&compressed);
+        let decompressed = decode_postings(&compressed).unwrap();
         assert_eq!(decompressed, doc_ids);
     }
+
+    #[test]
+    fn test_malformed_postings() {
+        use std::io::ErrorKind;
+
+        // Truncated: continuation bit set on the last byte
+        assert_eq!(decode_postings(&[5, 0x80]).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        // Too many bytes for a u32, and a fifth byte with bits past 32
+        assert_eq!(decode_postings(&[0xFF; 6]).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(decode_postings(&[0xFF, 0xFF, 0xFF, 0xFF, 0x7F]).unwrap_err().kind(), ErrorKind::InvalidData);
+        // Deltas summing past u32::MAX
+        let mut data = Vec::new();
+        encode_varint(u32::MAX, &mut data).unwrap();
+        encode_varint(1, &mut data).unwrap();
+        assert_eq!(decode_postings(&data).unwrap_err().kind(), ErrorKind::InvalidData);
+    }
 }
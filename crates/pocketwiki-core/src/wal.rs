@@ -0,0 +1,90 @@
+//! Append-only write-ahead log of index mutations
+//!
+//! Each entry is one JSON line written straight to the file, so entries
+//! logged before a crash survive it. A torn last line (a crash mid-write)
+//! is dropped when the log is reopened.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+use crate::tokenizer::Tokenizer;
+
+/// One logged index mutation
+///
+/// Metadata set by the `add_document_with_*` style methods is logged as a
+/// separate entry right after the document's `Add`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(crate) enum WalEntry {
+    Add { doc_id: u32, text: String },
+    AddFields { doc_id: u32, fields: Vec<String> },
+    AddCounts { doc_id: u32, counts: Vec<(String, u32)>, doc_len: u32 },
+    Append { doc_id: u32, text: String },
+    Remove { doc_id: u32 },
+    ExternalId { doc_id: u32, external_id: String },
+    Group { doc_id: u32, group_id: String },
+    Prior { doc_id: u32, prior: f32 },
+    Payload { doc_id: u32, payload: String },
+    HashedKey { doc_id: u32, key: String },
+    #[cfg(feature = "vectors")]
+    Vector { doc_id: u32, vector: Vec<f32> },
+    Decay { factor: f32 },
+    Reindex { tokenizer: Tokenizer },
+}
+
+/// Open log file and the first write error not yet reported
+pub(crate) struct Wal {
+    file: File,
+    error: Option<io::Error>,
+}
+
+impl Wal {
+    /// Open or create the log, returning it with the entries to replay
+    pub(crate) fn open(path: impl AsRef<Path>) -> Result<(Self, Vec<WalEntry>)> {
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let mut entries = Vec::new();
+        let mut valid_len = 0u64;
+        for line in BufReader::new(&file).split(b'\n') {
+            let line = line?;
+            match serde_json::from_slice(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(_) => break,
+            }
+            valid_len += line.len() as u64 + 1;
+        }
+        // Cut a torn entry so new ones don't get appended after it
+        let len = file.metadata()?.len();
+        if valid_len < len {
+            file.set_len(valid_len)?;
+        }
+        file.seek(io::SeekFrom::End(0))?;
+        if valid_len > len {
+            file.write_all(b"\n")?;
+        }
+        Ok((Self { file, error: None }, entries))
+    }
+
+    /// Append an entry, keeping the first failure for `take_error`
+    pub(crate) fn append(&mut self, entry: &WalEntry) {
+        let mut line = serde_json::to_vec(entry).expect("WAL entries always serialize");
+        line.push(b'\n');
+        if let Err(e) = self.file.write_all(&line) {
+            self.error.get_or_insert(e);
+        }
+    }
+
+    /// Discard every entry, e.g. once they are covered by a saved snapshot
+    pub(crate) fn truncate(&self) -> Result<()> {
+        self.file.set_len(0)?;
+        Ok(())
+    }
+
+    /// The first append error since the last call, if any
+    pub(crate) fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
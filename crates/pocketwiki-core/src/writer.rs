@@ -0,0 +1,84 @@
+//! One-shot index builder that writes straight to disk
+//!
+//! Useful when only the serialized file is needed. Documents are still
+//! indexed in memory until `finish`, which serializes the index without an
+//! intermediate copy and then drops it.
+
+use std::path::{Path, PathBuf};
+
+use crate::bm25::{BM25Index, BM25Params};
+use crate::error::Result;
+use crate::tokenizer::Tokenizer;
+
+/// Streaming BM25 index writer
+pub struct BM25IndexWriter {
+    path: PathBuf,
+    index: BM25Index,
+}
+
+impl BM25IndexWriter {
+    /// Create a writer targeting `path` with default parameters
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self::with_params(path, BM25Params::default())
+    }
+
+    /// Create a writer with custom parameters
+    pub fn with_params(path: impl AsRef<Path>, params: BM25Params) -> Self {
+        Self::with_tokenizer(path, params, Tokenizer::default())
+    }
+
+    /// Create a writer with custom parameters and tokenizer
+    pub fn with_tokenizer(path: impl AsRef<Path>, params: BM25Params, tokenizer: Tokenizer) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            index: BM25Index::with_tokenizer(params, tokenizer),
+        }
+    }
+
+    /// Add a document to the pending index
+    ///
+    /// Returns `false` if the document tokenized to nothing and was skipped.
+    pub fn add_document(&mut self, doc_id: u32, text: &str) -> bool {
+        self.index.add_document(doc_id, text)
+    }
+
+    /// Write the index to disk and release all in-memory state
+    ///
+    /// Postings aren't built here; the file is marked built so `load`
+    /// builds them instead.
+    pub fn finish(self) -> Result<()> {
+        let Self { path, index } = self;
+        index.save_built(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writer_matches_in_memory() {
+        let docs = [
+            (1, "Python programming language"),
+            (2, "Rust systems programming"),
+            (3, "Python data science"),
+        ];
+        let path = std::env::temp_dir().join(format!("pocketwiki-writer-{}.json", std::process::id()));
+
+        let mut writer = BM25IndexWriter::new(&path);
+        let mut index = BM25Index::new();
+        for (doc_id, text) in docs {
+            writer.add_document(doc_id, text);
+            index.add_document(doc_id, text);
+        }
+        writer.finish().unwrap();
+        index.build();
+
+        let loaded = BM25Index::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(loaded.is_built());
+        assert_eq!(loaded.search("Python programming", 10), index.search("Python programming", 10));
+        assert_eq!(loaded.stats().num_terms, index.stats().num_terms);
+    }
+}
@@ -1,27 +1,58 @@
 //! Python bindings for PocketWiki Rust components
 
-use pocketwiki_core::bm25::{BM25Index as CoreBM25Index, BM25Params, SearchResult as CoreSearchResult};
+// pyo3's generated wrappers for `PyResult` methods trip this lint
+#![allow(clippy::useless_conversion)]
+
+use pocketwiki_core::bm25::{
+    BM25Index as CoreBM25Index, BM25Params, BM25Scorer as CoreBM25Scorer,
+    SearchResult as CoreSearchResult, SearchResultWithPayload as CoreSearchResultWithPayload,
+};
+use pocketwiki_core::{varint, IndexError, Tokenizer as CoreTokenizer};
+use ahash::AHashSet;
+use pyo3::exceptions::{PyIOError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyString};
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
 
 /// Python-exposed search result
 #[pyclass]
 #[derive(Clone)]
 pub struct SearchResult {
+    #[pyo3(get)]
+    pub doc_id: u32,
     #[pyo3(get)]
     pub chunk_id: String,
     #[pyo3(get)]
     pub score: f32,
     #[pyo3(get)]
     pub rank: usize,
+    /// Payload string, only set by search_with_payload
+    #[pyo3(get)]
+    pub payload: Option<String>,
 }
 
 impl From<CoreSearchResult> for SearchResult {
     fn from(result: CoreSearchResult) -> Self {
         Self {
+            doc_id: result.doc_id,
             chunk_id: result.chunk_id,
             score: result.score,
             rank: result.rank,
+            payload: None,
+        }
+    }
+}
+
+impl From<CoreSearchResultWithPayload> for SearchResult {
+    fn from(result: CoreSearchResultWithPayload) -> Self {
+        Self {
+            doc_id: result.doc_id,
+            chunk_id: result.chunk_id,
+            score: result.score,
+            rank: result.rank,
+            payload: result.payload,
         }
     }
 }
@@ -30,17 +61,19 @@ impl From<CoreSearchResult> for SearchResult {
 impl SearchResult {
     fn __repr__(&self) -> String {
         format!(
-            "SearchResult(chunk_id='{}', score={:.4}, rank={})",
-            self.chunk_id, self.score, self.rank
+            "SearchResult(doc_id={}, chunk_id='{}', score={:.4}, rank={})",
+            self.doc_id, self.chunk_id, self.score, self.rank
         )
     }
 
     fn to_dict(&self) -> HashMap<String, PyObject> {
         Python::with_gil(|py| {
             let mut map = HashMap::new();
+            map.insert("doc_id".to_string(), self.doc_id.to_object(py));
             map.insert("chunk_id".to_string(), self.chunk_id.to_object(py));
             map.insert("score".to_string(), self.score.to_object(py));
             map.insert("rank".to_string(), self.rank.to_object(py));
+            map.insert("payload".to_string(), self.payload.to_object(py));
             map
         })
     }
@@ -55,12 +88,44 @@ pub struct BM25Index {
 #[pymethods]
 impl BM25Index {
     /// Create a new BM25 index
+    ///
+    /// Args:
+    ///     k1: Term frequency saturation parameter (default: 1.5)
+    ///     b: Length normalization parameter (default: 0.75)
+    ///     keep_symbols: Index emoji/symbols as tokens (default: False)
+    ///     locale: Language tag for lowercasing, e.g. "tr" (default: None)
+    ///     rank_base: Rank of the top search result, e.g. 1 (default: 0)
+    ///     store_text: Keep document text for highlight() (default: False)
+    ///     min_length: Minimum token length (default: 2)
+    ///     lowercase: Lowercase tokens (default: True)
+    ///     stopwords: Words to drop, compared after lowercasing (default: None)
     #[new]
-    #[pyo3(signature = (k1=1.5, b=0.75))]
-    fn new(k1: f32, b: f32) -> Self {
-        let params = BM25Params { k1, b };
+    #[pyo3(signature = (
+        k1=1.5, b=0.75, keep_symbols=false, locale=None, rank_base=0, store_text=false,
+        min_length=2, lowercase=true, stopwords=None
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        k1: f32,
+        b: f32,
+        keep_symbols: bool,
+        locale: Option<String>,
+        rank_base: usize,
+        store_text: bool,
+        min_length: usize,
+        lowercase: bool,
+        stopwords: Option<Vec<String>>,
+    ) -> Self {
+        let params = BM25Params { k1, b, ..BM25Params::default() };
+        let mut tokenizer = CoreTokenizer::new(min_length)
+            .with_keep_symbols(keep_symbols)
+            .with_lowercase(lowercase)
+            .with_stopwords(stopwords.unwrap_or_default());
+        tokenizer.locale = locale;
         Self {
-            index: CoreBM25Index::with_params(params),
+            index: CoreBM25Index::with_tokenizer(params, tokenizer)
+                .with_rank_base(rank_base)
+                .with_store_text(store_text),
         }
     }
 
@@ -69,34 +134,400 @@ impl BM25Index {
     /// Args:
     ///     doc_id: Document identifier (integer)
     ///     text: Document text content
-    fn add_document(&mut self, doc_id: u32, text: &str) {
-        self.index.add_document(doc_id, text);
+    ///     external_id: Optional external identifier reported as chunk_id
+    ///
+    /// Returns:
+    ///     False if the text tokenized to nothing and the document was skipped
+    ///
+    /// Raises:
+    ///     ValueError: If text or external_id isn't valid Unicode (e.g. has lone surrogates)
+    #[pyo3(signature = (doc_id, text, external_id=None))]
+    fn add_document(
+        &mut self,
+        doc_id: u32,
+        text: &Bound<'_, PyString>,
+        external_id: Option<&Bound<'_, PyString>>,
+    ) -> PyResult<bool> {
+        let text = utf8_arg(text, "text")?;
+        Ok(match external_id {
+            Some(external_id) => {
+                let external_id = utf8_arg(external_id, "external_id")?;
+                self.index.add_document_with_external_id(doc_id, &external_id, &text)
+            }
+            None => self.index.add_document(doc_id, &text),
+        })
+    }
+
+    /// Precompute IDF and other caches so the first searches are fast
+    ///
+    /// Call after build() or load(); adding documents drops the caches.
+    fn warm_up(&mut self) {
+        self.index.warm_up();
+    }
+
+    /// Add a document with an opaque payload string, e.g. JSON metadata
+    ///
+    /// Args:
+    ///     doc_id: Document identifier (integer)
+    ///     text: Document text content
+    ///     payload: String saved with the index and returned by search_with_payload
+    ///
+    /// Returns:
+    ///     False if the text tokenized to nothing and the document was skipped
+    fn add_document_with_payload(&mut self, doc_id: u32, text: &str, payload: String) -> bool {
+        self.index.add_document_with_payload(doc_id, text, payload)
+    }
+
+    /// Get the payload stored for a doc_id
+    ///
+    /// Returns:
+    ///     The payload string (parse it yourself), or None if none was stored
+    fn payload(&self, doc_id: u32) -> Option<String> {
+        self.index.payload(doc_id).map(str::to_string)
+    }
+
+    /// Search, setting each result's payload attribute
+    ///
+    /// Args:
+    ///     query: Search query
+    ///     k: Number of results to return
+    ///
+    /// Returns:
+    ///     List of SearchResult objects; payload is None for documents without one
+    #[pyo3(signature = (query, k=10))]
+    fn search_with_payload(&self, query: &str, k: usize) -> Vec<SearchResult> {
+        self.index.search_with_payload(query, k).into_iter().map(SearchResult::from).collect()
+    }
+
+    /// Resolve an external identifier to its doc_id
+    ///
+    /// Returns:
+    ///     The doc_id, or None if the external id is unknown
+    fn resolve_id(&self, external: &str) -> Option<u32> {
+        self.index.resolve_id(external)
+    }
+
+    /// Get the external identifier registered for a doc_id
+    ///
+    /// Returns:
+    ///     The external id, or None if none was registered
+    fn external_id(&self, doc_id: u32) -> Option<String> {
+        self.index.external_id(doc_id).map(str::to_string)
+    }
+
+    /// Save the index to a file
+    ///
+    /// Args:
+    ///     path: Destination file path
+    fn save(&self, path: &str) -> PyResult<()> {
+        self.index.save(path).map_err(to_py_err)
+    }
+
+    /// Load an index from a file
+    ///
+    /// Args:
+    ///     path: Source file path
+    ///
+    /// Returns:
+    ///     The loaded BM25Index
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<Self> {
+        let index = CoreBM25Index::load(path).map_err(to_py_err)?;
+        Ok(Self { index })
+    }
+
+    /// Add documents from a JSON-lines file
+    ///
+    /// Args:
+    ///     path: Path to a .jsonl file with one JSON object per line
+    ///     id_field: Field holding the integer doc_id (default: "id")
+    ///     text_field: Field holding the document text (default: "text")
+    ///     skip_malformed: Skip bad lines instead of raising (default: False)
+    ///
+    /// Returns:
+    ///     Number of documents indexed
+    ///
+    /// Raises:
+    ///     IOError: If the file can't be read or, unless skip_malformed is
+    ///         set, a line is malformed
+    #[pyo3(signature = (path, id_field="id", text_field="text", skip_malformed=false))]
+    fn index_jsonl(&mut self, path: &str, id_field: &str, text_field: &str, skip_malformed: bool) -> PyResult<usize> {
+        let file = File::open(path).map_err(|e| PyIOError::new_err(e.to_string()))?;
+        self.index
+            .index_jsonl(BufReader::new(file), id_field, text_field, skip_malformed)
+            .map_err(|e| PyIOError::new_err(e.to_string()))
+    }
+
+    /// Check the index's internal invariants
+    ///
+    /// Raises:
+    ///     ValueError: If the index is corrupt
+    fn verify(&self) -> PyResult<()> {
+        self.index.verify().map_err(|e| PyValueError::new_err(e.to_string()))
     }
 
     /// Build the index (must call after adding all documents)
+    ///
+    /// Idempotent: does nothing if the index is already built and no
+    /// documents were added since.
     fn build(&mut self) {
-        self.index.build();
+        if !self.index.is_built() {
+            self.index.build();
+        }
+    }
+
+    /// Whether build() has run since the last document was added
+    ///
+    /// Always False for an empty index.
+    #[getter]
+    fn is_built(&self) -> bool {
+        self.index.is_built()
+    }
+
+    /// Deterministically sample indexed doc_ids
+    ///
+    /// Args:
+    ///     n: Maximum number of doc_ids to return
+    ///     seed: RNG seed; the same seed yields the same sample (default: 0)
+    #[pyo3(signature = (n, seed=0))]
+    fn sample_docs(&self, n: usize, seed: u64) -> Vec<u32> {
+        self.index.sample_docs(n, seed)
+    }
+
+    /// Whether a (tokenized, lowercased) term exists in the index
+    fn contains_term(&self, term: &str) -> bool {
+        self.index.contains_term(term)
+    }
+
+    /// Whether any token of the text exists in the index
+    fn contains_tokenized_query(&self, text: &str) -> bool {
+        self.index.contains_tokenized_query(text)
+    }
+
+    /// List every term with its document frequency
+    ///
+    /// Returns:
+    ///     List of (term, doc_freq) tuples
+    fn vocabulary(&self) -> Vec<(String, usize)> {
+        self.index
+            .vocabulary()
+            .map(|(term, doc_freq)| (term.to_string(), doc_freq))
+            .collect()
+    }
+
+    /// Number of documents containing a (tokenized) term
+    fn posting_len(&self, term: &str) -> usize {
+        self.index.posting_len(term)
+    }
+
+    /// Doc_ids of every document containing a (tokenized) term, ascending
+    fn docs_for_term(&self, term: &str) -> Vec<u32> {
+        self.index.docs_for_term(term)
+    }
+
+    /// A document's term counts, reconstructed from the index
+    ///
+    /// Scans the whole vocabulary, so use it offline (e.g. feature
+    /// extraction), not per query.
+    ///
+    /// Returns:
+    ///     Dictionary mapping each term to its count; empty for an unknown doc_id
+    fn document_terms(&self, doc_id: u32) -> HashMap<String, u32> {
+        self.index.document_terms(doc_id).into_iter().collect()
+    }
+
+    /// Doc_ids a query would score, without computing BM25
+    ///
+    /// The union of the documents containing any query token, for ranking
+    /// with a custom model.
+    ///
+    /// Returns:
+    ///     List of doc_ids, ascending
+    fn candidates(&self, query: &str) -> Vec<u32> {
+        self.index.candidates(query)
+    }
+
+    /// Postings list lengths for several (tokenized) terms
+    ///
+    /// Returns:
+    ///     Dictionary mapping each term to its document count
+    fn posting_lens(&self, terms: Vec<String>) -> HashMap<String, usize> {
+        let terms: Vec<&str> = terms.iter().map(String::as_str).collect();
+        self.index.posting_lens(&terms)
+    }
+
+    /// IDF of each distinct query term
+    ///
+    /// Args:
+    ///     query: Query text
+    ///
+    /// Returns:
+    ///     List of (term, idf) tuples in query order; 0.0 for unknown terms
+    fn query_idfs(&self, query: &str) -> Vec<(String, f32)> {
+        self.index.query_idfs(query)
+    }
+
+    /// IDF of every term in the vocabulary
+    ///
+    /// Returns:
+    ///     Dictionary mapping each term to its IDF
+    fn all_idfs(&self) -> HashMap<String, f32> {
+        self.index.all_idfs().into_iter().collect()
     }
 
     /// Search the index
     ///
+    /// Works without build(): scoring reads the term frequencies, so
+    /// documents are searchable as soon as they are added. build() only
+    /// compresses postings, which posting-based lookups use.
+    ///
     /// Args:
     ///     query: Search query text
     ///     k: Number of results to return (default: 10)
+    ///     k1: Override k1 for this search only (default: index setting)
+    ///     b: Override b for this search only (default: index setting)
     ///
     /// Returns:
     ///     List of SearchResult objects
-    #[pyo3(signature = (query, k=10))]
-    fn search(&self, query: &str, k: usize) -> Vec<SearchResult> {
+    ///
+    /// Raises:
+    ///     ValueError: If query isn't valid Unicode (e.g. has lone surrogates)
+    #[pyo3(signature = (query, k=10, k1=None, b=None))]
+    fn search(
+        &self,
+        query: &Bound<'_, PyString>,
+        k: usize,
+        k1: Option<f32>,
+        b: Option<f32>,
+    ) -> PyResult<Vec<SearchResult>> {
+        let query = utf8_arg(query, "query")?;
+        let results = if k1.is_none() && b.is_none() {
+            self.index.search(&query, k)
+        } else {
+            let params = self.index.params();
+            let params = BM25Params {
+                k1: k1.unwrap_or(params.k1),
+                b: b.unwrap_or(params.b),
+                ..params
+            };
+            self.index.search_with_params(&query, k, params)
+        };
+        Ok(results.into_iter().map(SearchResult::from).collect())
+    }
+
+    /// BM25 score of one document for a query
+    ///
+    /// Returns:
+    ///     The score `search` would report, or None if the doc isn't indexed
+    fn score_pair(&self, query: &str, doc_id: u32) -> Option<f32> {
+        self.index.score_pair(query, doc_id)
+    }
+
+    /// Search with scores mapped to 0..1 probabilities
+    ///
+    /// Args:
+    ///     query: Search query
+    ///     k: Number of results to return (default: 10)
+    ///     sigmoid_params: (a, b) of the logistic 1 / (1 + exp(-(a * score + b)))
+    ///         (default: (1.0, 0.0))
+    ///
+    /// Returns:
+    ///     List of SearchResult objects, ordered as search() with calibrated scores
+    #[pyo3(signature = (query, k=10, sigmoid_params=(1.0, 0.0)))]
+    fn search_calibrated(&self, query: &str, k: usize, sigmoid_params: (f32, f32)) -> Vec<SearchResult> {
         self.index
-            .search(query, k)
+            .search_calibrated(query, k, sigmoid_params)
             .into_iter()
             .map(SearchResult::from)
             .collect()
     }
 
+    /// Search many queries in one call
+    ///
+    /// Releases the GIL while searching; with the `parallel` feature the
+    /// queries run concurrently.
+    ///
+    /// Args:
+    ///     queries: List of query texts
+    ///     k: Number of results per query (default: 10)
+    ///
+    /// Returns:
+    ///     List of SearchResult lists, one per query in order
+    #[pyo3(signature = (queries, k=10))]
+    fn search_batch(&self, py: Python<'_>, queries: Vec<String>, k: usize) -> Vec<Vec<SearchResult>> {
+        let queries: Vec<&str> = queries.iter().map(String::as_str).collect();
+        py.allow_threads(|| self.index.search_batch(&queries, k))
+            .into_iter()
+            .map(|results| results.into_iter().map(SearchResult::from).collect())
+            .collect()
+    }
+
+    /// Search only within an allow-list of doc_ids
+    ///
+    /// Args:
+    ///     query: Search query text
+    ///     allowed: Set or list of permitted doc_ids
+    ///     k: Number of results to return (default: 10)
+    ///
+    /// Returns:
+    ///     List of SearchResult objects, all from the allowed set
+    #[pyo3(signature = (query, allowed, k=10))]
+    fn search_within(&self, query: &str, allowed: &Bound<'_, PyAny>, k: usize) -> PyResult<Vec<SearchResult>> {
+        let allowed = allowed
+            .iter()?
+            .map(|doc_id| doc_id?.extract::<u32>())
+            .collect::<PyResult<AHashSet<u32>>>()?;
+        Ok(self
+            .index
+            .search_within(query, k, &allowed)
+            .into_iter()
+            .map(SearchResult::from)
+            .collect())
+    }
+
+    /// Search the index and report per-phase timings
+    ///
+    /// Returns:
+    ///     Tuple of (results, timings) where timings maps tokenize,
+    ///     candidates, scoring, sort and total to seconds
+    #[pyo3(signature = (query, k=10))]
+    fn search_timed(&self, query: &str, k: usize) -> (Vec<SearchResult>, HashMap<String, f64>) {
+        let (results, timings) = self.index.search_timed(query, k);
+        let timings = HashMap::from([
+            ("tokenize".to_string(), timings.tokenize.as_secs_f64()),
+            ("candidates".to_string(), timings.candidates.as_secs_f64()),
+            ("scoring".to_string(), timings.scoring.as_secs_f64()),
+            ("sort".to_string(), timings.sort.as_secs_f64()),
+            ("total".to_string(), timings.total.as_secs_f64()),
+        ]);
+        (results.into_iter().map(SearchResult::from).collect(), timings)
+    }
+
+    /// Measure search latency over a list of queries
+    ///
+    /// Args:
+    ///     queries: Query strings to run
+    ///     k: Number of results per search (default: 10)
+    ///     iterations: Times each query is run (default: 10)
+    ///
+    /// Returns:
+    ///     Dictionary mapping mean, p50, p95 and p99 to seconds
+    #[pyo3(signature = (queries, k=10, iterations=10))]
+    fn benchmark(&self, queries: Vec<String>, k: usize, iterations: usize) -> HashMap<String, f64> {
+        let queries: Vec<&str> = queries.iter().map(String::as_str).collect();
+        let report = self.index.benchmark(&queries, k, iterations);
+        HashMap::from([
+            ("mean".to_string(), report.mean.as_secs_f64()),
+            ("p50".to_string(), report.p50.as_secs_f64()),
+            ("p95".to_string(), report.p95.as_secs_f64()),
+            ("p99".to_string(), report.p99.as_secs_f64()),
+        ])
+    }
+
     /// Get index statistics
     ///
+    /// Always current, whether or not build() has run.
+    ///
     /// Returns:
     ///     Dictionary with num_docs, num_terms, avg_doc_len
     fn stats(&self) -> HashMap<String, PyObject> {
@@ -110,6 +541,52 @@ impl BM25Index {
         })
     }
 
+    /// Tokenizer settings as a dictionary
+    ///
+    /// Keys: min_length, lowercase, stopwords (sorted list), keep_symbols, locale
+    #[getter]
+    fn tokenizer_config(&self) -> HashMap<String, PyObject> {
+        let tokenizer = self.index.tokenizer();
+        Python::with_gil(|py| {
+            let stopwords: Vec<&str> = tokenizer.stopwords.iter().map(String::as_str).collect();
+            let mut map = HashMap::new();
+            map.insert("min_length".to_string(), tokenizer.min_length.to_object(py));
+            map.insert("lowercase".to_string(), tokenizer.lowercase.to_object(py));
+            map.insert("stopwords".to_string(), stopwords.to_object(py));
+            map.insert("keep_symbols".to_string(), tokenizer.keep_symbols.to_object(py));
+            map.insert("locale".to_string(), tokenizer.locale.to_object(py));
+            map
+        })
+    }
+
+    /// Total number of tokens indexed across all documents
+    #[getter]
+    fn total_tokens(&self) -> u64 {
+        self.index.total_tokens()
+    }
+
+    /// Set the markers highlight() wraps around matches
+    ///
+    /// Args:
+    ///     open: Marker inserted before each match (default: "<em>")
+    ///     close: Marker inserted after each match (default: "</em>")
+    fn set_highlight_markers(&mut self, open: &str, close: &str) {
+        self.index.set_highlight_markers(open, close);
+    }
+
+    /// Stored text of a document with query term matches marked
+    ///
+    /// Returns:
+    ///     Highlighted text, or None if the document's text isn't stored
+    fn highlight(&self, doc_id: u32, query: &str) -> Option<String> {
+        self.index.highlight(doc_id, query)
+    }
+
+    /// Human-readable summary of the index for debugging
+    fn report(&self) -> String {
+        self.index.report()
+    }
+
     fn __repr__(&self) -> String {
         let stats = self.index.stats();
         format!(
@@ -119,10 +596,151 @@ impl BM25Index {
     }
 }
 
+/// Read a Python string as UTF-8, rejecting lone surrogates with a ValueError
+fn utf8_arg<'a>(value: &'a Bound<'_, PyString>, name: &str) -> PyResult<std::borrow::Cow<'a, str>> {
+    value
+        .to_cow()
+        .map_err(|err| PyValueError::new_err(format!("{} is not valid Unicode: {}", name, err)))
+}
+
+/// Convert a core index error into a Python exception
+fn to_py_err(err: IndexError) -> PyErr {
+    match err {
+        IndexError::Io(e) => PyIOError::new_err(e.to_string()),
+        other => PyValueError::new_err(other.to_string()),
+    }
+}
+
+/// Python-exposed standalone BM25 scorer
+#[pyclass]
+pub struct BM25Scorer {
+    scorer: CoreBM25Scorer,
+}
+
+#[pymethods]
+impl BM25Scorer {
+    /// Create a new BM25 scorer
+    ///
+    /// Args:
+    ///     k1: Term frequency saturation parameter
+    ///     b: Length normalization parameter
+    ///     avg_doc_len: Average document length of the corpus
+    ///     doc_count: Number of documents in the corpus
+    #[new]
+    #[pyo3(signature = (k1=1.5, b=0.75, avg_doc_len=1.0, doc_count=0))]
+    fn new(k1: f32, b: f32, avg_doc_len: f32, doc_count: usize) -> Self {
+        let params = BM25Params { k1, b, ..BM25Params::default() };
+        Self {
+            scorer: CoreBM25Scorer::new(params, avg_doc_len, doc_count),
+        }
+    }
+
+    /// Calculate BM25 score for a single term
+    ///
+    /// Args:
+    ///     tf: Term frequency in the document
+    ///     doc_len: Document length in tokens
+    ///     doc_freq: Number of documents containing the term
+    ///
+    /// Returns:
+    ///     BM25 score as a float
+    fn score_term(&self, tf: f32, doc_len: f32, doc_freq: usize) -> f32 {
+        self.scorer.score_term(tf, doc_len, doc_freq)
+    }
+}
+
+/// Python-exposed tokenizer
+#[pyclass]
+pub struct Tokenizer {
+    tokenizer: CoreTokenizer,
+}
+
+#[pymethods]
+impl Tokenizer {
+    /// Create a new tokenizer
+    ///
+    /// Args:
+    ///     min_length: Minimum token length (default: 2)
+    ///     keep_symbols: Emit emoji/symbols as tokens (default: False)
+    ///     locale: Language tag for lowercasing, e.g. "tr" (default: None)
+    #[new]
+    #[pyo3(signature = (min_length=2, keep_symbols=false, locale=None))]
+    fn new(min_length: usize, keep_symbols: bool, locale: Option<String>) -> Self {
+        let mut tokenizer = CoreTokenizer::new(min_length).with_keep_symbols(keep_symbols);
+        tokenizer.locale = locale;
+        Self { tokenizer }
+    }
+
+    /// Tokenize text into terms
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self.tokenizer.tokenize(text)
+    }
+
+    /// Profile tokenization over a list of texts
+    ///
+    /// Returns:
+    ///     Dictionary with total_tokens, unique_tokens and length_histogram
+    ///     (token length in characters -> count)
+    fn analyze(&self, texts: Vec<String>) -> HashMap<String, PyObject> {
+        let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+        let stats = self.tokenizer.analyze(&texts);
+        Python::with_gil(|py| {
+            let mut map = HashMap::new();
+            map.insert("total_tokens".to_string(), stats.total_tokens.to_object(py));
+            map.insert("unique_tokens".to_string(), stats.unique_tokens.to_object(py));
+            map.insert("length_histogram".to_string(), stats.length_histogram.to_object(py));
+            map
+        })
+    }
+}
+
+/// Delta/VByte-encode a sorted list of doc ids
+///
+/// Args:
+///     doc_ids: Doc ids in ascending order, each in 0..2**32
+///
+/// Returns:
+///     The encoded postings as bytes
+///
+/// Raises:
+///     ValueError: If an id is negative, too large, or smaller than the one before it
+#[pyfunction]
+fn encode_postings(py: Python<'_>, doc_ids: Vec<i64>) -> PyResult<Py<PyBytes>> {
+    let mut checked = Vec::with_capacity(doc_ids.len());
+    for (i, &doc_id) in doc_ids.iter().enumerate() {
+        let doc_id = u32::try_from(doc_id)
+            .map_err(|_| PyValueError::new_err(format!("doc id {} at index {} is out of range", doc_id, i)))?;
+        if checked.last().is_some_and(|&prev| doc_id < prev) {
+            return Err(PyValueError::new_err(format!("doc ids are not sorted at index {}", i)));
+        }
+        checked.push(doc_id);
+    }
+    Ok(PyBytes::new_bound(py, &varint::encode_postings(&checked)).unbind())
+}
+
+/// Decode postings produced by encode_postings
+///
+/// Args:
+///     data: Encoded postings bytes
+///
+/// Returns:
+///     The doc ids in ascending order
+///
+/// Raises:
+///     ValueError: If the bytes are truncated or decode past 2**32 - 1
+#[pyfunction]
+fn decode_postings(data: &[u8]) -> PyResult<Vec<u32>> {
+    varint::decode_postings(data).map_err(|e| PyValueError::new_err(format!("invalid postings data: {}", e)))
+}
+
 /// Python module
 #[pymodule]
 fn pocketwiki_rust(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<BM25Index>()?;
     m.add_class::<SearchResult>()?;
+    m.add_class::<BM25Scorer>()?;
+    m.add_class::<Tokenizer>()?;
+    m.add_function(wrap_pyfunction!(encode_postings, m)?)?;
+    m.add_function(wrap_pyfunction!(decode_postings, m)?)?;
     Ok(())
 }
@@ -1,10 +1,11 @@
 """Tests for Rust BM25 integration."""
 import json
+import math
 import pytest
 from pathlib import Path
 
 try:
-    from pocketwiki_rust import BM25Index
+    from pocketwiki_rust import BM25Index, BM25Scorer, Tokenizer, decode_postings, encode_postings
     RUST_AVAILABLE = True
 except ImportError:
     RUST_AVAILABLE = False
@@ -29,6 +30,7 @@ class TestRustBM25:
         # Search
         results = index.search("Python programming", k=2)
         assert len(results) == 2
+        assert results[0].doc_id == 1
         assert results[0].chunk_id == "chunk_1"
         assert results[0].score > results[1].score
         assert results[0].rank == 0
@@ -61,9 +63,11 @@ class TestRustBM25:
         results = index.search("test", k=1)
         assert len(results) == 1
         result_dict = results[0].to_dict()
+        assert result_dict["doc_id"] == 1
         assert "chunk_id" in result_dict
         assert "score" in result_dict
         assert "rank" in result_dict
+        assert result_dict["payload"] is None
 
     def test_index_stats(self):
         """Test index statistics."""
@@ -77,6 +81,327 @@ class TestRustBM25:
         assert stats["num_terms"] > 0
         assert stats["avg_doc_len"] > 0
 
+    def test_external_ids_survive_save_load(self, tmp_path):
+        """Test external ids resolve both ways after a save/load round trip."""
+        index = BM25Index()
+        index.add_document(7, "Python programming language", external_id="enwiki:Python#0")
+        index.add_document(8, "Rust systems programming")
+        index.build()
+
+        path = tmp_path / "index.json"
+        index.save(str(path))
+        loaded = BM25Index.load(str(path))
+
+        assert loaded.resolve_id("enwiki:Python#0") == 7
+        assert loaded.external_id(7) == "enwiki:Python#0"
+        assert loaded.external_id(8) is None
+        assert loaded.resolve_id("missing") is None
+        assert loaded.search("python", k=1)[0].chunk_id == "enwiki:Python#0"
+
+    def test_load_missing_file(self, tmp_path):
+        """Test loading a missing index raises IOError."""
+        with pytest.raises(IOError):
+            BM25Index.load(str(tmp_path / "missing.json"))
+
+    def test_total_tokens(self):
+        """Test total_tokens counts every indexed token."""
+        index = BM25Index()
+        index.add_document(1, "Python programming language")
+        index.add_document(2, "Rust systems programming")
+        assert index.total_tokens == 6
+
+    def test_highlight_markers(self):
+        """Test custom highlight markers wrap matched terms."""
+        index = BM25Index(store_text=True)
+        index.add_document(1, "Rust is fast, rust is safe")
+        assert index.highlight(1, "rust") == "<em>Rust</em> is fast, <em>rust</em> is safe"
+
+        index.set_highlight_markers("**", "**")
+        assert index.highlight(1, "safe") == "Rust is fast, rust is **safe**"
+        assert index.highlight(2, "rust") is None
+
+    def test_index_jsonl(self, tmp_path):
+        """Test indexing documents from a JSON-lines file."""
+        path = tmp_path / "docs.jsonl"
+        path.write_text(
+            '{"id": 1, "text": "Python programming language"}\n'
+            "not json\n"
+            '{"id": 2, "text": "Rust systems programming"}\n'
+        )
+
+        index = BM25Index()
+        assert index.index_jsonl(str(path), skip_malformed=True) == 2
+        index.build()
+        assert len(index.search("programming", k=10)) == 2
+
+        with pytest.raises(IOError):
+            BM25Index().index_jsonl(str(path))
+
+    def test_invalid_unicode_raises(self):
+        """Test lone surrogates raise ValueError instead of crashing."""
+        index = BM25Index()
+        bad = "broken \ud800 text"
+
+        with pytest.raises(ValueError, match="text is not valid Unicode"):
+            index.add_document(1, bad)
+        with pytest.raises(ValueError, match="external_id is not valid Unicode"):
+            index.add_document(1, "fine", external_id=bad)
+        with pytest.raises(ValueError, match="query is not valid Unicode"):
+            index.search(bad)
+
+        assert index.add_document(1, "still usable")
+        index.build()
+        assert len(index.search("usable")) == 1
+
+    def test_vocabulary(self):
+        """Test vocabulary listing matches the term count."""
+        index = BM25Index()
+        index.add_document(1, "the quick brown fox")
+        index.add_document(2, "the lazy dog")
+        index.build()
+
+        vocab = dict(index.vocabulary())
+        assert len(vocab) == index.stats()["num_terms"]
+        assert vocab["the"] == 2
+
+    def test_search_k1_override(self):
+        """Test per-search k1/b overrides reorder results without changing the index."""
+        index = BM25Index()
+        index.add_document(1, "apple apple apple apple apple apple")
+        index.add_document(2, "apple banana")
+        index.add_document(3, "cherry")
+        index.add_document(4, "grape")
+        index.build()
+
+        assert index.search("apple banana", k=1, k1=0.01, b=0.0)[0].doc_id == 2
+        assert index.search("apple banana", k=1, k1=100.0, b=0.0)[0].doc_id == 1
+        default = index.search("apple banana", k=2)
+        assert [r.doc_id for r in default] == [2, 1]
+        assert index.search("apple banana", k=2, k1=1.5, b=0.75)[0].score == pytest.approx(default[0].score)
+
+    def test_search_batch(self):
+        """Test batched search matches individual searches."""
+        index = BM25Index()
+        index.add_document(1, "Python programming language")
+        index.add_document(2, "Rust systems programming")
+        index.add_document(3, "Python data science")
+        index.build()
+
+        queries = ["python", "rust programming", "missing"]
+        batched = index.search_batch(queries, k=2)
+        assert len(batched) == len(queries)
+        for query, results in zip(queries, batched):
+            expected = index.search(query, k=2)
+            assert [r.to_dict() for r in results] == [r.to_dict() for r in expected]
+
+    def test_search_calibrated(self):
+        """Test calibrated scores are probabilities in search order."""
+        index = BM25Index()
+        index.add_document(1, "Python programming language")
+        index.add_document(2, "Rust systems programming")
+        index.add_document(3, "Python data science")
+        index.build()
+
+        raw = index.search("python programming", k=3)
+        calibrated = index.search_calibrated("python programming", k=3, sigmoid_params=(0.5, -1.0))
+        assert [r.doc_id for r in calibrated] == [r.doc_id for r in raw]
+        for r, c in zip(raw, calibrated):
+            assert 0.0 < c.score < 1.0
+            assert c.score == pytest.approx(1.0 / (1.0 + math.exp(-(0.5 * r.score - 1.0))), rel=1e-5)
+
+    def test_is_built(self):
+        """Test is_built tracks build() and later additions."""
+        index = BM25Index()
+        index.add_document(1, "Python programming language")
+        assert index.is_built is False
+
+        index.build()
+        assert index.is_built is True
+        index.build()  # idempotent
+        assert index.is_built is True
+        assert index.stats()["num_terms"] == 3
+
+        index.add_document(2, "Rust systems programming")
+        assert index.is_built is False
+        assert index.stats()["num_terms"] == 5
+
+    def test_document_terms(self):
+        """Test a document's bag of words is reconstructed from the index."""
+        index = BM25Index()
+        index.add_document(1, "The cat sat on the mat; the cat slept.")
+
+        assert index.document_terms(1) == {"the": 3, "cat": 2, "sat": 1, "on": 1, "mat": 1, "slept": 1}
+        assert index.document_terms(42) == {}
+
+    def test_all_idfs(self):
+        """Test every term gets the IDF that query_idfs reports."""
+        index = BM25Index()
+        index.add_document(1, "Python programming language")
+        index.add_document(2, "Rust systems programming")
+        index.build()
+
+        idfs = index.all_idfs()
+        assert len(idfs) == index.stats()["num_terms"]
+        assert idfs["programming"] == index.query_idfs("programming")[0][1]
+        assert idfs["python"] > idfs["programming"]
+
+    def test_candidates(self):
+        """Test candidates are exactly the docs containing a query token."""
+        index = BM25Index()
+        index.add_document(42, "Python programming language")
+        index.add_document(7, "Rust systems programming")
+        index.add_document(19, "Python data science")
+        index.add_document(3, "Cooking recipes")
+
+        assert index.candidates("python programming") == [7, 19, 42]
+        assert index.candidates("science recipes") == [3, 19]
+        assert index.candidates("missing") == []
+
+    def test_docs_for_term(self):
+        """Test postings for a term list the documents that contain it."""
+        index = BM25Index()
+        index.add_document(42, "Python programming language")
+        index.add_document(7, "Rust systems programming")
+        index.add_document(19, "Python data science")
+        index.build()
+
+        assert index.docs_for_term("python") == [19, 42]
+        assert index.docs_for_term("missing") == []
+
+    def test_search_within(self):
+        """Test filtered search never returns disallowed docs."""
+        index = BM25Index()
+        index.add_document(1, "python python python")
+        index.add_document(2, "python programming language")
+        index.add_document(3, "python snake")
+        index.build()
+
+        for allowed in ({2, 3}, [2, 3]):
+            results = index.search_within("python", allowed, k=2)
+            assert [r.doc_id for r in results] == [3, 2]
+
+    def test_contains_term(self):
+        """Test term existence checks."""
+        index = BM25Index()
+        index.add_document(1, "Python programming language")
+        index.build()
+
+        assert index.contains_term("python")
+        assert not index.contains_term("rust")
+        assert index.contains_tokenized_query("Rust or Python")
+        assert not index.contains_tokenized_query("Rust systems")
+
+    def test_keep_symbols(self):
+        """Test emoji queries match emoji-bearing docs when enabled."""
+        index = BM25Index(keep_symbols=True)
+        index.add_document(1, "the crowd reacted with 🔥 everywhere")
+        index.add_document(2, "the crowd was quiet")
+        index.build()
+
+        results = index.search("🔥", k=10)
+        assert len(results) == 1
+        assert results[0].chunk_id == "chunk_1"
+
+    def test_payload_roundtrip(self, tmp_path):
+        """Test payloads are saved, loaded and returned with results."""
+        payload = json.dumps({"url": "https://example.org/Rust", "section": "History"})
+        index = BM25Index()
+        index.add_document_with_payload(1, "Rust systems programming", payload)
+        index.add_document(2, "Python programming language")
+        index.build()
+
+        path = tmp_path / "index.jsonl"
+        index.save(str(path))
+        loaded = BM25Index.load(str(path))
+
+        assert json.loads(loaded.payload(1))["section"] == "History"
+        assert loaded.payload(2) is None
+        results = loaded.search_with_payload("rust programming", k=2)
+        assert results[0].payload == payload
+        assert results[1].payload is None
+        assert results[0].to_dict()["payload"] == payload
+        assert results[1].to_dict()["payload"] is None
+        assert loaded.search("rust", k=1)[0].payload is None
+
+    def test_tokenizer_options(self):
+        """Test tokenizer keyword arguments and tokenizer_config."""
+        index = BM25Index(min_length=4, stopwords=["with"])
+        index.add_document(1, "Go is fun with Rust code")
+        index.build()
+
+        assert index.search("go", k=10) == []
+        assert index.search("fun", k=10) == []
+        assert index.search("with", k=10) == []
+        assert index.search("rust", k=10)[0].doc_id == 1
+        assert index.total_tokens == 2
+
+        config = index.tokenizer_config
+        assert config["min_length"] == 4
+        assert config["lowercase"] is True
+        assert config["stopwords"] == ["with"]
+        assert BM25Index().tokenizer_config["stopwords"] == []
+
+
+@pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extension not available")
+class TestRustBM25Scorer:
+    """Test standalone Rust BM25 scorer."""
+
+    def test_score_term_matches_formula(self):
+        """Test scorer output against a hand-computed BM25 value."""
+        k1, b, avg_doc_len, doc_count = 1.2, 0.75, 10.0, 100
+        tf, doc_len, df = 2.0, 12.0, 10
+
+        scorer = BM25Scorer(k1=k1, b=b, avg_doc_len=avg_doc_len, doc_count=doc_count)
+
+        idf = math.log((doc_count - df + 0.5) / (df + 0.5) + 1.0)
+        tf_part = (tf * (k1 + 1.0)) / (tf + k1 * (1.0 - b + b * doc_len / avg_doc_len))
+        expected = idf * tf_part
+
+        assert scorer.score_term(tf, doc_len, df) == pytest.approx(expected, rel=1e-5)
+
+
+@pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extension not available")
+class TestRustTokenizer:
+    """Test Rust tokenizer helpers."""
+
+    def test_analyze(self):
+        """Test corpus token statistics."""
+        tokenizer = Tokenizer()
+        stats = tokenizer.analyze(["the cat sat", "a dog, the end"])
+
+        assert stats["total_tokens"] == 6
+        assert stats["unique_tokens"] == 5
+        assert stats["length_histogram"] == {3: 6}
+
+
+@pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extension not available")
+class TestRustPostings:
+    """Test postings compression helpers."""
+
+    def test_roundtrip(self):
+        """Test encoding and decoding postings lists."""
+        for doc_ids in ([], [0], [1, 5, 5, 300, 2**32 - 1]):
+            data = encode_postings(doc_ids)
+            assert isinstance(data, bytes)
+            assert decode_postings(data) == doc_ids
+        assert encode_postings([]) == b""
+        assert encode_postings([1, 128]) == bytes([1, 127])
+
+    def test_invalid_input_raises(self):
+        """Test unsorted, negative and oversized ids are rejected."""
+        with pytest.raises(ValueError, match="not sorted"):
+            encode_postings([3, 1])
+        with pytest.raises(ValueError, match="out of range"):
+            encode_postings([-1])
+        with pytest.raises(ValueError, match="out of range"):
+            encode_postings([2**32])
+
+    def test_malformed_bytes_raise(self):
+        """Test truncated and overflowing postings bytes raise instead of crashing."""
+        for data in (bytes([5, 0x80]), bytes([0xFF] * 6), encode_postings([2**32 - 1]) + bytes([1])):
+            with pytest.raises(ValueError, match="invalid postings data"):
+                decode_postings(data)
+
 
 @pytest.mark.skipif(not RUST_AVAILABLE, reason="Rust extension not available")
 class TestSparseRetriever: